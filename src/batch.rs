@@ -0,0 +1,712 @@
+use crate::cache;
+use crate::cache::{Cache, CacheStats, Entry};
+use rs_image_compressor::compressor;
+use rs_image_compressor::compressor::{CompressionResult, OutputOptions, Status};
+use rs_image_compressor::config_json::Config;
+use rs_image_compressor::error::CompressorError;
+use rs_image_compressor::file_type::FileType;
+use rs_image_compressor::io::file::{
+    apply_file_attributes, copy_file, create_parent_dir, detect_file_type, get_file_metadata,
+    list_files, read_file_bytes, BACKUP_EXTENSION,
+};
+use anyhow::{anyhow, Result};
+use std::any::Any;
+use std::collections::{HashMap, HashSet};
+use std::io::{self, Write};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Component, Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+#[derive(Debug)]
+pub struct Job {
+    pub input_path: String,
+    pub output_path: String,
+}
+
+#[derive(Debug)]
+pub enum Outcome {
+    /// 圧縮後のほうが大きい場合は status が KeptOriginal
+    Compressed(CompressionResult),
+    /// キャッシュ済みの出力を使用
+    Cached { before_bytes: u64, after_bytes: u64 },
+    Copied { file_size: u64 },
+    Skipped,
+    Failed(String),
+}
+
+/// 各ファイルの処理結果（入力順を保持）
+#[derive(Debug)]
+pub struct FileResult {
+    pub input_path: String,
+    pub output_path: String,
+    pub outcome: Outcome,
+    pub elapsed: Duration,
+}
+
+#[derive(Debug, Default)]
+pub struct Summary {
+    pub compressed: usize,
+    pub kept_original: usize,
+    pub cached: usize,
+    pub copied: usize,
+    pub skipped: usize,
+    pub failed: usize,
+    pub before_bytes: u64,
+    pub after_bytes: u64,
+    pub results: Vec<FileResult>,
+    /// --cache を指定した場合のみ
+    pub cache: Option<CacheStats>,
+}
+
+/// 入力が単一ファイルではなく、ディレクトリ・複数パス・globパターンの場合はバッチモードとする
+pub fn is_batch(inputs: &[String]) -> bool {
+    inputs.len() > 1
+        || inputs
+            .iter()
+            .any(|input| is_glob_pattern(input) || Path::new(input).is_dir())
+}
+
+/// 入力パスを展開し、出力ディレクトリ配下にディレクトリ構造をミラーリングしたジョブを作成
+///
+/// 出力ディレクトリが None の場合は入力ファイルを置き換える（in-place）。複数の入力が同じ出力先になる場合はエラー
+pub fn collect_jobs(inputs: &[String], output_dir: Option<&str>) -> Result<Vec<Job>> {
+    let output_root = output_dir.map(Path::new);
+    let excluded_root = output_root.and_then(|output_root| output_root.canonicalize().ok());
+
+    let mut seen = HashSet::new();
+    let mut outputs = HashMap::new();
+    let mut jobs = Vec::new();
+
+    for input in inputs {
+        let mut files: Vec<(PathBuf, PathBuf)> = Vec::new();
+
+        if is_glob_pattern(input) {
+            let base = glob_base(input);
+            let paths = glob::glob(input)
+                .map_err(|e| anyhow!("Invalid glob pattern: {}. Error: {}", input, e))?;
+
+            for path in paths {
+                let path = path?;
+                if path.is_dir() {
+                    for file in list_files(&path)? {
+                        files.push((relative_path(&file, &base), file));
+                    }
+                } else if path.is_file() {
+                    files.push((relative_path(&path, &base), path));
+                }
+            }
+        } else {
+            let path = Path::new(input);
+            if path.is_dir() {
+                for file in list_files(path)? {
+                    files.push((relative_path(&file, path), file));
+                }
+            } else if path.is_file() {
+                files.push((PathBuf::from(path.file_name().unwrap()), path.to_path_buf()));
+            } else {
+                return Err(anyhow!("Input not found: {}", input));
+            }
+        }
+
+        for (relative, file) in files {
+            let canonical = file.canonicalize()?;
+
+            // NOTE: Skip files inside the output directory (e.g. "-o" nested in "-i")
+            if let Some(excluded_root) = excluded_root.as_ref()
+                && canonical.starts_with(excluded_root)
+            {
+                continue;
+            }
+
+            // NOTE: Skip backups left by a previous in-place run
+            if output_root.is_none()
+                && file.extension().is_some_and(|extension| extension == BACKUP_EXTENSION)
+            {
+                continue;
+            }
+
+            if !seen.insert(canonical) {
+                continue;
+            }
+
+            let output_path = match output_root {
+                Some(output_root) => output_root.join(relative),
+                None => file.clone(),
+            };
+
+            // NOTE: Inputs with the same name in different directories would overwrite each other
+            if let Some(other) = outputs.insert(output_path.clone(), file.clone()) {
+                return Err(anyhow!(
+                    "Multiple inputs map to the same output: {} and {} -> {}",
+                    other.display(),
+                    file.display(),
+                    output_path.display()
+                ));
+            }
+
+            jobs.push(Job {
+                input_path: file.to_string_lossy().into_owned(),
+                output_path: output_path.to_string_lossy().into_owned(),
+            });
+        }
+    }
+
+    Ok(jobs)
+}
+
+/// バッチ処理の設定
+pub struct Settings<'a> {
+    pub copy_unsupported: bool,
+    /// 変換後の形式（ラスター画像のみ）
+    pub format: Option<&'a FileType>,
+    pub worker_count: usize,
+    pub output_options: &'a OutputOptions,
+    pub cache: Option<&'a Cache>,
+}
+
+/// ジョブをワーカープールで並列処理し、結果を入力順に集計
+pub fn run(config: &Config, verbose: bool, jobs: &[Job], settings: &Settings) -> Summary {
+    let worker_count = settings.worker_count.clamp(1, jobs.len().max(1));
+
+    // NOTE: oxipng ("parallel" feature) uses the global rayon pool, so split the CPUs between workers
+    if worker_count > 1 {
+        let cpu_count = std::thread::available_parallelism().map_or(1, |n| n.get());
+        if let Err(e) = rayon::ThreadPoolBuilder::new()
+            .num_threads((cpu_count / worker_count).max(1))
+            .build_global()
+        {
+            // NOTE: The global pool can only be built once per process; keep using the existing one
+            eprintln!("Warning: Failed to configure the thread pool: {}", e);
+        }
+    }
+
+    // NOTE: Per-file verbose output would interleave between workers
+    let verbose_per_file = verbose && worker_count == 1;
+
+    let next_index = AtomicUsize::new(0);
+    let gif_lock = Mutex::new(());
+    let results: Vec<Mutex<Option<FileResult>>> =
+        jobs.iter().map(|_| Mutex::new(None)).collect();
+
+    std::thread::scope(|scope| {
+        for _ in 0..worker_count {
+            scope.spawn(|| {
+                loop {
+                    let index = next_index.fetch_add(1, Ordering::SeqCst);
+                    let Some(job) = jobs.get(index) else {
+                        break;
+                    };
+
+                    let now = Instant::now();
+                    // NOTE: A panic in one file (e.g. inside an encoder) fails only that file
+                    let (output_path, outcome) = panic::catch_unwind(AssertUnwindSafe(|| {
+                        process(config, verbose_per_file, job, settings, &gif_lock)
+                    }))
+                    .unwrap_or_else(|payload| {
+                        (job.output_path.clone(), Outcome::Failed(panic_message(payload)))
+                    });
+                    *results[index].lock().unwrap() = Some(FileResult {
+                        input_path: job.input_path.clone(),
+                        output_path,
+                        outcome,
+                        elapsed: now.elapsed(),
+                    });
+                }
+            });
+        }
+    });
+
+    let mut summary = Summary::default();
+
+    for result in results {
+        summary.push(result.into_inner().unwrap().unwrap());
+    }
+
+    summary.cache = settings.cache.map(|cache| cache.stats());
+
+    summary
+}
+
+/// panic のメッセージ（文字列以外の場合は固定の文言）
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    let message = payload
+        .downcast_ref::<&str>()
+        .map(|message| message.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "unknown error".to_string());
+
+    format!("Panicked: {}", message)
+}
+
+fn process(
+    config: &Config,
+    verbose: bool,
+    job: &Job,
+    settings: &Settings,
+    gif_lock: &Mutex<()>,
+) -> (String, Outcome) {
+    let options = settings.output_options;
+
+    match detect_file_type(&job.input_path) {
+        Ok(file_type) => {
+            // NOTE: Only raster images are converted, other formats keep their own format
+            let format = settings.format.filter(|_| file_type.is_image());
+            let output_type = compressor::output_type(config, &file_type, format);
+
+            // NOTE: In-place jobs would otherwise leave the input and write a sibling file
+            if job.output_path == job.input_path && output_type != file_type {
+                let error = compressor::in_place_error(&file_type, &output_type);
+                return (job.output_path.clone(), Outcome::Failed(error.to_string()));
+            }
+
+            let output_path = if output_type != file_type || format.is_some() {
+                Path::new(&job.output_path)
+                    .with_extension(output_type.extension())
+                    .to_string_lossy()
+                    .into_owned()
+            } else {
+                job.output_path.clone()
+            };
+
+            if let Err(e) = create_parent_dir(&output_path) {
+                return (output_path, Outcome::Failed(e.to_string()));
+            }
+
+            let cache_entry = match settings.cache {
+                Some(cache) => match read_file_bytes(&job.input_path) {
+                    Ok(input) => {
+                        let input_hash = cache::hash(&input);
+                        let config_hash =
+                            Cache::config_hash(config, &output_type);
+
+                        if let Some(file_size) =
+                            cache.lookup(&job.input_path, &output_path, &input_hash, &config_hash)
+                        {
+                            let outcome = cached(job, &output_path, input.len(), file_size, options);
+                            return (output_path, outcome);
+                        }
+
+                        Some((cache, input_hash, config_hash))
+                    }
+                    Err(e) => return (output_path, Outcome::Failed(e.to_string())),
+                },
+                None => None,
+            };
+
+            // NOTE: gifski spawns its own threads, so GIF outputs are encoded one at a time
+            let _guard = match output_type {
+                FileType::GIF => Some(gif_lock.lock().unwrap_or_else(|e| e.into_inner())),
+                _ => None,
+            };
+
+            let result = compressor::compress(
+                config,
+                verbose,
+                &job.input_path,
+                &output_path,
+                Some(&output_type),
+                options,
+            );
+
+            let outcome = match result {
+                Ok(result) => {
+                    if let Some((cache, input_hash, config_hash)) = cache_entry
+                        && let Ok(output) = read_file_bytes(&output_path)
+                    {
+                        let entry = Entry {
+                            input_hash,
+                            config_hash,
+                            output_hash: cache::hash(&output),
+                        };
+                        cache.insert(&output_path, entry);
+                    }
+
+                    Outcome::Compressed(result)
+                }
+                Err(e) => Outcome::Failed(e.to_string()),
+            };
+
+            (output_path, outcome)
+        }
+        Err(CompressorError::UnknownFileFormat) => {
+            // NOTE: In-place mode leaves unsupported files untouched
+            if !settings.copy_unsupported || job.input_path == job.output_path {
+                return (job.output_path.clone(), Outcome::Skipped);
+            }
+
+            let result = create_parent_dir(&job.output_path)
+                .and_then(|_| copy_file(&job.input_path, &job.output_path))
+                .and_then(|file_size| {
+                    if options.preserve_attributes {
+                        let metadata = get_file_metadata(&job.input_path)?;
+                        apply_file_attributes(&job.output_path, &metadata)?;
+                    }
+                    Ok(file_size)
+                });
+
+            let outcome = match result {
+                Ok(file_size) => Outcome::Copied { file_size },
+                Err(e) => Outcome::Failed(e.to_string()),
+            };
+
+            (job.output_path.clone(), outcome)
+        }
+        Err(e) => (job.output_path.clone(), Outcome::Failed(e.to_string())),
+    }
+}
+
+/// キャッシュ済みの出力を使用
+fn cached(
+    job: &Job,
+    output_path: &str,
+    before_bytes: usize,
+    after_bytes: u64,
+    options: &OutputOptions,
+) -> Outcome {
+    if options.preserve_attributes && job.input_path != output_path {
+        let result = get_file_metadata(&job.input_path)
+            .and_then(|metadata| apply_file_attributes(output_path, &metadata));
+
+        if let Err(e) = result {
+            return Outcome::Failed(e.to_string());
+        }
+    }
+
+    Outcome::Cached {
+        before_bytes: before_bytes as u64,
+        after_bytes,
+    }
+}
+
+impl Summary {
+    /// 処理結果を追加して集計
+    pub fn push(&mut self, result: FileResult) {
+        match &result.outcome {
+            Outcome::Compressed(compressed) => {
+                match compressed.status {
+                    Status::Compressed => self.compressed += 1,
+                    Status::KeptOriginal => self.kept_original += 1,
+                }
+                self.before_bytes += compressed.before_bytes;
+                self.after_bytes += compressed.after_bytes;
+            }
+            Outcome::Cached {
+                before_bytes,
+                after_bytes,
+            } => {
+                self.cached += 1;
+                self.before_bytes += before_bytes;
+                self.after_bytes += after_bytes;
+            }
+            Outcome::Copied { file_size } => {
+                self.copied += 1;
+                self.before_bytes += file_size;
+                self.after_bytes += file_size;
+            }
+            Outcome::Skipped => self.skipped += 1,
+            Outcome::Failed(_) => self.failed += 1,
+        }
+
+        self.results.push(result);
+    }
+
+    pub fn print(&self, out: &mut dyn Write, verbose: bool) -> io::Result<()> {
+        let saved_bytes = self.before_bytes as i64 - self.after_bytes as i64;
+        let saved_ratio = if self.before_bytes > 0 {
+            saved_bytes as f64 / self.before_bytes as f64 * 100.0
+        } else {
+            0.0
+        };
+
+        if verbose {
+            writeln!(out, "\n===== Files =====")?;
+            for result in &self.results {
+                match &result.outcome {
+                    Outcome::Compressed(compressed) => match compressed.status {
+                        Status::Compressed => writeln!(
+                            out,
+                            "\tCompressed: {} -> {} ({} -> {} bytes)",
+                            result.input_path,
+                            result.output_path,
+                            compressed.before_bytes,
+                            compressed.after_bytes
+                        )?,
+                        Status::KeptOriginal => writeln!(
+                            out,
+                            "\tKept original: {} -> {} ({} bytes)",
+                            result.input_path, result.output_path, compressed.before_bytes
+                        )?,
+                    },
+                    Outcome::Cached { .. } => {
+                        writeln!(out, "\tCached: {} -> {}", result.input_path, result.output_path)?
+                    }
+                    Outcome::Copied { .. } => {
+                        writeln!(out, "\tCopied: {} -> {}", result.input_path, result.output_path)?
+                    }
+                    Outcome::Skipped => writeln!(out, "\tSkipped: {}", result.input_path)?,
+                    Outcome::Failed(_) => writeln!(out, "\tFailed: {}", result.input_path)?,
+                }
+            }
+        }
+
+        for result in &self.results {
+            if let Outcome::Failed(e) = &result.outcome {
+                eprintln!("Failed: {}. Error: {}", result.input_path, e);
+            }
+        }
+
+        writeln!(out, "\n===== Summary =====")?;
+        writeln!(out, "\tCompressed: {}", self.compressed)?;
+        writeln!(out, "\tKept original: {}", self.kept_original)?;
+        writeln!(out, "\tCached: {}", self.cached)?;
+        writeln!(out, "\tCopied: {}", self.copied)?;
+        writeln!(out, "\tSkipped: {}", self.skipped)?;
+        writeln!(out, "\tFailed: {}", self.failed)?;
+        writeln!(out, "\tBefore: {} bytes", self.before_bytes)?;
+        writeln!(out, "\tAfter: {} bytes", self.after_bytes)?;
+        writeln!(out, "\tSaved: {} bytes ({:.2}%)", saved_bytes, saved_ratio)?;
+
+        if let Some(cache) = self.cache {
+            writeln!(out, "\tCache hits: {}", cache.hits)?;
+            writeln!(out, "\tCache misses: {}", cache.misses)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn is_glob_pattern(input: &str) -> bool {
+    input.contains(['*', '?', '['])
+}
+
+/// globパターンのうち、ワイルドカードを含まない先頭部分をミラーリングの基準ディレクトリとする
+fn glob_base(pattern: &str) -> PathBuf {
+    let mut base = PathBuf::new();
+
+    for component in Path::new(pattern).components() {
+        if let Component::Normal(name) = component
+            && is_glob_pattern(&name.to_string_lossy())
+        {
+            break;
+        }
+        base.push(component);
+    }
+
+    base
+}
+
+fn relative_path(path: &Path, base: &Path) -> PathBuf {
+    match path.strip_prefix(base) {
+        Ok(relative) if !relative.as_os_str().is_empty() => relative.to_path_buf(),
+        _ => PathBuf::from(path.file_name().unwrap()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// テストごとの一時ディレクトリ
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "rs-image-compressor-batch-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn touch(dir: &Path, name: &str) -> String {
+        let path = dir.join(name);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, b"data").unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    fn path(dir: &Path, name: &str) -> String {
+        dir.join(name).to_string_lossy().into_owned()
+    }
+
+    fn outputs(jobs: &[Job]) -> Vec<&str> {
+        jobs.iter().map(|job| job.output_path.as_str()).collect()
+    }
+
+    #[test]
+    fn glob_base_stops_at_the_first_wildcard() {
+        assert_eq!(glob_base("images/**/*.png"), PathBuf::from("images"));
+        assert_eq!(glob_base("a/b/photo-?.jpg"), PathBuf::from("a/b"));
+        assert_eq!(glob_base("*.png"), PathBuf::new());
+        assert_eq!(glob_base("/srv/[ab]/x.png"), PathBuf::from("/srv"));
+    }
+
+    #[test]
+    fn relative_path_falls_back_to_the_file_name() {
+        assert_eq!(relative_path(Path::new("images/a/x.png"), Path::new("images")), PathBuf::from("a/x.png"));
+        assert_eq!(relative_path(Path::new("other/x.png"), Path::new("images")), PathBuf::from("x.png"));
+        assert_eq!(relative_path(Path::new("images"), Path::new("images")), PathBuf::from("images"));
+    }
+
+    #[test]
+    fn collect_jobs_mirrors_directories() {
+        let dir = temp_dir("mirror");
+        touch(&dir, "in/a.png");
+        touch(&dir, "in/sub/b.jpg");
+        let single = touch(&dir, "single.webp");
+
+        let jobs = collect_jobs(&[path(&dir, "in"), single], Some(&path(&dir, "out"))).unwrap();
+
+        assert_eq!(
+            outputs(&jobs),
+            vec![path(&dir, "out/a.png"), path(&dir, "out/sub/b.jpg"), path(&dir, "out/single.webp")]
+        );
+        assert_eq!(jobs[1].input_path, path(&dir, "in/sub/b.jpg"));
+    }
+
+    #[test]
+    fn collect_jobs_expands_globs_relative_to_their_base() {
+        let dir = temp_dir("glob");
+        touch(&dir, "in/a.png");
+        touch(&dir, "in/sub/b.png");
+        touch(&dir, "in/sub/c.jpg");
+
+        let jobs = collect_jobs(&[path(&dir, "in/**/*.png")], Some(&path(&dir, "out"))).unwrap();
+
+        assert_eq!(outputs(&jobs), vec![path(&dir, "out/a.png"), path(&dir, "out/sub/b.png")]);
+    }
+
+    #[test]
+    fn collect_jobs_rejects_inputs_with_the_same_output() {
+        let dir = temp_dir("duplicate");
+        let first = touch(&dir, "a/x.png");
+        let second = touch(&dir, "b/x.png");
+
+        let error = collect_jobs(&[first.clone(), second], Some(&path(&dir, "out"))).unwrap_err();
+        assert!(error.to_string().contains("same output"), "{}", error);
+
+        // NOTE: The same file given twice is processed once
+        let jobs = collect_jobs(&[first.clone(), first], Some(&path(&dir, "out"))).unwrap();
+        assert_eq!(jobs.len(), 1);
+    }
+
+    #[test]
+    fn collect_jobs_skips_the_output_directory_and_backups() {
+        let dir = temp_dir("skip");
+        touch(&dir, "in/a.png");
+        touch(&dir, "in/out/a.png");
+        touch(&dir, "in/a.png.bak");
+
+        let jobs = collect_jobs(&[path(&dir, "in")], Some(&path(&dir, "in/out"))).unwrap();
+        assert_eq!(outputs(&jobs), vec![path(&dir, "in/out/a.png"), path(&dir, "in/out/a.png.bak")]);
+
+        // NOTE: In-place mode replaces the inputs and ignores backups of a previous run
+        let jobs = collect_jobs(&[path(&dir, "in")], None).unwrap();
+        assert_eq!(outputs(&jobs), vec![path(&dir, "in/a.png"), path(&dir, "in/out/a.png")]);
+    }
+
+    #[test]
+    fn collect_jobs_rejects_missing_inputs() {
+        let dir = temp_dir("missing");
+
+        assert!(collect_jobs(&[path(&dir, "missing.png")], Some(&path(&dir, "out"))).is_err());
+    }
+
+    /// width x height の PNG を作成
+    fn png(dir: &Path, name: &str, width: u32, height: u32) -> String {
+        let path = dir.join(name);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        let image = image::RgbaImage::from_fn(width, height, |x, y| image::Rgba([x as u8, y as u8, 64, 255]));
+        image.save_with_format(&path, image::ImageFormat::Png).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    fn settings(worker_count: usize, options: &OutputOptions) -> Settings<'_> {
+        Settings {
+            copy_unsupported: true,
+            format: None,
+            worker_count,
+            output_options: options,
+            cache: None,
+        }
+    }
+
+    fn kinds(summary: &Summary) -> Vec<(&str, &'static str)> {
+        summary
+            .results
+            .iter()
+            .map(|result| {
+                let kind = match &result.outcome {
+                    Outcome::Compressed(_) => "compressed",
+                    Outcome::Cached { .. } => "cached",
+                    Outcome::Copied { .. } => "copied",
+                    Outcome::Skipped => "skipped",
+                    Outcome::Failed(_) => "failed",
+                };
+                (result.input_path.as_str(), kind)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn run_reports_results_in_input_order() {
+        let dir = temp_dir("run");
+        // NOTE: The large first file finishes after the others with several workers
+        let inputs = [
+            png(&dir, "in/large.png", 160, 160),
+            touch(&dir, "in/notes.txt"),
+            png(&dir, "in/small.png", 8, 8),
+            path(&dir, "in/missing.png"),
+            png(&dir, "in/medium.png", 64, 64),
+        ];
+        let jobs: Vec<Job> = inputs
+            .iter()
+            .map(|input_path| Job {
+                input_path: input_path.clone(),
+                output_path: input_path.replace("/in/", "/out/"),
+            })
+            .collect();
+        let options = OutputOptions::default();
+
+        for worker_count in [1, 3] {
+            let summary = run(&Config::default(), false, &jobs, &settings(worker_count, &options));
+
+            assert_eq!(
+                kinds(&summary),
+                vec![
+                    (inputs[0].as_str(), "compressed"),
+                    (inputs[1].as_str(), "copied"),
+                    (inputs[2].as_str(), "compressed"),
+                    (inputs[3].as_str(), "failed"),
+                    (inputs[4].as_str(), "compressed"),
+                ]
+            );
+            assert_eq!(summary.compressed + summary.kept_original, 3);
+            assert_eq!((summary.copied, summary.failed, summary.skipped), (1, 1, 0));
+        }
+    }
+
+    #[test]
+    fn run_handles_more_workers_than_jobs() {
+        let dir = temp_dir("workers");
+        let input_path = png(&dir, "a.png", 16, 16);
+        let jobs = vec![Job {
+            input_path: input_path.clone(),
+            output_path: path(&dir, "out/a.png"),
+        }];
+        let options = OutputOptions::default();
+
+        let summary = run(&Config::default(), false, &jobs, &settings(8, &options));
+        assert_eq!(kinds(&summary), vec![(input_path.as_str(), "compressed")]);
+        assert!(run(&Config::default(), false, &[], &settings(8, &options)).results.is_empty());
+    }
+
+    #[test]
+    fn panic_message_reads_string_payloads() {
+        assert_eq!(panic_message(Box::new("broken")), "Panicked: broken");
+        assert_eq!(panic_message(Box::new("broken".to_string())), "Panicked: broken");
+        assert_eq!(panic_message(Box::new(42)), "Panicked: unknown error");
+    }
+}
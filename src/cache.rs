@@ -0,0 +1,290 @@
+use rs_image_compressor::config_json::Config;
+use rs_image_compressor::file_type::FileType;
+use rs_image_compressor::io::file::{copy_file, read_file_bytes, write_file_bytes};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+const MANIFEST_VERSION: u32 = 1;
+
+/// 出力ファイルごとの圧縮時の入力・設定・出力のハッシュ
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Entry {
+    pub input_hash: String,
+    pub config_hash: String,
+    pub output_hash: String,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Manifest {
+    version: u32,
+    /// キー: 出力ファイルのパス
+    entries: BTreeMap<String, Entry>,
+}
+
+/// キャッシュのヒット・ミス数
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct CacheStats {
+    pub hits: usize,
+    pub misses: usize,
+}
+
+/// 入力内容と設定のハッシュをキーにしたキャッシュ（マニフェストファイルに保存）
+pub struct Cache {
+    manifest_path: String,
+    entries: Mutex<BTreeMap<String, Entry>>,
+    hits: AtomicUsize,
+    misses: AtomicUsize,
+}
+
+impl Cache {
+    /// マニフェストを読み込み（存在しない・バージョンが異なる場合は空のキャッシュ）
+    pub fn load(manifest_path: &str) -> Result<Self> {
+        let entries = if Path::new(manifest_path).exists() {
+            let manifest: Manifest = serde_json::from_slice(&read_file_bytes(manifest_path)?)?;
+            if manifest.version == MANIFEST_VERSION {
+                manifest.entries
+            } else {
+                BTreeMap::new()
+            }
+        } else {
+            BTreeMap::new()
+        };
+
+        Ok(Self {
+            manifest_path: manifest_path.to_string(),
+            entries: Mutex::new(entries),
+            hits: AtomicUsize::new(0),
+            misses: AtomicUsize::new(0),
+        })
+    }
+
+    /// 出力形式と設定全体のハッシュ（ツールのバージョンも含め、更新時は無効化）
+    ///
+    /// 入力形式の設定（tiff.page・ops など）や responsive も出力に影響するため、出力形式の設定だけでなく全体を含める
+    pub fn config_hash(config: &Config, file_type: &FileType) -> String {
+        let value = json!({
+            "version": env!("CARGO_PKG_VERSION"),
+            "format": file_type,
+            "config": config,
+        });
+
+        hash(value.to_string().as_bytes())
+    }
+
+    /// キャッシュ済みの出力を探し、見つかった場合は出力ファイルのサイズを返す
+    ///
+    /// 出力先が最新の場合はそのまま、同じ入力・設定の出力が別のパスにある場合はコピーして再利用
+    pub fn lookup(
+        &self,
+        input_path: &str,
+        output_path: &str,
+        input_hash: &str,
+        config_hash: &str,
+    ) -> Option<u64> {
+        let (current, candidates) = {
+            let entries = self.entries.lock().unwrap();
+            let candidates: Vec<(String, Entry)> = entries
+                .iter()
+                .filter(|(path, entry)| {
+                    path.as_str() != output_path
+                        && path.as_str() != input_path
+                        && entry.input_hash == input_hash
+                        && entry.config_hash == config_hash
+                })
+                .map(|(path, entry)| (path.clone(), entry.clone()))
+                .collect();
+
+            (entries.get(output_path).cloned(), candidates)
+        };
+
+        if let Some(entry) = current
+            && entry.config_hash == config_hash
+            // NOTE: In-place outputs replace the input, so its hash becomes the output hash
+            && (entry.input_hash == input_hash || input_path == output_path)
+            && let Some(file_size) = verify(output_path, &entry.output_hash)
+        {
+            self.hits.fetch_add(1, Ordering::SeqCst);
+            return Some(file_size);
+        }
+
+        let reusable = candidates
+            .into_iter()
+            .find(|(path, entry)| verify(path, &entry.output_hash).is_some());
+
+        if let Some((path, entry)) = reusable
+            && let Ok(file_size) = copy_file(&path, output_path)
+        {
+            self.insert(output_path, entry);
+            self.hits.fetch_add(1, Ordering::SeqCst);
+            return Some(file_size);
+        }
+
+        self.misses.fetch_add(1, Ordering::SeqCst);
+        None
+    }
+
+    pub fn insert(&self, output_path: &str, entry: Entry) {
+        self.entries
+            .lock()
+            .unwrap()
+            .insert(output_path.to_string(), entry);
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits.load(Ordering::SeqCst),
+            misses: self.misses.load(Ordering::SeqCst),
+        }
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let manifest = Manifest {
+            version: MANIFEST_VERSION,
+            entries: self.entries.lock().unwrap().clone(),
+        };
+
+        write_file_bytes(
+            &self.manifest_path,
+            serde_json::to_string_pretty(&manifest)?.as_bytes(),
+        )?;
+
+        Ok(())
+    }
+}
+
+/// SHA-256 (16進数)
+pub fn hash(data: &[u8]) -> String {
+    Sha256::digest(data)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// 出力ファイルが記録時のまま残っている場合はサイズを返す
+fn verify(output_path: &str, output_hash: &str) -> Option<u64> {
+    let data = read_file_bytes(output_path).ok()?;
+
+    (hash(&data) == output_hash).then_some(data.len() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rs_image_compressor::config_json::TiffConfig;
+    use std::path::PathBuf;
+
+    /// テストごとの一時ディレクトリ
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "rs-image-compressor-cache-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn path(dir: &Path, name: &str) -> String {
+        dir.join(name).to_string_lossy().into_owned()
+    }
+
+    /// 出力ファイルを書き込み、そのエントリを記録したキャッシュ
+    fn cache_with_output(dir: &Path, output_path: &str, config_hash: &str) -> Cache {
+        let cache = Cache::load(&path(dir, "manifest.json")).unwrap();
+        write_file_bytes(output_path, b"output").unwrap();
+        cache.insert(
+            output_path,
+            Entry {
+                input_hash: hash(b"input"),
+                config_hash: config_hash.to_string(),
+                output_hash: hash(b"output"),
+            },
+        );
+        cache
+    }
+
+    #[test]
+    fn config_hash_covers_other_sections() {
+        let config = Config::default();
+        let tiff_config = Config {
+            tiff: Some(TiffConfig {
+                page: Some(1),
+                ..TiffConfig::default()
+            }),
+            ..Config::default()
+        };
+
+        assert_eq!(
+            Cache::config_hash(&config, &FileType::PNG),
+            Cache::config_hash(&Config::default(), &FileType::PNG)
+        );
+        assert_ne!(
+            Cache::config_hash(&config, &FileType::PNG),
+            Cache::config_hash(&tiff_config, &FileType::PNG)
+        );
+        assert_ne!(
+            Cache::config_hash(&config, &FileType::PNG),
+            Cache::config_hash(&config, &FileType::WEBP)
+        );
+    }
+
+    #[test]
+    fn lookup_hits_unchanged_output() {
+        let dir = temp_dir("hit");
+        let output_path = path(&dir, "output.png");
+        let cache = cache_with_output(&dir, &output_path, "config");
+
+        assert_eq!(cache.lookup("input.png", &output_path, &hash(b"input"), "config"), Some(6));
+        assert_eq!(cache.stats().hits, 1);
+    }
+
+    #[test]
+    fn lookup_misses_when_input_or_config_changes() {
+        let dir = temp_dir("changed");
+        let output_path = path(&dir, "output.png");
+        let cache = cache_with_output(&dir, &output_path, "config");
+
+        assert_eq!(cache.lookup("input.png", &output_path, &hash(b"changed"), "config"), None);
+        assert_eq!(cache.lookup("input.png", &output_path, &hash(b"input"), "changed"), None);
+        assert_eq!(cache.stats().misses, 2);
+    }
+
+    #[test]
+    fn lookup_misses_when_output_is_modified() {
+        let dir = temp_dir("modified");
+        let output_path = path(&dir, "output.png");
+        let cache = cache_with_output(&dir, &output_path, "config");
+        write_file_bytes(&output_path, b"modified").unwrap();
+
+        assert_eq!(cache.lookup("input.png", &output_path, &hash(b"input"), "config"), None);
+    }
+
+    #[test]
+    fn lookup_reuses_output_at_another_path() {
+        let dir = temp_dir("reuse");
+        let cached_path = path(&dir, "cached.png");
+        let output_path = path(&dir, "output.png");
+        let cache = cache_with_output(&dir, &cached_path, "config");
+
+        assert_eq!(cache.lookup("input.png", &output_path, &hash(b"input"), "config"), Some(6));
+        assert_eq!(read_file_bytes(&output_path).unwrap(), b"output");
+        assert!(cache.entries.lock().unwrap().contains_key(&output_path));
+    }
+
+    #[test]
+    fn save_and_load_round_trip() {
+        let dir = temp_dir("save");
+        let output_path = path(&dir, "output.png");
+        cache_with_output(&dir, &output_path, "config").save().unwrap();
+
+        let cache = Cache::load(&path(&dir, "manifest.json")).unwrap();
+        assert_eq!(cache.lookup("input.png", &output_path, &hash(b"input"), "config"), Some(6));
+    }
+}
@@ -1,324 +1,1956 @@
-mod jpeg_compressor;
-mod png_compressor;
-mod webp_compressor;
-mod gif_compressor;
-mod heif_compressor;
-mod pdf_compressor;
-mod svg_compressor;
-
-use crate::config_json::Config;
+pub mod jpeg_compressor;
+pub mod png_compressor;
+pub mod webp_compressor;
+pub mod gif_compressor;
+pub mod heif_compressor;
+pub mod avif_compressor;
+pub mod jxl_compressor;
+pub mod tiff_compressor;
+pub mod ico_compressor;
+pub mod pdf_compressor;
+pub mod svg_compressor;
+mod quality_search;
+mod responsive;
+
+use crate::config_json::{Config, FiltersConfig, JxlConfig, OpConfig, SizeFilterConfig, WebpConfig};
 use crate::error::CompressorError;
-use crate::file_type::FileType;
-use crate::io::file::{detect_file_type, get_file_size, write_file_bytes};
+use crate::file_type::{self, detect_from_bytes, FileType};
+use crate::imaging::animation::Animation;
+use crate::imaging::metrics;
+use crate::imaging::metrics::Metrics;
+use crate::imaging::{filter, orientation, transform};
+use crate::io::file::{
+    apply_file_attributes, backup_file, get_file_metadata, read_file_bytes,
+    read_image_from_bytes, read_stdin_bytes, write_file_bytes, write_stdout_bytes, STDIO_PATH,
+};
 use anyhow::{anyhow, Result};
-use little_exif::exif_tag::ExifTag;
-use little_exif::filetype::FileExtension;
-use little_exif::metadata::Metadata;
-use std::fs::File;
-use std::io::{Read, Seek};
+use image::imageops::FilterType;
+use image::{DynamicImage, GenericImageView, ImageFormat, ImageReader};
+use serde::Serialize;
+use std::borrow::Cow;
+use std::io::{self, Cursor, Write};
 use std::path::Path;
 use std::time::Instant;
 
-pub fn compress(
-    config: Config,
-    verbose: bool,
-    input_path: &String,
-    output_path: &String,
-) -> Result<()> {
-    let now = Instant::now();
-    let input_file_name = Path::new(input_path)
-        .file_name()
-        .unwrap()
-        .to_string_lossy()
-        .into_owned();
-    let output_file_name = Path::new(output_path)
-        .file_name()
-        .unwrap()
-        .to_string_lossy()
-        .into_owned();
+/// 圧縮結果の状態
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Status {
+    Compressed,
+    /// 圧縮後のほうが大きいため元のデータを維持
+    KeptOriginal,
+}
+
+impl Status {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Status::Compressed => "compressed",
+            Status::KeptOriginal => "kept original",
+        }
+    }
+}
+
+/// 圧縮結果
+#[derive(Debug)]
+pub struct CompressedOutput {
+    pub data: Vec<u8>,
+    pub file_type: FileType,
+    pub status: Status,
+    /// target_bytes による探索で選択された品質
+    pub quality: Option<u8>,
+    /// 非可逆圧縮の場合の画質指標
+    pub metrics: Option<Metrics>,
+}
+
+/// 画像の幅と高さ
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Dimensions {
+    pub width: u32,
+    pub height: u32,
+}
+
+/// ファイル圧縮の結果
+#[derive(Debug)]
+pub struct CompressionResult {
+    /// 入力形式
+    pub input_type: FileType,
+    /// 出力形式
+    pub file_type: FileType,
+    pub status: Status,
+    pub before_bytes: u64,
+    pub after_bytes: u64,
+    pub before_dimensions: Option<Dimensions>,
+    pub after_dimensions: Option<Dimensions>,
+    pub quality: Option<u8>,
+    pub metrics: Option<Metrics>,
+    /// 全ページを個別に出力した場合の出力ファイル
+    pub page_paths: Vec<String>,
+    /// responsive.variants で出力した画像
+    pub variants: Vec<VariantOutput>,
+}
+
+/// responsive.variants で出力した画像
+#[derive(Debug, Clone, Serialize)]
+pub struct VariantOutput {
+    pub path: String,
+    pub format: FileType,
+    pub dimensions: Dimensions,
+    pub bytes: u64,
+    pub quality: Option<u8>,
+}
+
+/// ファイル出力の設定
+#[derive(Debug, Clone, Default)]
+pub struct OutputOptions {
+    /// 入力ファイルを置き換える場合に元のファイルを `<file>.bak` として残す
+    pub backup: bool,
+    /// 入力ファイルの日時・パーミッション・所有者を出力ファイルに反映
+    pub preserve_attributes: bool,
+    /// 詳細表示がなくても非可逆圧縮の画質指標を計測（レポート用）
+    pub metrics: bool,
+    /// レポートを標準出力に書き出すため、詳細表示を標準エラー出力に出力
+    pub report_to_stdout: bool,
+}
+
+/// 変換先として指定できる形式（GIF・BMP・TGA・PDF・SVG へは変換できない）
+pub fn is_output_format(file_type: &FileType) -> bool {
+    file_type.is_image() && !matches!(file_type, FileType::GIF | FileType::BMP | FileType::TGA)
+}
+
+/// 入力ファイルを別の形式の出力で置き換えようとした場合のエラー
+pub fn in_place_error(input_type: &FileType, output_type: &FileType) -> anyhow::Error {
+    anyhow!(CompressorError::UnsupportedConversion(format!(
+        "{} cannot be replaced in place with {} output",
+        format_name(input_type),
+        format_name(output_type)
+    )))
+}
+
+/// 出力形式（指定がない場合は入力形式、GIF・TIFF は convert_to の形式、BMP・TGA は PNG）
+pub fn output_type(
+    config: &Config,
+    input_type: &FileType,
+    output_format: Option<&FileType>,
+) -> FileType {
+    if let Some(output_format) = output_format {
+        return output_format.clone();
+    }
+
+    match input_type {
+        FileType::GIF => config
+            .gif
+            .as_ref()
+            .and_then(|gif_config| gif_config.convert_to.as_deref())
+            .and_then(file_type::from_extension)
+            .unwrap_or(FileType::GIF),
+        FileType::TIFF => config
+            .tiff
+            .as_ref()
+            .and_then(|tiff_config| tiff_config.convert_to.as_deref())
+            .and_then(file_type::from_extension)
+            .unwrap_or(FileType::TIFF),
+        FileType::BMP | FileType::TGA => FileType::PNG,
+        _ => input_type.clone(),
+    }
+}
+
+/// メモリ上のバイト列を圧縮（ファイル形式は内容から判定）
+pub fn compress_bytes(input: &[u8], config: &Config) -> Result<CompressedOutput> {
+    let file_type =
+        detect_from_bytes(input).ok_or_else(|| anyhow!(CompressorError::UnknownFileFormat))?;
+
+    let output_type = output_type(config, &file_type, None);
+
+    process_bytes(config, input, &file_type, &output_type)
+}
+
+/// メモリ上のバイト列を指定した形式に変換して圧縮（入力と同じ形式の場合は通常の圧縮）
+pub fn convert_bytes(
+    input: &[u8],
+    config: &Config,
+    output_type: &FileType,
+) -> Result<CompressedOutput> {
+    let file_type =
+        detect_from_bytes(input).ok_or_else(|| anyhow!(CompressorError::UnknownFileFormat))?;
+
+    process_bytes(config, input, &file_type, output_type)
+}
+
+fn process_bytes(
+    config: &Config,
+    input: &[u8],
+    file_type: &FileType,
+    output_type: &FileType,
+) -> Result<CompressedOutput> {
+    check_conversion(file_type, output_type)?;
+
+    let input = &page_input(input, selected_page(config, file_type))?;
+
+    match process_data(config, file_type, output_type, input, true) {
+        Ok(processed) => Ok(CompressedOutput {
+            data: processed.data,
+            file_type: output_type.clone(),
+            status: processed.status,
+            quality: processed.quality,
+            metrics: processed.metrics,
+        }),
+        Err(e) if file_type == output_type => Err(anyhow!(
+            "{} compression failed. Error: {}",
+            format_name(file_type),
+            e
+        )),
+        Err(e) => Err(anyhow!(
+            "{} to {} conversion failed. Error: {}",
+            format_name(file_type),
+            format_name(output_type),
+            e
+        )),
+    }
+}
+
+/// 変換できない組み合わせ（画像以外からの変換、GIF・BMP・TGA などへの変換）はエラー
+fn check_conversion(file_type: &FileType, output_type: &FileType) -> Result<()> {
+    if (output_type != file_type && (!file_type.is_image() || !is_output_format(output_type)))
+        || matches!(output_type, FileType::BMP | FileType::TGA)
+    {
+        return Err(anyhow!(CompressorError::UnsupportedConversion(format!(
+            "{} to {} conversion is not supported",
+            format_name(file_type),
+            format_name(output_type)
+        ))));
+    }
+
+    Ok(())
+}
+
+/// ラスター画像のバイト列を DynamicImage にデコード（EXIF の Orientation は画素に適用）
+pub fn decode_image(file_type: &FileType, input: &[u8]) -> Result<DynamicImage> {
+    match file_type {
+        FileType::PNG | FileType::JPEG | FileType::WEBP => Ok(orientation::apply(
+            read_image_from_bytes(input)?,
+            orientation::read(file_type, input),
+        )),
+        FileType::GIF | FileType::BMP | FileType::ICO => Ok(read_image_from_bytes(input)?),
+        // NOTE: TGA has no magic number to guess the format from
+        FileType::TGA => Ok(image::load_from_memory_with_format(input, ImageFormat::Tga)
+            .map_err(CompressorError::ImageDecodeError)?),
+        FileType::HEIF | FileType::AVIF => heif_compressor::decode_image(input),
+        FileType::JXL => jxl_compressor::decode_image(input),
+        FileType::TIFF => tiff_compressor::decode_image(input),
+        FileType::PDF | FileType::XML => Err(anyhow!(CompressorError::UnsupportedConversion(
+            format!("{} cannot be decoded as an image", format_name(file_type))
+        ))),
+    }
+}
+
+/// DynamicImage を指定した形式でエンコード
+pub fn encode_image(
+    config: &Config,
+    file_type: &FileType,
+    dynamic_image: DynamicImage,
+) -> Result<Vec<u8>> {
+    match file_type {
+        FileType::PNG => png_compressor::compress_image(config.png.as_ref(), dynamic_image),
+        FileType::JPEG => jpeg_compressor::compress_image(config.jpeg.as_ref(), dynamic_image),
+        FileType::WEBP => webp_compressor::compress_image(config.webp.as_ref(), dynamic_image),
+        FileType::HEIF => heif_compressor::compress_image(config.heif.as_ref(), dynamic_image),
+        FileType::AVIF => avif_compressor::compress_image(config.avif.as_ref(), dynamic_image),
+        FileType::JXL => jxl_compressor::compress_image(config.jxl.as_ref(), dynamic_image),
+        FileType::TIFF => tiff_compressor::compress_image(config.tiff.as_ref(), dynamic_image),
+        FileType::ICO => {
+            ico_compressor::compress_image(config.ico.as_ref(), config.png.as_ref(), dynamic_image)
+        }
+        FileType::GIF | FileType::BMP | FileType::TGA | FileType::PDF | FileType::XML => {
+            Err(anyhow!(CompressorError::UnsupportedConversion(format!(
+                "{} output is not supported",
+                format_name(file_type)
+            ))))
+        }
+    }
+}
+
+/// ラスター画像の幅と高さ（デコードせずにヘッダーから取得し、EXIF の Orientation を反映）
+pub fn image_dimensions(file_type: &FileType, input: &[u8]) -> Option<Dimensions> {
+    let (width, height) = match file_type {
+        FileType::PNG
+        | FileType::JPEG
+        | FileType::WEBP
+        | FileType::GIF
+        | FileType::BMP
+        | FileType::ICO => ImageReader::new(Cursor::new(input))
+            .with_guessed_format()
+            .ok()?
+            .into_dimensions()
+            .ok()?,
+        FileType::TGA => ImageReader::with_format(Cursor::new(input), ImageFormat::Tga)
+            .into_dimensions()
+            .ok()?,
+        FileType::HEIF | FileType::AVIF => heif_compressor::dimensions(input).ok()?,
+        FileType::JXL => jxl_compressor::decode_image(input).ok()?.dimensions(),
+        FileType::TIFF => tiff_compressor::dimensions(input).ok()?,
+        FileType::PDF | FileType::XML => return None,
+    };
+
+    if orientation::is_transposed(orientation::read(file_type, input)) {
+        return Some(Dimensions {
+            width: height,
+            height: width,
+        });
+    }
+
+    Some(Dimensions { width, height })
+}
+
+/// 出力データが指定した形式として読み込めるか検証
+pub fn validate_output(file_type: &FileType, data: &[u8]) -> Result<()> {
+    if data.is_empty() {
+        return Err(anyhow!("Output is empty"));
+    }
+
+    let valid = match file_type {
+        // NOTE: Minified SVG has no XML declaration to detect
+        FileType::XML => true,
+        FileType::PDF => detect_from_bytes(data).as_ref() == Some(file_type),
+        _ => {
+            detect_from_bytes(data).as_ref() == Some(file_type)
+                && image_dimensions(file_type, data).is_some()
+        }
+    };
+
+    if !valid {
+        return Err(anyhow!("Output is not a valid {} file", format_name(file_type)));
+    }
+
+    Ok(())
+}
+
+/// 検証した出力で入力ファイルを置き換え（検証に失敗した場合はバックアップも作成せずに入力ファイルを維持）
+fn replace_input(
+    input_path: &str,
+    output_path: &str,
+    file_type: &FileType,
+    data: &[u8],
+    backup: bool,
+) -> Result<()> {
+    validate_output(file_type, data)
+        .map_err(|e| anyhow!("Invalid output for file: {}. Error: {}", input_path, e))?;
+
+    if backup {
+        backup_file(input_path)?;
+    }
+    write_file_bytes(output_path, data)?;
+
+    Ok(())
+}
+
+fn is_same_file(input_path: &str, output_path: &str) -> bool {
+    if input_path == output_path {
+        return true;
+    }
+
+    match (Path::new(input_path).canonicalize(), Path::new(output_path).canonicalize()) {
+        (Ok(input), Ok(output)) => input == output,
+        _ => false,
+    }
+}
+
+/// 圧縮処理の結果
+struct Processed {
+    data: Vec<u8>,
+    status: Status,
+    quality: Option<u8>,
+    metrics: Option<Metrics>,
+}
+
+/// 圧縮または形式変換（同じ形式で圧縮後のほうが大きい場合は元のデータを維持）
+///
+/// measure が false の場合、画質指標は min_ssim の判定に必要なときだけ計測
+fn process_data(
+    config: &Config,
+    input_type: &FileType,
+    output_type: &FileType,
+    input: &[u8],
+    measure: bool,
+) -> Result<Processed> {
+    let processed = encode_best(config, input_type, output_type, input, measure)?;
+
+    // NOTE: The original can only be kept when the format is not converted and the pixels are unchanged
+    if input_type != output_type
+        || config.allow_larger.unwrap_or(false)
+        || has_transforms(config, input_type, input)
+    {
+        return Ok(processed);
+    }
+
+    // NOTE: The kept original must not carry the metadata that the config strips
+    let original = strip_metadata(config, input_type, input)?;
+    if processed.data.len() >= original.len() {
+        return Ok(Processed {
+            data: original,
+            status: Status::KeptOriginal,
+            quality: None,
+            metrics: None,
+        });
+    }
+
+    Ok(processed)
+}
+
+/// 元のデータから設定で削除するメタデータ（JPEG の exif、PNG の strip）を除く
+fn strip_metadata(config: &Config, file_type: &FileType, input: &[u8]) -> Result<Vec<u8>> {
+    match file_type {
+        FileType::PNG => png_compressor::strip_metadata(config.png.as_ref(), input),
+        FileType::JPEG => Ok(jpeg_compressor::strip_metadata(config.jpeg.as_ref(), input)),
+        _ => Ok(input.to_vec()),
+    }
+}
+
+/// 画素を変更する設定（size・ops・filters、EXIF の Orientation の適用）があるかどうか
+fn has_transforms(config: &Config, file_type: &FileType, input: &[u8]) -> bool {
+    let configured = match file_type {
+        FileType::PNG => config.png.as_ref().is_some_and(|png_config| {
+            png_config.size.is_some() || png_config.ops.is_some() || png_config.filters.is_some()
+        }),
+        FileType::JPEG => config.jpeg.as_ref().is_some_and(|jpeg_config| {
+            jpeg_config.size.is_some() || jpeg_config.ops.is_some() || jpeg_config.filters.is_some()
+        }),
+        FileType::WEBP => config.webp.as_ref().is_some_and(|webp_config| {
+            webp_config.size.is_some() || webp_config.ops.is_some() || webp_config.filters.is_some()
+        }),
+        FileType::GIF => config.gif.as_ref().is_some_and(|gif_config| {
+            gif_config.size.is_some() || gif_config.ops.is_some() || gif_config.filters.is_some()
+        }),
+        FileType::HEIF => config.heif.as_ref().is_some_and(|heif_config| {
+            heif_config.size.is_some() || heif_config.ops.is_some() || heif_config.filters.is_some()
+        }),
+        FileType::AVIF => config.avif.as_ref().is_some_and(|avif_config| {
+            avif_config.size.is_some() || avif_config.ops.is_some() || avif_config.filters.is_some()
+        }),
+        FileType::JXL => config.jxl.as_ref().is_some_and(|jxl_config| {
+            jxl_config.size.is_some() || jxl_config.ops.is_some() || jxl_config.filters.is_some()
+        }),
+        FileType::TIFF => config.tiff.as_ref().is_some_and(|tiff_config| {
+            tiff_config.size.is_some() || tiff_config.ops.is_some() || tiff_config.filters.is_some()
+        }),
+        FileType::BMP | FileType::ICO | FileType::TGA | FileType::PDF | FileType::XML => false,
+    };
+
+    configured || orientation::read(file_type, input) != 1
+}
+
+/// target_bytes が設定されている場合は品質を二分探索し、min_ssim を下回る場合は品質を上げて再圧縮
+fn encode_best(
+    config: &Config,
+    input_type: &FileType,
+    output_type: &FileType,
+    input: &[u8],
+    measure: bool,
+) -> Result<Processed> {
+    let (mut data, mut quality) = match target_range(config, output_type) {
+        Some((target_bytes, quality_min, quality_max)) => {
+            let (quality, data) =
+                quality_search::search(quality_min, quality_max, target_bytes, |quality| {
+                    encode_with_quality(config, input_type, output_type, input, quality)
+                })?;
+            (data, Some(quality))
+        }
+        None => (encode_data(config, input_type, output_type, input)?, None),
+    };
+
+    // NOTE: Decoding both images is expensive, so skip it unless the metrics are used
+    //       (the JPEG transcode is lossless and ignores the quality, so there is nothing to retry)
+    if !is_lossy(config, output_type)
+        || is_jpeg_transcode(config, input_type, output_type, input)
+        || (!measure && min_ssim(config, output_type).is_none())
+    {
+        return Ok(Processed {
+            data,
+            status: Status::Compressed,
+            quality,
+            metrics: None,
+        });
+    }
+
+    // NOTE: Compare against the pixels passed to the encoder, so that transforms are not scored as loss
+    let original = reference_image(config, output_type, decode_image(input_type, input)?)?;
+    let mut metrics = metrics::compare(&original, &decode_image(output_type, &data)?);
+
+    if let Some(min_ssim) = min_ssim(config, output_type)
+        && metrics.ssim < min_ssim
+    {
+        let current_quality = quality
+            .or_else(|| quality_range(config, output_type).map(|(_, quality_max)| quality_max))
+            .unwrap_or(100);
+
+        // NOTE: Retry with the lowest higher quality that satisfies min_ssim
+        let retried = match current_quality.checked_add(1).filter(|quality| *quality <= 100) {
+            Some(quality_min) => quality_search::search_lowest(quality_min, 100, |quality| {
+                let data = encode_with_quality(config, input_type, output_type, input, quality)?;
+                let metrics = metrics::compare(&original, &decode_image(output_type, &data)?);
+
+                Ok((metrics.ssim >= min_ssim).then_some((data, metrics)))
+            })?,
+            None => None,
+        };
+
+        match retried {
+            Some((retried_quality, (retried_data, retried_metrics))) => {
+                data = retried_data;
+                quality = Some(retried_quality);
+                metrics = retried_metrics;
+            }
+            None => {
+                // NOTE: Fall back to lossless (WebP, PNG) or the highest quality
+                let mut fallback_config = config.clone();
+                quality = if set_lossless(&mut fallback_config, output_type) {
+                    None
+                } else {
+                    set_quality(&mut fallback_config, output_type, 100);
+                    Some(100)
+                };
+
+                data = encode_data(&fallback_config, input_type, output_type, input)?;
+                metrics = metrics::compare(&original, &decode_image(output_type, &data)?);
+            }
+        }
+    }
+
+    Ok(Processed {
+        data,
+        status: Status::Compressed,
+        quality,
+        metrics: Some(metrics),
+    })
+}
+
+/// エンコーダーに渡される画像（出力形式の ops・size・filters を適用し、JPEG は背景色に合成）
+fn reference_image(config: &Config, file_type: &FileType, mut dynamic_image: DynamicImage) -> Result<DynamicImage> {
+    // NOTE: HEIF scales to the exact size (in libheif, or with Lanczos3 before the filters)
+    if file_type == &FileType::HEIF
+        && let Some(heif_config) = config.heif.as_ref()
+    {
+        if let Some(ops) = heif_config.ops.as_deref() {
+            dynamic_image = transform::apply_ops(dynamic_image, ops)?;
+        }
+        if let Some(size_config) = heif_config.size.as_ref() {
+            dynamic_image = dynamic_image.resize_exact(size_config.width, size_config.height, FilterType::Lanczos3);
+        }
+        if let Some(filters_config) = heif_config.filters.as_ref() {
+            dynamic_image = filter::apply_filters(dynamic_image, filters_config)?;
+        }
+
+        return Ok(dynamic_image);
+    }
+
+    let (ops, size, filters) = match file_type {
+        FileType::PNG => config.png.as_ref().map(|png_config| {
+            (png_config.ops.as_deref(), png_config.size.as_ref(), png_config.filters.as_ref())
+        }),
+        FileType::JPEG => config.jpeg.as_ref().map(|jpeg_config| {
+            (jpeg_config.ops.as_deref(), jpeg_config.size.as_ref(), jpeg_config.filters.as_ref())
+        }),
+        FileType::WEBP => config.webp.as_ref().map(|webp_config| {
+            (webp_config.ops.as_deref(), webp_config.size.as_ref(), webp_config.filters.as_ref())
+        }),
+        FileType::AVIF => config.avif.as_ref().map(|avif_config| {
+            (avif_config.ops.as_deref(), avif_config.size.as_ref(), avif_config.filters.as_ref())
+        }),
+        FileType::JXL => config.jxl.as_ref().map(|jxl_config| {
+            (jxl_config.ops.as_deref(), jxl_config.size.as_ref(), jxl_config.filters.as_ref())
+        }),
+        FileType::HEIF
+        | FileType::GIF
+        | FileType::TIFF
+        | FileType::BMP
+        | FileType::ICO
+        | FileType::TGA
+        | FileType::PDF
+        | FileType::XML => None,
+    }
+    .unwrap_or((None, None, None));
+
+    if let Some(ops) = ops {
+        dynamic_image = transform::apply_ops(dynamic_image, ops)?;
+    }
+
+    if let Some(size_config) = size {
+        dynamic_image = transform::resize_image(&dynamic_image, size_config)?;
+    }
+
+    if let Some(filters_config) = filters {
+        dynamic_image = filter::apply_filters(dynamic_image, filters_config)?;
+    }
+
+    if file_type == &FileType::JPEG && dynamic_image.color().has_alpha() {
+        let background = jpeg_compressor::background_color(
+            config.jpeg.as_ref().and_then(|jpeg_config| jpeg_config.background.as_ref()),
+        )?;
+        dynamic_image = transform::flatten(&dynamic_image, background);
+    }
+
+    Ok(dynamic_image)
+}
+
+fn encode_data(
+    config: &Config,
+    input_type: &FileType,
+    output_type: &FileType,
+    input: &[u8],
+) -> Result<Vec<u8>> {
+    if input_type == output_type {
+        compress_data(config, input_type, input)
+    } else {
+        convert_data(config, input_type, output_type, input)
+    }
+}
+
+fn encode_with_quality(
+    config: &Config,
+    input_type: &FileType,
+    output_type: &FileType,
+    input: &[u8],
+    quality: u8,
+) -> Result<Vec<u8>> {
+    let mut config = config.clone();
+    set_quality(&mut config, output_type, quality);
+
+    encode_data(&config, input_type, output_type, input)
+}
+
+/// 非可逆圧縮で出力されるかどうか（画質指標の計算対象）
+fn is_lossy(config: &Config, file_type: &FileType) -> bool {
+    match file_type {
+        FileType::PNG => config
+            .png
+            .as_ref()
+            .is_some_and(|png_config| png_config.lossy.is_some()),
+        FileType::WEBP => !config
+            .webp
+            .as_ref()
+            .and_then(|webp_config| webp_config.lossless)
+            .unwrap_or(false),
+        FileType::JXL => !config
+            .jxl
+            .as_ref()
+            .and_then(|jxl_config| jxl_config.lossless)
+            .unwrap_or(false),
+        FileType::JPEG | FileType::HEIF | FileType::AVIF => true,
+        FileType::GIF
+        | FileType::TIFF
+        | FileType::BMP
+        | FileType::ICO
+        | FileType::TGA
+        | FileType::PDF
+        | FileType::XML => false,
+    }
+}
+
+/// 品質の範囲（最小, 設定値）
+fn quality_range(config: &Config, file_type: &FileType) -> Option<(u8, u8)> {
+    match file_type {
+        FileType::PNG => config
+            .png
+            .as_ref()
+            .and_then(|png_config| png_config.lossy.as_ref())
+            .map(|lossy| (lossy.quality_min, lossy.quality_max)),
+        FileType::JPEG => config
+            .jpeg
+            .as_ref()
+            .map(|jpeg_config| (1, jpeg_config.quality)),
+        FileType::WEBP => config
+            .webp
+            .as_ref()
+            .map(|webp_config| (1, webp_config.quality)),
+        FileType::HEIF => config
+            .heif
+            .as_ref()
+            .map(|heif_config| (1, heif_config.quality.unwrap_or(100))),
+        FileType::AVIF => config
+            .avif
+            .as_ref()
+            .map(|avif_config| (1, avif_config.quality)),
+        // NOTE: Quality 90 corresponds to the default distance 1.0
+        FileType::JXL => config
+            .jxl
+            .as_ref()
+            .map(|jxl_config| (1, jxl_config.quality.unwrap_or(90))),
+        FileType::GIF
+        | FileType::TIFF
+        | FileType::BMP
+        | FileType::ICO
+        | FileType::TGA
+        | FileType::PDF
+        | FileType::XML => None,
+    }
+}
+
+/// target_bytes と品質の探索範囲
+fn target_range(config: &Config, file_type: &FileType) -> Option<(u64, u8, u8)> {
+    let target_bytes = match file_type {
+        FileType::PNG => config
+            .png
+            .as_ref()
+            .and_then(|png_config| png_config.lossy.as_ref())
+            .and_then(|lossy| lossy.target_bytes),
+        FileType::JPEG => config.jpeg.as_ref().and_then(|jpeg_config| jpeg_config.target_bytes),
+        FileType::WEBP => config.webp.as_ref().and_then(|webp_config| webp_config.target_bytes),
+        FileType::HEIF => config.heif.as_ref().and_then(|heif_config| heif_config.target_bytes),
+        FileType::AVIF => config.avif.as_ref().and_then(|avif_config| avif_config.target_bytes),
+        FileType::JXL => config.jxl.as_ref().and_then(|jxl_config| jxl_config.target_bytes),
+        FileType::GIF
+        | FileType::TIFF
+        | FileType::BMP
+        | FileType::ICO
+        | FileType::TGA
+        | FileType::PDF
+        | FileType::XML => None,
+    }?;
+
+    quality_range(config, file_type)
+        .map(|(quality_min, quality_max)| (target_bytes, quality_min, quality_max))
+}
+
+fn min_ssim(config: &Config, file_type: &FileType) -> Option<f64> {
+    match file_type {
+        FileType::PNG => config
+            .png
+            .as_ref()
+            .and_then(|png_config| png_config.lossy.as_ref())
+            .and_then(|lossy| lossy.min_ssim),
+        FileType::JPEG => config.jpeg.as_ref().and_then(|jpeg_config| jpeg_config.min_ssim),
+        FileType::WEBP => config.webp.as_ref().and_then(|webp_config| webp_config.min_ssim),
+        FileType::HEIF => config.heif.as_ref().and_then(|heif_config| heif_config.min_ssim),
+        FileType::AVIF => config.avif.as_ref().and_then(|avif_config| avif_config.min_ssim),
+        FileType::JXL => config.jxl.as_ref().and_then(|jxl_config| jxl_config.min_ssim),
+        FileType::GIF
+        | FileType::TIFF
+        | FileType::BMP
+        | FileType::ICO
+        | FileType::TGA
+        | FileType::PDF
+        | FileType::XML => None,
+    }
+}
+
+/// 可逆圧縮に切り替え（対応していない形式は false）
+fn set_lossless(config: &mut Config, file_type: &FileType) -> bool {
+    match file_type {
+        FileType::PNG => {
+            if let Some(png_config) = config.png.as_mut() {
+                png_config.lossy = None;
+            }
+            true
+        }
+        FileType::WEBP => {
+            config.webp.get_or_insert_with(WebpConfig::default).lossless = Some(true);
+            true
+        }
+        FileType::JXL => {
+            config.jxl.get_or_insert_with(JxlConfig::default).lossless = Some(true);
+            true
+        }
+        _ => false,
+    }
+}
+
+fn set_quality(config: &mut Config, file_type: &FileType, quality: u8) {
+    match file_type {
+        FileType::PNG => {
+            if let Some(lossy) = config.png.as_mut().and_then(|png_config| png_config.lossy.as_mut()) {
+                // NOTE: Keep the configured lower bound; only the upper bound is searched
+                lossy.quality_min = lossy.quality_min.min(quality);
+                lossy.quality_max = quality;
+            }
+        }
+        FileType::JPEG => {
+            if let Some(jpeg_config) = config.jpeg.as_mut() {
+                jpeg_config.quality = quality;
+            }
+        }
+        FileType::WEBP => {
+            if let Some(webp_config) = config.webp.as_mut() {
+                webp_config.quality = quality;
+            }
+        }
+        FileType::HEIF => {
+            if let Some(heif_config) = config.heif.as_mut() {
+                heif_config.quality = Some(quality);
+            }
+        }
+        FileType::AVIF => {
+            if let Some(avif_config) = config.avif.as_mut() {
+                avif_config.quality = quality;
+            }
+        }
+        FileType::JXL => {
+            if let Some(jxl_config) = config.jxl.as_mut() {
+                jxl_config.quality = Some(quality);
+            }
+        }
+        FileType::GIF
+        | FileType::TIFF
+        | FileType::BMP
+        | FileType::ICO
+        | FileType::TGA
+        | FileType::PDF
+        | FileType::XML => {}
+    }
+}
+
+fn convert_data(
+    config: &Config,
+    input_type: &FileType,
+    output_type: &FileType,
+    input: &[u8],
+) -> Result<Vec<u8>> {
+    // NOTE: JPEG is losslessly transcoded to JPEG XL and the original JPEG is restored when converted back
+    //       (unless it is rotated by EXIF, as the orientation must be applied to the pixels)
+    if is_jpeg_transcode(config, input_type, output_type, input) {
+        return jxl_compressor::transcode_jpeg(config.jxl.as_ref(), input);
+    }
+
+    if input_type == &FileType::JXL
+        && output_type == &FileType::JPEG
+        && jxl_compressor::can_reconstruct(config.jpeg.as_ref())
+        && let Some(jpeg) = jxl_compressor::reconstruct_jpeg(input)?
+    {
+        return Ok(jpeg);
+    }
+
+    // NOTE: Animations keep their frames when converted to a format that can be animated
+    if matches!(output_type, FileType::PNG | FileType::WEBP | FileType::AVIF)
+        && let Some(animation) = decode_animation(input_type, input)?
+    {
+        return encode_animation(config, output_type, animation);
+    }
+
+    let dynamic_image = decode_image(input_type, input)?;
+
+    encode_image(config, output_type, dynamic_image)
+}
+
+/// JPEG を画素にデコードせずに JPEG XL へ可逆変換するかどうか
+fn is_jpeg_transcode(config: &Config, input_type: &FileType, output_type: &FileType, input: &[u8]) -> bool {
+    input_type == &FileType::JPEG
+        && output_type == &FileType::JXL
+        && jxl_compressor::can_transcode(config.jxl.as_ref())
+        && orientation::read(input_type, input) == 1
+}
+
+/// アニメーション画像（2フレーム以上）の場合は全フレームをデコード
+fn decode_animation(file_type: &FileType, input: &[u8]) -> Result<Option<Animation>> {
+    let animation = match file_type {
+        FileType::GIF => gif_compressor::decode_animation(input)?,
+        FileType::PNG if file_type::is_apng(input) => png_compressor::decode_animation(input)?,
+        FileType::WEBP if webp_compressor::is_animated(input) => {
+            webp_compressor::decode_animation(input)?
+        }
+        _ => return Ok(None),
+    };
+
+    Ok((animation.frames.len() > 1).then_some(animation))
+}
+
+fn encode_animation(config: &Config, file_type: &FileType, animation: Animation) -> Result<Vec<u8>> {
+    match file_type {
+        FileType::PNG => png_compressor::compress_animation(config.png.as_ref(), animation),
+        FileType::WEBP => webp_compressor::compress_animation(config.webp.as_ref(), animation),
+        _ => Err(anyhow!(CompressorError::UnsupportedConversion(format!(
+            "Animated {} output is not supported",
+            format_name(file_type)
+        )))),
+    }
+}
+
+fn compress_data(config: &Config, file_type: &FileType, input: &[u8]) -> Result<Vec<u8>> {
+    match file_type {
+        FileType::PNG => png_compressor::compress(config.png.as_ref(), input),
+        FileType::JPEG => jpeg_compressor::compress(config.jpeg.as_ref(), input),
+        FileType::WEBP => webp_compressor::compress(config.webp.as_ref(), input),
+        FileType::GIF => gif_compressor::compress(config.gif.as_ref(), input),
+        FileType::HEIF => heif_compressor::compress(config.heif.as_ref(), input),
+        FileType::AVIF => avif_compressor::compress(config.avif.as_ref(), input),
+        FileType::JXL => jxl_compressor::compress(config.jxl.as_ref(), input),
+        FileType::TIFF => tiff_compressor::compress(config.tiff.as_ref(), input),
+        FileType::ICO => ico_compressor::compress(config.png.as_ref(), input),
+        FileType::BMP | FileType::TGA => Err(anyhow!(CompressorError::UnsupportedConversion(
+            format!("{} output is not supported", format_name(file_type))
+        ))),
+        FileType::PDF => pdf_compressor::compress(input, config.pdf.as_ref()),
+        FileType::XML => svg_compressor::compress(input),
+    }
+}
+
+pub fn compress(
+    config: &Config,
+    verbose: bool,
+    input_path: &String,
+    output_path: &String,
+    output_format: Option<&FileType>,
+    options: &OutputOptions,
+) -> Result<CompressionResult> {
+    let now = Instant::now();
+    let input_file_name = Path::new(input_path)
+        .file_name()
+        .unwrap()
+        .to_string_lossy()
+        .into_owned();
+
+    let buffer = if input_path == STDIO_PATH {
+        read_stdin_bytes()?
+    } else {
+        read_file_bytes(input_path)?
+    };
+
+    // NOTE: Read before the output may replace the input file
+    let input_metadata = if options.preserve_attributes && input_path != STDIO_PATH {
+        Some(get_file_metadata(input_path)?)
+    } else {
+        None
+    };
+    let file_type =
+        detect_from_bytes(&buffer).ok_or_else(|| anyhow!(CompressorError::UnknownFileFormat))?;
+    let output_type = &output_type(config, &file_type, output_format);
+
+    // NOTE: Fail before encoding when the output extension or --format names an unsupported target
+    check_conversion(&file_type, output_type)?;
+
+    if output_type != &file_type && output_path != STDIO_PATH && is_same_file(input_path, output_path) {
+        return Err(in_place_error(&file_type, output_type));
+    }
+
+    // NOTE: Keep the standard output clean when the compressed data or the report is written to it
+    let mut out: Box<dyn Write> = if output_path == STDIO_PATH || options.report_to_stdout {
+        Box::new(io::stderr())
+    } else {
+        Box::new(io::stdout())
+    };
+
+    if verbose {
+        writeln!(out, "===== Start =====")?;
+        writeln!(out, "\n[Input]")?;
+        writeln!(out, "\tFile name: {}", input_file_name)?;
+
+        writeln!(out, "\tSize: {} bytes", buffer.len())?;
+
+        print_options(&mut out, config, output_type)?;
+    }
+
+    if responsive::is_enabled(config) && file_type.is_image() {
+        if output_path == STDIO_PATH {
+            return Err(anyhow!("Multiple variants cannot be written to standard output"));
+        }
+
+        // NOTE: Decode once and resize the same image for every width and format
+        let variants = page_input(&buffer, selected_page(config, &file_type))
+            .and_then(|input| decode_image(&file_type, &input))
+            .and_then(|dynamic_image| {
+                responsive::encode_variants(config, &dynamic_image, output_path, output_type)
+            })
+            .map_err(|e| {
+                anyhow!(
+                    "{} variant generation failed for file: {}. Error: {}",
+                    format_name(&file_type),
+                    input_path,
+                    e
+                )
+            })?;
+
+        for variant in variants.iter() {
+            write_file_bytes(&variant.path, &variant.data)?;
+
+            if let Some(input_metadata) = input_metadata.as_ref() {
+                apply_file_attributes(&variant.path, input_metadata)?;
+            }
+        }
+
+        let after_bytes: usize = variants.iter().map(|variant| variant.data.len()).sum();
+
+        if verbose {
+            writeln!(out, "\n[Result]")?;
+
+            writeln!(out, "\tBefore: {} bytes", buffer.len())?;
+            writeln!(out, "\tAfter: {} bytes", after_bytes)?;
+
+            writeln!(out, "\n[Output]")?;
+            for variant in variants.iter() {
+                writeln!(
+                    out,
+                    "\tFile name: {} ({}, {}x{}, {} bytes)",
+                    file_name(&variant.path),
+                    format_name(&variant.file_type),
+                    variant.dimensions.width,
+                    variant.dimensions.height,
+                    variant.data.len()
+                )?;
+            }
+            writeln!(out, "\tProcessing time: {:?} sec", now.elapsed().as_secs_f64())?;
+            writeln!(out, "\n===== End =====")?;
+        }
+
+        return Ok(CompressionResult {
+            input_type: file_type.clone(),
+            file_type: output_type.clone(),
+            status: Status::Compressed,
+            before_bytes: buffer.len() as u64,
+            after_bytes: after_bytes as u64,
+            before_dimensions: image_dimensions(&file_type, &buffer),
+            after_dimensions: variants.first().map(|variant| variant.dimensions),
+            quality: None,
+            metrics: None,
+            page_paths: Vec::new(),
+            variants: variants
+                .into_iter()
+                .map(|variant| VariantOutput {
+                    bytes: variant.data.len() as u64,
+                    path: variant.path,
+                    format: variant.file_type,
+                    dimensions: variant.dimensions,
+                    quality: variant.quality,
+                })
+                .collect(),
+        });
+    }
+
+    let measure = verbose || options.metrics;
+    let mut outputs = Vec::new();
+
+    for (page, page_output_path) in page_outputs(config, &file_type, output_path, &buffer)? {
+        let input = page_input(&buffer, page)?;
+
+        // NOTE: Compress a file
+        let processed = if output_type == &file_type {
+            match process_data(config, &file_type, &file_type, &input, measure) {
+                Ok(processed) => processed,
+                Err(e) => {
+                    return Err(anyhow!(
+                        "{} compression failed for file: {}. Error: {}",
+                        format_name(&file_type),
+                        input_path,
+                        e
+                    ));
+                }
+            }
+        } else {
+            // NOTE: Convert to another format
+            match process_data(config, &file_type, output_type, &input, measure) {
+                Ok(processed) => processed,
+                Err(e) => {
+                    return Err(anyhow!(
+                        "{} to {} conversion failed for file: {}. Error: {}",
+                        format_name(&file_type),
+                        format_name(output_type),
+                        input_path,
+                        e
+                    ));
+                }
+            }
+        };
+
+        if page_output_path == STDIO_PATH {
+            write_stdout_bytes(&processed.data)?;
+        } else if is_same_file(input_path, &page_output_path) {
+            // NOTE: Replace the source only with a valid output, and leave it untouched if kept as is
+            if processed.status == Status::Compressed || processed.data.as_slice() != &*input {
+                replace_input(input_path, &page_output_path, output_type, &processed.data, options.backup)?;
+            }
+        } else {
+            write_file_bytes(&page_output_path, &processed.data)?;
+        }
+
+        if page_output_path != STDIO_PATH
+            && let Some(input_metadata) = input_metadata.as_ref()
+        {
+            apply_file_attributes(&page_output_path, input_metadata)?;
+        }
+
+        outputs.push((page_output_path, processed));
+    }
+
+    let after_bytes: usize = outputs.iter().map(|(_, processed)| processed.data.len()).sum();
+    let page_paths = if outputs.len() > 1 {
+        outputs.iter().map(|(path, _)| path.clone()).collect()
+    } else {
+        Vec::new()
+    };
+    let (first_output_path, processed) = outputs.swap_remove(0);
+
+    if verbose {
+        writeln!(out, "\n[Result]")?;
+
+        writeln!(out, "\tBefore: {} bytes", buffer.len())?;
+        writeln!(out, "\tAfter: {} bytes", after_bytes)?;
+
+        writeln!(out, "\tStatus: {}", processed.status.as_str())?;
+
+        if let Some(quality) = processed.quality {
+            writeln!(out, "\tQuality: {}", quality)?;
+        }
+
+        if let Some(metrics) = processed.metrics.as_ref() {
+            writeln!(out, "\tSSIM: {:.6}", metrics.ssim)?;
+            writeln!(out, "\tDSSIM: {:.6}", metrics.dssim)?;
+            writeln!(out, "\tPSNR: {:.2} dB", metrics.psnr)?;
+        }
+
+        writeln!(out, "\n[Output]")?;
+        if page_paths.is_empty() {
+            writeln!(out, "\tFile name: {}", file_name(&first_output_path))?;
+        } else {
+            for page_path in page_paths.iter() {
+                writeln!(out, "\tFile name: {}", file_name(page_path))?;
+            }
+        }
+        writeln!(out, "\tFormat: {}", format_name(output_type))?;
+        writeln!(out, "\tProcessing time: {:?} sec", now.elapsed().as_secs_f64())?;
+        writeln!(out, "\n===== End =====")?;
+    }
+
+    Ok(CompressionResult {
+        input_type: file_type.clone(),
+        file_type: output_type.clone(),
+        status: processed.status,
+        before_bytes: buffer.len() as u64,
+        after_bytes: after_bytes as u64,
+        before_dimensions: image_dimensions(&file_type, &buffer),
+        after_dimensions: image_dimensions(output_type, &processed.data),
+        quality: processed.quality,
+        metrics: processed.metrics,
+        page_paths,
+        variants: Vec::new(),
+    })
+}
+
+fn file_name(path: &str) -> String {
+    Path::new(path)
+        .file_name()
+        .unwrap()
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// 出力するページ（1 から。None: 入力全体）と出力先
+///
+/// tiff.all_pages の場合は複数ページの TIFF の各ページを `{stem}-{page}.{ext}` に出力
+fn page_outputs(
+    config: &Config,
+    file_type: &FileType,
+    output_path: &str,
+    input: &[u8],
+) -> Result<Vec<(Option<u32>, String)>> {
+    let Some(tiff_config) = config.tiff.as_ref().filter(|_| file_type == &FileType::TIFF) else {
+        return Ok(vec![(None, output_path.to_string())]);
+    };
+
+    if tiff_config.all_pages.unwrap_or(false) {
+        let page_count = tiff_compressor::page_count(input)?;
+
+        if page_count > 1 {
+            if output_path == STDIO_PATH {
+                return Err(anyhow!("Multiple pages cannot be written to standard output"));
+            }
+
+            return Ok((1..=page_count)
+                .map(|page| (Some(page), page_path(output_path, page)))
+                .collect());
+        }
+    }
+
+    Ok(vec![(tiff_config.page, output_path.to_string())])
+}
+
+/// ページを指定した場合はそのページのみの入力
+fn page_input(input: &[u8], page: Option<u32>) -> Result<Cow<'_, [u8]>> {
+    match page {
+        Some(page) => Ok(Cow::Owned(tiff_compressor::extract_page(input, page)?)),
+        None => Ok(Cow::Borrowed(input)),
+    }
+}
+
+fn selected_page(config: &Config, file_type: &FileType) -> Option<u32> {
+    match file_type {
+        FileType::TIFF => config.tiff.as_ref().and_then(|tiff_config| tiff_config.page),
+        _ => None,
+    }
+}
+
+fn page_path(output_path: &str, page: u32) -> String {
+    let path = Path::new(output_path);
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let file_name = match path.extension() {
+        Some(extension) => format!("{}-{}.{}", stem, page, extension.to_string_lossy()),
+        None => format!("{}-{}", stem, page),
+    };
+
+    path.with_file_name(file_name).to_string_lossy().into_owned()
+}
+
+fn format_name(file_type: &FileType) -> &'static str {
+    match file_type {
+        FileType::PNG => "PNG",
+        FileType::JPEG => "JPEG",
+        FileType::WEBP => "WebP",
+        FileType::GIF => "GIF",
+        FileType::HEIF => "HEIF/HEIC",
+        FileType::AVIF => "AVIF",
+        FileType::JXL => "JPEG XL",
+        FileType::TIFF => "TIFF",
+        FileType::BMP => "BMP",
+        FileType::ICO => "ICO",
+        FileType::TGA => "TGA",
+        FileType::PDF => "PDF",
+        FileType::XML => "SVG",
+    }
+}
+
+/// 幅x高さ（未指定は "-"）とリサイズ方法
+fn size_text(size: &SizeFilterConfig) -> String {
+    let length = |value: Option<u32>| value.map_or("-".to_string(), |value| value.to_string());
+    let mut text = format!(
+        "{}x{} ({}, {}",
+        length(size.width),
+        length(size.height),
+        size.mode.as_deref().unwrap_or("fit"),
+        size.filter
+    );
+
+    if size.no_upscale.unwrap_or(false) {
+        text.push_str(", no upscale");
+    }
+
+    match (size.focus.as_ref(), size.gravity.as_deref()) {
+        (Some(focus), _) => text.push_str(&format!(", focus ({}, {})", focus.x, focus.y)),
+        (None, Some(gravity)) => text.push_str(&format!(", gravity {}", gravity)),
+        (None, None) => {}
+    }
+    text.push(')');
+
+    text
+}
 
-    let file_type = detect_file_type(input_path)?;
+/// 適用する操作の一覧（例: "trim (fuzz 10) → pad (square) → rotate 90"）
+fn ops_text(ops: &[OpConfig]) -> String {
+    let texts: Vec<String> = ops
+        .iter()
+        .map(|op| match op {
+            OpConfig::Crop(crop) => {
+                format!("crop {}x{}+{}+{}", crop.width, crop.height, crop.x, crop.y)
+            }
+            OpConfig::Rotate(angle) => format!("rotate {}", angle),
+            OpConfig::Flip(direction) => format!("flip {}", direction),
+            OpConfig::Pad(pad) => {
+                let mut options = Vec::new();
+                let sides = [pad.top, pad.right, pad.bottom, pad.left].map(|side| side.unwrap_or(0));
+                if sides.iter().any(|side| *side > 0) {
+                    options.push(format!("{} {} {} {}", sides[0], sides[1], sides[2], sides[3]));
+                }
+                if pad.width.is_some() || pad.height.is_some() {
+                    let length = |value: Option<u32>| value.map_or("-".to_string(), |value| value.to_string());
+                    options.push(format!("{}x{}", length(pad.width), length(pad.height)));
+                }
+                if pad.square.unwrap_or(false) {
+                    options.push("square".to_string());
+                }
+                if let Some(background) = pad.background.as_ref() {
+                    options.push(background.clone());
+                }
+                format!("pad ({})", options.join(", "))
+            }
+            OpConfig::Trim(trim) => format!("trim (fuzz {})", trim.fuzz.unwrap_or(0.0)),
+            OpConfig::Resize(size) => format!("resize {}", size_text(size)),
+        })
+        .collect();
 
-    if verbose {
-        println!("===== Start =====");
-        println!("\n[Input]");
-        println!("\tFile name: {}", input_file_name);
+    texts.join(" → ")
+}
 
-        if let Ok(file_size) = get_file_size(input_path) {
-            println!("\tSize: {} bytes", file_size);
-        }
+/// 適用するフィルターの一覧（例: "brightness 10, unsharp (radius 1, amount 0.8, threshold 2)"）
+fn filters_text(filters: &FiltersConfig) -> String {
+    let mut texts = Vec::new();
+
+    if let Some(brightness) = filters.brightness {
+        texts.push(format!("brightness {}", brightness));
+    }
+    if let Some(contrast) = filters.contrast {
+        texts.push(format!("contrast {}", contrast));
+    }
+    if let Some(gamma) = filters.gamma {
+        texts.push(format!("gamma {}", gamma));
+    }
+    if filters.grayscale.unwrap_or(false) {
+        texts.push("grayscale".to_string());
+    }
+    if let Some(blur) = filters.blur {
+        texts.push(format!("blur {}", blur));
+    }
+    if let Some(unsharp) = filters.unsharp.as_ref() {
+        texts.push(format!(
+            "unsharp (radius {}, amount {}, threshold {})",
+            unsharp.radius,
+            unsharp.amount.unwrap_or(1.0),
+            unsharp.threshold.unwrap_or(0)
+        ));
     }
 
-    // NOTE: Compress a file
-    let compressed_data = match file_type {
+    texts.join(", ")
+}
+
+fn print_options(out: &mut dyn Write, config: &Config, file_type: &FileType) -> io::Result<()> {
+    match file_type {
         FileType::PNG => {
-            if verbose {
-                if let Some(png_config) = config.png.as_ref() {
-                    println!("\n[Options]");
-                    println!("\tQuality: {}", png_config.quality);
+            if let Some(png_config) = config.png.as_ref() {
+                writeln!(out, "\n[Options]")?;
+                writeln!(out, "\tQuality: {}", png_config.quality)?;
 
-                    if let Some(size) = png_config.size.as_ref() {
-                        println!("\tSize: {}x{}", size.width, size.width);
-                    }
+                if let Some(ops) = png_config.ops.as_deref() {
+                    writeln!(out, "\tOps: {}", ops_text(ops))?;
+                }
 
-                    println!("\tStrip: {}", png_config.strip);
-                    println!("\tInterlacing: {}", png_config.interlacing);
-                    println!("\tOptimize alpha: {}", png_config.optimize_alpha);
+                if let Some(size) = png_config.size.as_ref() {
+                    writeln!(out, "\tSize: {}", size_text(size))?;
+                }
 
-                    if let Some(libdeflater) = png_config.libdeflater.as_ref() {
-                        println!("\tLibdeflater:");
-                        println!("\t\tCompression: {}", libdeflater.compression);
-                    }
+                if let Some(filters) = png_config.filters.as_ref() {
+                    writeln!(out, "\tFilters: {}", filters_text(filters))?;
+                }
 
-                    if let Some(zopfli) = png_config.zopfli.as_ref() {
-                        println!("\tZopfli:");
-                        println!("\t\tIterations: {}", zopfli.iterations);
-                    }
+                writeln!(out, "\tStrip: {}", png_config.strip)?;
+                writeln!(out, "\tInterlacing: {}", png_config.interlacing)?;
+                writeln!(out, "\tOptimize alpha: {}", png_config.optimize_alpha)?;
 
-                    if let Some(lossy) = png_config.lossy.as_ref() {
-                        println!("\tLossy:");
-                        println!("\t\tQuality min: {}", lossy.quality_min);
-                        println!("\t\tQuality max: {}", lossy.quality_max);
-                        if let Some(colors) = lossy.colors {
-                            println!("\t\tColors: {}", colors);
-                        }
-                        if let Some(speed) = lossy.speed {
-                            println!("\t\tSpeed: {}", speed);
-                        }
-                    }
+                if let Some(libdeflater) = png_config.libdeflater.as_ref() {
+                    writeln!(out, "\tLibdeflater:")?;
+                    writeln!(out, "\t\tCompression: {}", libdeflater.compression)?;
                 }
-            }
 
-            let result = png_compressor::compress(config.png.as_ref(), input_path);
-            match result {
-                Ok(data) => data,
-                Err(e) => {
-                    return Err(anyhow!(
-                        "PNG compression failed for file: {}. Error: {}",
-                        input_path,
-                        e
-                    ));
+                if let Some(zopfli) = png_config.zopfli.as_ref() {
+                    writeln!(out, "\tZopfli:")?;
+                    writeln!(out, "\t\tIterations: {}", zopfli.iterations)?;
+                }
+
+                if let Some(lossy) = png_config.lossy.as_ref() {
+                    writeln!(out, "\tLossy:")?;
+                    writeln!(out, "\t\tQuality min: {}", lossy.quality_min)?;
+                    writeln!(out, "\t\tQuality max: {}", lossy.quality_max)?;
+                    if let Some(colors) = lossy.colors {
+                        writeln!(out, "\t\tColors: {}", colors)?;
+                    }
+                    if let Some(speed) = lossy.speed {
+                        writeln!(out, "\t\tSpeed: {}", speed)?;
+                    }
                 }
             }
         }
         FileType::JPEG => {
-            if verbose {
-                if let Some(jpeg_config) = config.jpeg.as_ref() {
-                    println!("\n[Options]");
-                    println!("\tQuality: {}", jpeg_config.quality);
+            if let Some(jpeg_config) = config.jpeg.as_ref() {
+                writeln!(out, "\n[Options]")?;
+                writeln!(out, "\tQuality: {}", jpeg_config.quality)?;
 
-                    if let Some(size) = jpeg_config.size.as_ref() {
-                        println!("\tSize: {}x{}", size.width, size.width);
-                    }
+                if let Some(ops) = jpeg_config.ops.as_deref() {
+                    writeln!(out, "\tOps: {}", ops_text(ops))?;
+                }
 
-                    if let Some(scan_optimization_mode) = jpeg_config.scan_optimization_mode.as_ref() {
-                        println!("\tScan optimization mode: {}", scan_optimization_mode);
-                    }
+                if let Some(size) = jpeg_config.size.as_ref() {
+                    writeln!(out, "\tSize: {}", size_text(size))?;
+                }
+
+                if let Some(filters) = jpeg_config.filters.as_ref() {
+                    writeln!(out, "\tFilters: {}", filters_text(filters))?;
+                }
 
-                    println!("\tProgressive mode: {}", jpeg_config.progressive_mode);
-                    println!("\tOptimize coding: {}", jpeg_config.optimize_coding);
-                    println!("\tUse scans in trellis: {}", jpeg_config.use_scans_in_trellis);
-                    println!("\tSmoothing factor: {}", jpeg_config.smoothing_factor);
-                    println!("\tExif: {}", jpeg_config.exif);
+                if let Some(scan_optimization_mode) = jpeg_config.scan_optimization_mode.as_ref() {
+                    writeln!(out, "\tScan optimization mode: {}", scan_optimization_mode)?;
                 }
+
+                writeln!(out, "\tProgressive mode: {}", jpeg_config.progressive_mode)?;
+                writeln!(out, "\tOptimize coding: {}", jpeg_config.optimize_coding)?;
+                writeln!(out, "\tUse scans in trellis: {}", jpeg_config.use_scans_in_trellis)?;
+                writeln!(out, "\tSmoothing factor: {}", jpeg_config.smoothing_factor)?;
+                writeln!(out, "\tExif: {}", jpeg_config.exif)?;
             }
+        }
+        FileType::WEBP => {
+            if let Some(webp_config) = config.webp.as_ref() {
+                writeln!(out, "\n[Options]")?;
+                writeln!(out, "\tQuality: {}", webp_config.quality)?;
 
-            let mut input_file = File::open(input_path)
-                .map_err(|e| anyhow!(CompressorError::IoError(e)))?;
-
-            let mut buffer = [0; 50];
-            input_file.read_exact(&mut buffer)?;
-            input_file.rewind()?;
-            let exif_marker: [u8; 6] = [0x45, 0x78, 0x69, 0x66, 0x00, 0x00];
-            let exif_exists = buffer
-                .windows(6)
-                .position(|window| window == exif_marker)
-                .is_some();
-            let metadata = if exif_exists {
-                Metadata::new_from_path(Path::new(input_path))?
-            } else {
-                Metadata::new()
-            };
-
-            let result =
-                jpeg_compressor::compress(config.jpeg.as_ref(), input_path, &metadata);
-            match result {
-                Ok(mut data) => {
-                    if let Some(jpeg_config) = config.jpeg {
-                        match jpeg_config.exif.as_str() {
-                            "all" => {
-                                // NOTE: Write "all" exif
-                                metadata.write_to_vec(&mut data, FileExtension::JPEG)?;
-                            }
-                            "orientation" => {
-                                // NOTE: Write "orientation" exif
-                                let mut tag_iterator =
-                                    metadata.get_tag(&ExifTag::Orientation(vec![]));
-                                if let Some(exif_tag) = tag_iterator.next() {
-                                    let mut new_metadata = Metadata::new();
-                                    new_metadata.set_tag(exif_tag.clone());
-                                    new_metadata.write_to_vec(&mut data, FileExtension::JPEG)?;
-                                }
-                            }
-                            _ => {}
-                        }
-                    }
+                // TODO:
+            }
+        }
+        FileType::GIF => {
+            if let Some(gif_config) = config.gif.as_ref() {
+                writeln!(out, "\n[Options]")?;
+                writeln!(out, "\tQuality: {}", gif_config.quality)?;
 
-                    data
+                if let Some(ops) = gif_config.ops.as_deref() {
+                    writeln!(out, "\tOps: {}", ops_text(ops))?;
                 }
-                Err(e) => {
-                    return Err(anyhow!(
-                        "JPEG compression failed for file: {}. Error: {}",
-                        input_path,
-                        e
-                    ));
+
+                if let Some(size) = gif_config.size.as_ref() {
+                    writeln!(out, "\tSize: {}", size_text(size))?;
+                }
+
+                if let Some(filters) = gif_config.filters.as_ref() {
+                    writeln!(out, "\tFilters: {}", filters_text(filters))?;
+                }
+
+                if let Some(fast) = gif_config.fast {
+                    writeln!(out, "\tFast: {}", fast)?;
+                }
+
+                if let Some(loop_speed) = gif_config.loop_speed {
+                    writeln!(out, "\tLoop speed: {}", loop_speed)?;
+                }
+
+                if let Some(loop_count) = gif_config.loop_count {
+                    writeln!(out, "\tLoop count: {}", loop_count)?;
                 }
             }
         }
-        FileType::WEBP => {
-            if verbose {
-                if let Some(webp_config) = config.webp.as_ref() {
-                    println!("\n[Options]");
-                    println!("\tQuality: {}", webp_config.quality);
+        FileType::HEIF => {
+            if let Some(heif_config) = config.heif.as_ref() {
+                writeln!(out, "\n[Options]")?;
 
-                    // TODO:
+                if let Some(quality) = heif_config.quality {
+                    writeln!(out, "\tQuality: {}", quality)?;
                 }
-            }
 
-            let result = webp_compressor::compress(config.webp.as_ref(), input_path);
-            match result {
-                Ok(data) => data,
-                Err(e) => {
-                    return Err(anyhow!(
-                        "WebP compression failed for file: {}. Error: {}",
-                        input_path,
-                        e
-                    ));
+                if let Some(ops) = heif_config.ops.as_deref() {
+                    writeln!(out, "\tOps: {}", ops_text(ops))?;
+                }
+
+                if let Some(filters) = heif_config.filters.as_ref() {
+                    writeln!(out, "\tFilters: {}", filters_text(filters))?;
                 }
             }
         }
-        FileType::GIF => {
-            if verbose {
-                if let Some(gif_config) = config.gif.as_ref() {
-                    println!("\n[Options]");
-                    println!("\tQuality: {}", gif_config.quality);
+        FileType::AVIF => {
+            if let Some(avif_config) = config.avif.as_ref() {
+                writeln!(out, "\n[Options]")?;
+                writeln!(out, "\tQuality: {}", avif_config.quality)?;
 
-                    if let Some(size) = gif_config.size.as_ref() {
-                        println!("\tSize: {}x{}", size.width, size.height);
-                    }
+                if let Some(ops) = avif_config.ops.as_deref() {
+                    writeln!(out, "\tOps: {}", ops_text(ops))?;
+                }
 
-                    if let Some(fast) = gif_config.fast {
-                        println!("\tFast: {}", fast);
-                    }
+                if let Some(size) = avif_config.size.as_ref() {
+                    writeln!(out, "\tSize: {}", size_text(size))?;
+                }
 
-                    if let Some(loop_speed) = gif_config.loop_speed {
-                        println!("\tLoop speed: {}", loop_speed);
-                    }
+                if let Some(filters) = avif_config.filters.as_ref() {
+                    writeln!(out, "\tFilters: {}", filters_text(filters))?;
+                }
 
-                    if let Some(loop_count) = gif_config.loop_count {
-                        println!("\tLoop count: {}", loop_count);
-                    }
+                if let Some(alpha_quality) = avif_config.alpha_quality {
+                    writeln!(out, "\tAlpha quality: {}", alpha_quality)?;
                 }
-            }
 
-            let result = gif_compressor::compress(config.gif.as_ref(), input_path);
-            match result {
-                Ok(data) => data,
-                Err(e) => {
-                    return Err(anyhow!(
-                        "GIF compression failed for file: {}. Error: {}",
-                        input_path,
-                        e
-                    ));
+                if let Some(speed) = avif_config.speed {
+                    writeln!(out, "\tSpeed: {}", speed)?;
+                }
+
+                if let Some(bit_depth) = avif_config.bit_depth {
+                    writeln!(out, "\tBit depth: {}", bit_depth)?;
+                }
+
+                if let Some(chroma_subsampling) = avif_config.chroma_subsampling.as_ref() {
+                    writeln!(out, "\tChroma subsampling: {}", chroma_subsampling)?;
                 }
             }
         }
-        FileType::HEIF => {
-            if verbose {
-                if let Some(heif_config) = config.heif.as_ref() {
-                    println!("\n[Options]");
+        FileType::JXL => {
+            if let Some(jxl_config) = config.jxl.as_ref() {
+                writeln!(out, "\n[Options]")?;
 
-                    if let Some(quality) = heif_config.quality {
-                        println!("\tQuality: {}", quality);
-                    }
+                if let Some(quality) = jxl_config.quality {
+                    writeln!(out, "\tQuality: {}", quality)?;
                 }
-            }
 
-            let result = heif_compressor::compress(config.heif.as_ref(), input_path);
-            match result {
-                Ok(data) => data,
-                Err(e) => {
-                    return Err(anyhow!(
-                        "HEIF/HEIC compression failed for file: {}. Error: {}",
-                        input_path,
-                        e
-                    ));
+                if let Some(distance) = jxl_config.distance {
+                    writeln!(out, "\tDistance: {}", distance)?;
+                }
+
+                if let Some(effort) = jxl_config.effort {
+                    writeln!(out, "\tEffort: {}", effort)?;
+                }
+
+                if let Some(lossless) = jxl_config.lossless {
+                    writeln!(out, "\tLossless: {}", lossless)?;
+                }
+
+                if let Some(jpeg_transcode) = jxl_config.jpeg_transcode {
+                    writeln!(out, "\tJPEG transcode: {}", jpeg_transcode)?;
+                }
+
+                if let Some(ops) = jxl_config.ops.as_deref() {
+                    writeln!(out, "\tOps: {}", ops_text(ops))?;
+                }
+
+                if let Some(size) = jxl_config.size.as_ref() {
+                    writeln!(out, "\tSize: {}", size_text(size))?;
+                }
+
+                if let Some(filters) = jxl_config.filters.as_ref() {
+                    writeln!(out, "\tFilters: {}", filters_text(filters))?;
                 }
             }
         }
-        FileType::PDF => {
-            if verbose {
-                if let Some(pdf_config) = config.pdf.as_ref() {
-                    println!("\n[Options]");
-                    println!("\tRemove info: {}", pdf_config.remove_info);
-                    println!("\tRemove metadata: {}", pdf_config.remove_metadata);
-                    println!("\tRemove unuse fonts: {}", pdf_config.remove_unuse_fonts);
+        FileType::TIFF => {
+            if let Some(tiff_config) = config.tiff.as_ref() {
+                writeln!(out, "\n[Options]")?;
+
+                if let Some(compression) = tiff_config.compression.as_ref() {
+                    writeln!(out, "\tCompression: {}", compression)?;
+                }
+
+                if let Some(ops) = tiff_config.ops.as_deref() {
+                    writeln!(out, "\tOps: {}", ops_text(ops))?;
+                }
 
-                    println!("\tPng:");
-                    println!("\t\tQuality Min: {}", pdf_config.png.quality_min);
-                    println!("\t\tQuality Max: {}", pdf_config.png.quality_max);
+                if let Some(size) = tiff_config.size.as_ref() {
+                    writeln!(out, "\tSize: {}", size_text(size))?;
+                }
 
-                    println!("\tJpeg:");
-                    println!("\t\tQuality: {}", pdf_config.jpeg.quality);
-                    println!("\t\tMax length: {}", pdf_config.jpeg.max_length);
+                if let Some(filters) = tiff_config.filters.as_ref() {
+                    writeln!(out, "\tFilters: {}", filters_text(filters))?;
                 }
             }
+        }
+        FileType::ICO => {
+            if let Some(ico_config) = config.ico.as_ref() {
+                writeln!(out, "\n[Options]")?;
 
-            let result = pdf_compressor::compress(input_path, config.pdf.as_ref());
-            match result {
-                Ok(data) => data,
-                Err(e) => {
-                    return Err(anyhow!(
-                        "PDF compression failed for file: {}. Error: {}",
-                        input_path,
-                        e
-                    ));
+                if let Some(sizes) = ico_config.sizes.as_ref() {
+                    let sizes: Vec<String> = sizes.iter().map(|size| size.to_string()).collect();
+                    writeln!(out, "\tSizes: {}", sizes.join(", "))?;
+                }
+
+                if let Some(filter) = ico_config.filter.as_ref() {
+                    writeln!(out, "\tFilter: {}", filter)?;
                 }
             }
         }
-        FileType::XML => {
-            let result = svg_compressor::compress(input_path);
-            match result {
-                Ok(data) => data,
-                Err(e) => {
-                    return Err(anyhow!(
-                        "SVG compression failed for file: {}. Error: {}",
-                        input_path,
-                        e
-                    ));
+        FileType::PDF => {
+            if let Some(pdf_config) = config.pdf.as_ref() {
+                writeln!(out, "\n[Options]")?;
+                writeln!(out, "\tRemove info: {}", pdf_config.remove_info)?;
+                writeln!(out, "\tRemove metadata: {}", pdf_config.remove_metadata)?;
+                writeln!(out, "\tRemove unuse fonts: {}", pdf_config.remove_unuse_fonts)?;
+
+                writeln!(out, "\tPng:")?;
+                writeln!(out, "\t\tQuality Min: {}", pdf_config.png.quality_min)?;
+                writeln!(out, "\t\tQuality Max: {}", pdf_config.png.quality_max)?;
+
+                writeln!(out, "\tJpeg:")?;
+                writeln!(out, "\t\tQuality: {}", pdf_config.jpeg.quality)?;
+                match pdf_config.jpeg.size.as_ref() {
+                    Some(size) => writeln!(out, "\t\tSize: {}", size_text(size))?,
+                    None => writeln!(out, "\t\tMax length: {}", pdf_config.jpeg.max_length)?,
                 }
             }
         }
-    };
+        FileType::BMP | FileType::TGA | FileType::XML => {}
+    }
+
+    Ok(())
+}
 
-    write_file_bytes(output_path, &compressed_data)?;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::codecs::gif::{GifEncoder, Repeat};
+    use image::{Delay, Frame, Rgba, RgbaImage};
 
-    if verbose {
-        println!("\n[Result]");
+    /// 赤・青の2フレーム（表示時間 50ms, 200ms、ループ 2 回 = 再生 3 回）の GIF
+    fn animated_gif() -> Vec<u8> {
+        let frames = [(Rgba([255, 0, 0, 255]), 50), (Rgba([0, 0, 255, 255]), 200)].map(|(color, delay_ms)| {
+            Frame::from_parts(RgbaImage::from_pixel(4, 4, color), 0, 0, Delay::from_numer_denom_ms(delay_ms, 1))
+        });
+
+        let mut bytes = Vec::new();
+        {
+            let mut encoder = GifEncoder::new(&mut bytes);
+            encoder.set_repeat(Repeat::Finite(2)).unwrap();
+            encoder.encode_frames(frames).unwrap();
+        }
+        bytes
+    }
+
+    /// 色の変化が細かい画像（非可逆圧縮で SSIM が下がりやすい）
+    fn noisy_png() -> Vec<u8> {
+        let image = RgbaImage::from_fn(64, 64, |x, y| {
+            let value = ((x * 37 + y * y * 11) % 200) as u8 + 20;
+            Rgba([value, value, value, 255])
+        });
+        png_bytes(image)
+    }
+
+    /// 左右で明るさが異なるなめらかな画像
+    fn smooth_png() -> Vec<u8> {
+        let image = RgbaImage::from_fn(96, 64, |x, y| {
+            let base = if x < 48 { 40 } else { 180 };
+            Rgba([(base + y) as u8, (base + x / 2) as u8, base as u8, 255])
+        });
+        png_bytes(image)
+    }
+
+    fn png_bytes(image: RgbaImage) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        DynamicImage::ImageRgba8(image)
+            .write_to(&mut Cursor::new(&mut bytes), ImageFormat::Png)
+            .unwrap();
+        bytes
+    }
 
-        if let Ok(file_size) = get_file_size(input_path) {
-            println!("\tBefore: {} bytes", file_size);
+    fn jpeg_settings(json: &str) -> Config {
+        let mut jpeg = serde_json::to_value(crate::config_json::JpegConfig::default()).unwrap();
+        for (key, value) in serde_json::from_str::<serde_json::Map<_, _>>(json).unwrap() {
+            jpeg[key] = value;
         }
 
-        if let Ok(file_size) = get_file_size(input_path) {
-            println!("\tAfter: {} bytes", file_size);
+        Config {
+            jpeg: Some(serde_json::from_value(jpeg).unwrap()),
+            ..Config::default()
         }
+    }
 
-        println!("\n[Output]");
-        println!("\tFile name: {}", output_file_name);
-        println!("\tProcessing time: {:?} sec", now.elapsed().as_secs_f64());
-        println!("\n===== End =====");
+    fn delays(animation: &Animation) -> Vec<u32> {
+        animation.frames.iter().map(|frame| frame.delay_ms).collect()
     }
 
-    Ok(())
+    #[test]
+    fn animated_gif_converts_to_animated_webp() {
+        let output = convert_bytes(&animated_gif(), &Config::default(), &FileType::WEBP).unwrap();
+
+        assert_eq!(output.file_type, FileType::WEBP);
+        assert!(webp_compressor::is_animated(&output.data));
+
+        let animation = webp_compressor::decode_animation(&output.data).unwrap();
+        assert_eq!(delays(&animation), vec![50, 200]);
+        assert_eq!(animation.loop_count, 3);
+        // NOTE: Lossy WebP shifts the colors slightly
+        let pixel = animation.frames[1].image.to_rgba8().get_pixel(0, 0).0;
+        assert!(pixel[2] > 240 && pixel[0] < 16, "{:?}", pixel);
+    }
+
+    #[test]
+    fn webp_loop_count_overrides_gif_loop_count() {
+        let mut config = Config::default();
+        config.webp.as_mut().unwrap().loop_count = Some(0);
+
+        let output = convert_bytes(&animated_gif(), &config, &FileType::WEBP).unwrap();
+
+        assert_eq!(webp_compressor::decode_animation(&output.data).unwrap().loop_count, 0);
+    }
+
+    #[test]
+    fn animated_gif_converts_to_apng() {
+        let output = convert_bytes(&animated_gif(), &Config::default(), &FileType::PNG).unwrap();
+
+        assert_eq!(output.file_type, FileType::PNG);
+        assert!(file_type::is_apng(&output.data));
+
+        let animation = png_compressor::decode_animation(&output.data).unwrap();
+        assert_eq!(delays(&animation), vec![50, 200]);
+        assert_eq!(animation.loop_count, 3);
+        assert_eq!(animation.frames[0].image.to_rgba8().get_pixel(0, 0), &Rgba([255, 0, 0, 255]));
+    }
+
+    #[test]
+    fn animated_gif_to_avif_is_rejected() {
+        let error = convert_bytes(&animated_gif(), &Config::default(), &FileType::AVIF).unwrap_err();
+
+        assert!(error.to_string().contains("Animated AVIF output is not supported"));
+    }
+
+    #[test]
+    fn min_ssim_retries_with_higher_quality() {
+        let config = jpeg_settings(r#"{"quality": 5, "min_ssim": 0.99}"#);
+        let processed = encode_best(&config, &FileType::PNG, &FileType::JPEG, &noisy_png(), false).unwrap();
+
+        let quality = processed.quality.unwrap();
+        assert!(quality > 5 && quality < 100, "{}", quality);
+        assert!(processed.metrics.unwrap().ssim >= 0.99);
+    }
+
+    #[test]
+    fn min_ssim_falls_back_to_highest_quality() {
+        let config = jpeg_settings(r#"{"quality": 50, "min_ssim": 1.0}"#);
+        let processed = encode_best(&config, &FileType::PNG, &FileType::JPEG, &noisy_png(), false).unwrap();
+
+        assert_eq!(processed.quality, Some(100));
+        assert!(processed.metrics.unwrap().ssim < 1.0);
+    }
+
+    #[test]
+    fn min_ssim_falls_back_to_lossless() {
+        let mut config = Config::default();
+        let webp_config = config.webp.as_mut().unwrap();
+        webp_config.quality = 50;
+        webp_config.min_ssim = Some(1.0);
+
+        let processed = encode_best(&config, &FileType::PNG, &FileType::WEBP, &noisy_png(), false).unwrap();
+
+        assert_eq!(processed.quality, None);
+        assert_eq!(processed.metrics.unwrap().ssim, 1.0);
+    }
+
+    #[test]
+    fn metrics_compare_against_transformed_pixels() {
+        let config = jpeg_settings(
+            r#"{"quality": 90, "min_ssim": 0.9, "ops": [{"rotate": 90}, {"crop": {"x": 0, "y": 0, "width": 64, "height": 48}}], "filters": {"grayscale": true}}"#,
+        );
+        let processed = encode_best(&config, &FileType::PNG, &FileType::JPEG, &smooth_png(), false).unwrap();
+
+        // NOTE: No retry is needed, as the rotation and filters are not counted as loss
+        assert_eq!(processed.quality, None);
+        assert!(processed.metrics.unwrap().ssim > 0.95);
+    }
+
+    #[test]
+    fn metrics_are_skipped_unless_needed() {
+        let config = jpeg_settings(r#"{"quality": 80}"#);
+
+        assert!(encode_best(&config, &FileType::PNG, &FileType::JPEG, &smooth_png(), false).unwrap().metrics.is_none());
+        assert!(encode_best(&config, &FileType::PNG, &FileType::JPEG, &smooth_png(), true).unwrap().metrics.is_some());
+    }
+
+    /// 品質 30 の JPEG と、その SOI の直後に EXIF（APP1）を挿入した JPEG
+    fn jpeg_with_exif() -> (Vec<u8>, Vec<u8>) {
+        let low_quality = crate::config_json::JpegConfig {
+            quality: 30,
+            ..crate::config_json::JpegConfig::default()
+        };
+        let image = image::load_from_memory(&noisy_png()).unwrap();
+        let data = jpeg_compressor::compress_image(Some(&low_quality), image).unwrap();
+        let mut input = data[..2].to_vec();
+        // NOTE: An empty big-endian IFD is the smallest EXIF that parses
+        input.extend_from_slice(&[0xff, 0xe1, 0x00, 0x16]);
+        input.extend_from_slice(b"Exif\0\0MM\0*\0\0\0\x08\0\0\0\0\0\0");
+        input.extend_from_slice(&data[2..]);
+
+        (data, input)
+    }
+
+    fn contains_exif(data: &[u8]) -> bool {
+        data.windows(4).any(|window| window == b"Exif")
+    }
+
+    /// テストごとの一時ディレクトリ
+    fn temp_dir(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("rs-image-compressor-compressor-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn file_names(dir: &Path) -> Vec<String> {
+        let mut files: Vec<_> = std::fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        files.sort();
+        files
+    }
+
+    #[test]
+    fn kept_original_drops_stripped_metadata() {
+        let (data, input) = jpeg_with_exif();
+
+        let processed = process_data(&jpeg_settings(r#"{"quality": 90}"#), &FileType::JPEG, &FileType::JPEG, &input, false).unwrap();
+        assert!(matches!(processed.status, Status::KeptOriginal));
+        assert_eq!(processed.data, data);
+
+        let processed = process_data(&jpeg_settings(r#"{"quality": 90, "exif": "all"}"#), &FileType::JPEG, &FileType::JPEG, &input, false).unwrap();
+        assert!(matches!(processed.status, Status::KeptOriginal));
+        assert!(contains_exif(&processed.data));
+    }
+
+    #[test]
+    fn in_place_kept_original_drops_stripped_metadata() {
+        let dir = temp_dir("kept");
+        let (data, input) = jpeg_with_exif();
+        let path = dir.join("photo.jpg").to_string_lossy().into_owned();
+        std::fs::write(&path, &input).unwrap();
+
+        let result = compress(&jpeg_settings(r#"{"quality": 90}"#), false, &path, &path, None, &OutputOptions::default()).unwrap();
+
+        assert!(matches!(result.status, Status::KeptOriginal));
+        assert_eq!(std::fs::read(&path).unwrap(), data);
+        assert_eq!(file_names(&dir), ["photo.jpg"]);
+    }
+
+    #[test]
+    fn invalid_output_leaves_the_input_untouched() {
+        let dir = temp_dir("invalid");
+        let path = dir.join("photo.png").to_string_lossy().into_owned();
+        let input = smooth_png();
+        std::fs::write(&path, &input).unwrap();
+
+        for data in [&[][..], b"not an image", &jpeg_with_exif().0] {
+            assert!(replace_input(&path, &path, &FileType::PNG, data, true).is_err());
+        }
+
+        assert_eq!(std::fs::read(&path).unwrap(), input);
+        assert_eq!(file_names(&dir), ["photo.png"]);
+    }
+
+    #[test]
+    fn valid_output_replaces_the_input_with_a_backup() {
+        let dir = temp_dir("replace");
+        let path = dir.join("photo.png").to_string_lossy().into_owned();
+        std::fs::write(&path, smooth_png()).unwrap();
+
+        replace_input(&path, &path, &FileType::PNG, &noisy_png(), true).unwrap();
+
+        assert_eq!(std::fs::read(&path).unwrap(), noisy_png());
+        assert_eq!(std::fs::read(format!("{}.bak", path)).unwrap(), smooth_png());
+        assert_eq!(file_names(&dir), ["photo.png", "photo.png.bak"]);
+    }
+
+    #[test]
+    fn unsupported_variant_format_writes_nothing() {
+        let dir = temp_dir("responsive");
+        let input_path = dir.join("photo.png").to_string_lossy().into_owned();
+        std::fs::write(&input_path, smooth_png()).unwrap();
+
+        let config: Config = serde_json::from_str(
+            r#"{"responsive": {"variants": [{"width": 32, "formats": ["png", "webp"]}, {"width": 16, "formats": ["gif"]}]}}"#,
+        )
+        .unwrap();
+        let result = compress(&config, false, &input_path, &input_path, None, &OutputOptions::default());
+
+        assert!(result.is_err());
+        assert_eq!(file_names(&dir), ["photo.png"]);
+    }
+
+    #[test]
+    fn compress_bytes_detects_the_format() {
+        let output = compress_bytes(&smooth_png(), &Config::default()).unwrap();
+
+        assert_eq!(output.file_type, FileType::PNG);
+        assert_eq!(image::load_from_memory_with_format(&output.data, ImageFormat::Png).unwrap().dimensions(), (96, 64));
+        // NOTE: The in-memory API measures the metrics of lossy output
+        let output = convert_bytes(&smooth_png(), &Config::default(), &FileType::JPEG).unwrap();
+        assert_eq!(output.file_type, FileType::JPEG);
+        assert!(output.metrics.is_some());
+    }
+
+    #[test]
+    fn compress_bytes_follows_convert_to() {
+        let config = Config {
+            gif: Some(crate::config_json::GifConfig {
+                convert_to: Some("webp".to_string()),
+                ..crate::config_json::GifConfig::default()
+            }),
+            ..Config::default()
+        };
+
+        let output = compress_bytes(&animated_gif(), &config).unwrap();
+        assert_eq!(output.file_type, FileType::WEBP);
+        assert_eq!(detect_from_bytes(&output.data), Some(FileType::WEBP));
+
+        // NOTE: An explicit format wins over convert_to
+        let output = convert_bytes(&animated_gif(), &config, &FileType::GIF).unwrap();
+        assert_eq!(output.file_type, FileType::GIF);
+        assert_eq!(detect_from_bytes(&output.data), Some(FileType::GIF));
+    }
+
+    #[test]
+    fn bytes_api_rejects_unknown_and_unsupported_inputs() {
+        let error = compress_bytes(b"not an image", &Config::default()).unwrap_err();
+        assert!(matches!(error.downcast_ref::<CompressorError>(), Some(CompressorError::UnknownFileFormat)));
+
+        for output_type in [FileType::GIF, FileType::BMP, FileType::PDF] {
+            let error = convert_bytes(&smooth_png(), &Config::default(), &output_type).unwrap_err();
+            assert!(
+                matches!(error.downcast_ref::<CompressorError>(), Some(CompressorError::UnsupportedConversion(_))),
+                "{:?}",
+                output_type
+            );
+        }
+    }
+
+    #[test]
+    fn preserve_attributes_copies_the_input_times() {
+        let dir = temp_dir("attributes");
+        let input_path = dir.join("photo.png").to_string_lossy().into_owned();
+        let output_path = dir.join("photo.webp").to_string_lossy().into_owned();
+        std::fs::write(&input_path, smooth_png()).unwrap();
+        let modified = filetime::FileTime::from_unix_time(1_300_000_000, 0);
+        filetime::set_file_mtime(&input_path, modified).unwrap();
+
+        let options = OutputOptions {
+            preserve_attributes: true,
+            ..OutputOptions::default()
+        };
+        compress(&Config::default(), false, &input_path, &output_path, None, &options).unwrap();
+
+        let metadata = std::fs::metadata(&output_path).unwrap();
+        assert_eq!(filetime::FileTime::from_last_modification_time(&metadata), modified);
+
+        // NOTE: Without the option, the output gets the time it was written
+        compress(&Config::default(), false, &input_path, &output_path, None, &OutputOptions::default()).unwrap();
+        let metadata = std::fs::metadata(&output_path).unwrap();
+        assert_ne!(filetime::FileTime::from_last_modification_time(&metadata), modified);
+    }
+
+    /// 4x2 と 3x3 の2ページの TIFF
+    fn two_page_tiff() -> Vec<u8> {
+        let mut bytes = Cursor::new(Vec::new());
+        let mut encoder = tiff::encoder::TiffEncoder::new(&mut bytes).unwrap();
+        encoder.write_image::<tiff::encoder::colortype::Gray8>(4, 2, &[0; 8]).unwrap();
+        encoder.write_image::<tiff::encoder::colortype::RGB8>(3, 3, &[255; 27]).unwrap();
+        bytes.into_inner()
+    }
+
+    fn tiff_settings(json: &str) -> Config {
+        let mut tiff = serde_json::to_value(crate::config_json::TiffConfig::default()).unwrap();
+        for (key, value) in serde_json::from_str::<serde_json::Map<_, _>>(json).unwrap() {
+            tiff[key] = value;
+        }
+
+        Config {
+            tiff: Some(serde_json::from_value(tiff).unwrap()),
+            ..Config::default()
+        }
+    }
+
+    #[test]
+    fn page_path_numbers_the_stem() {
+        assert_eq!(page_path("out/scan.png", 2), "out/scan-2.png");
+        assert_eq!(page_path("out/scan", 10), "out/scan-10");
+    }
+
+    #[test]
+    fn page_outputs_split_all_pages() {
+        let input = two_page_tiff();
+
+        assert_eq!(
+            page_outputs(&tiff_settings(r#"{"all_pages": true}"#), &FileType::TIFF, "out/scan.png", &input).unwrap(),
+            vec![(Some(1), "out/scan-1.png".to_string()), (Some(2), "out/scan-2.png".to_string())]
+        );
+        assert_eq!(
+            page_outputs(&tiff_settings(r#"{"page": 2}"#), &FileType::TIFF, "out/scan.png", &input).unwrap(),
+            vec![(Some(2), "out/scan.png".to_string())]
+        );
+        assert!(page_outputs(&tiff_settings(r#"{"all_pages": true}"#), &FileType::TIFF, STDIO_PATH, &input).is_err());
+        // NOTE: The TIFF settings do not apply to other formats
+        assert_eq!(
+            page_outputs(&tiff_settings(r#"{"all_pages": true}"#), &FileType::PNG, "out/a.png", &input).unwrap(),
+            vec![(None, "out/a.png".to_string())]
+        );
+    }
+
+    #[test]
+    fn selected_tiff_page_is_converted() {
+        let output = convert_bytes(&two_page_tiff(), &tiff_settings(r#"{"page": 2}"#), &FileType::PNG).unwrap();
+        let image = image::load_from_memory(&output.data).unwrap();
+
+        assert_eq!(image.dimensions(), (3, 3));
+        assert!(convert_bytes(&two_page_tiff(), &tiff_settings(r#"{"page": 3}"#), &FileType::PNG).is_err());
+        // NOTE: Without a page, the first page is converted
+        let output = convert_bytes(&two_page_tiff(), &Config::default(), &FileType::PNG).unwrap();
+        assert_eq!(image::load_from_memory(&output.data).unwrap().dimensions(), (4, 2));
+    }
 }
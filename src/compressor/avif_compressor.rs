@@ -0,0 +1,110 @@
+use crate::compressor::heif_compressor;
+use crate::config_json::AvifConfig;
+use crate::error::CompressorError;
+use crate::imaging::{filter, transform};
+use anyhow::anyhow;
+use image::DynamicImage;
+use libheif_rs::{CompressionFormat, EncoderParameterValue, EncoderQuality, HeifContext, LibHeif};
+
+pub fn compress(config: Option<&AvifConfig>, input: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let dynamic_image = heif_compressor::decode_image(input)?;
+
+    compress_image(config, dynamic_image)
+}
+
+pub fn compress_image(
+    config: Option<&AvifConfig>,
+    mut dynamic_image: DynamicImage,
+) -> anyhow::Result<Vec<u8>> {
+    let default_config = AvifConfig::default();
+    let (quality, alpha_quality, speed, bit_depth, chroma_subsampling, size, ops, filters) = match config {
+        Some(config) => (
+            config.quality,
+            config.alpha_quality,
+            config.speed,
+            config.bit_depth,
+            config.chroma_subsampling.as_ref(),
+            config.size.as_ref(),
+            config.ops.as_deref(),
+            config.filters.as_ref(),
+        ),
+        None => (
+            default_config.quality,
+            default_config.alpha_quality,
+            default_config.speed,
+            default_config.bit_depth,
+            default_config.chroma_subsampling.as_ref(),
+            default_config.size.as_ref(),
+            default_config.ops.as_deref(),
+            default_config.filters.as_ref(),
+        ),
+    };
+
+    if let Some(ops) = ops {
+        dynamic_image = transform::apply_ops(dynamic_image, ops)?;
+    }
+
+    if let Some(size_config) = size {
+        dynamic_image = transform::resize_image(&dynamic_image, size_config)?;
+    }
+
+    if let Some(filters_config) = filters {
+        dynamic_image = filter::apply_filters(dynamic_image, filters_config)?;
+    }
+
+    let image = heif_compressor::to_heif_image(&dynamic_image, bit_depth.unwrap_or(8))?;
+
+    let lib_heif = LibHeif::new();
+    let mut encoder = lib_heif.encoder_for_format(CompressionFormat::Av1)?;
+
+    encoder.set_quality(EncoderQuality::Lossy(quality))?;
+
+    if let Some(alpha_quality) = alpha_quality {
+        encoder.set_parameter_value(
+            "alpha-quality",
+            EncoderParameterValue::Int(alpha_quality as i32),
+        )?;
+    }
+
+    if let Some(speed) = speed {
+        encoder.set_parameter_value("speed", EncoderParameterValue::Int(speed as i32))?;
+    }
+
+    if let Some(chroma_subsampling) = chroma_subsampling {
+        encoder.set_parameter_value(
+            "chroma",
+            EncoderParameterValue::String(chroma_subsampling.clone()),
+        )?;
+    }
+
+    let mut encode_context = HeifContext::new()?;
+    encode_context.encode_image(&image, &mut encoder, None)?;
+
+    let bytes = encode_context
+        .write_to_bytes()
+        .map_err(|e| anyhow!(CompressorError::AvifCompressError(e.to_string())))?;
+
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file_type::{detect_from_bytes, FileType};
+    use image::{Rgba, RgbaImage};
+
+    #[test]
+    fn encodes_resized_avif() {
+        let config = AvifConfig {
+            size: serde_json::from_str(r#"{"width": 16, "filter": "triangle", "mode": "width-only"}"#).unwrap(),
+            speed: Some(10),
+            ..AvifConfig::default()
+        };
+        let image = DynamicImage::ImageRgba8(RgbaImage::from_fn(64, 32, |x, y| Rgba([x as u8 * 4, y as u8 * 8, 64, 255])));
+
+        let data = compress_image(Some(&config), image).unwrap();
+
+        assert_eq!(detect_from_bytes(&data), Some(FileType::AVIF));
+        assert_eq!(heif_compressor::dimensions(&data).unwrap(), (16, 8));
+    }
+}
@@ -1,7 +1,6 @@
 use crate::config_json::GifConfig;
 use crate::error::CompressorError;
-use crate::imaging::transform;
-use crate::io::file::read_file_bytes;
+use crate::imaging::animation::{Animation, Frame};
 use anyhow::{anyhow, Result};
 use gifski::collector::ImgVec;
 use gifski::{progress::NoProgress, Repeat, Settings};
@@ -10,13 +9,19 @@ use image::{AnimationDecoder, DynamicImage, RgbaImage};
 use rgb::RGBA8;
 use std::io::{BufWriter, Cursor};
 
-pub fn compress(config: Option<&GifConfig>, input_path: &String) -> Result<Vec<u8>> {
+const NETSCAPE_EXTENSION: &[u8] = b"NETSCAPE2.0";
+const MIN_DELAY_MS: u32 = 10;
+const DEFAULT_DELAY_MS: u32 = 100;
+
+pub fn compress(config: Option<&GifConfig>, input: &[u8]) -> Result<Vec<u8>> {
     // 設定値の取得
     let default_config = GifConfig::default();
-    let (quality, size, fast, loop_speed, loop_count) = match config {
+    let (quality, size, ops, filters, fast, loop_speed, loop_count) = match config {
         Some(config) => (
             config.quality,
             config.size.as_ref(),
+            config.ops.as_deref(),
+            config.filters.as_ref(),
             config.fast,
             config.loop_speed,
             config.loop_count,
@@ -24,34 +29,30 @@ pub fn compress(config: Option<&GifConfig>, input_path: &String) -> Result<Vec<u
         None => (
             default_config.quality,
             default_config.size.as_ref(),
+            default_config.ops.as_deref(),
+            default_config.filters.as_ref(),
             default_config.fast,
             default_config.loop_speed,
             default_config.loop_count,
         ),
     };
 
-    // ファイルの内容を読み込み
-    let buffer = read_file_bytes(input_path)?;
-
     // GIFファイルを解析
-    let reader = Cursor::new(&buffer);
-    let decoder = GifDecoder::new(reader).map_err(|e| anyhow!(CompressorError::ImageDecodeError(e.into())))?;
-    let frames = decoder
-        .into_frames()
-        .collect_frames()
-        .map_err(|e| anyhow!(CompressorError::ImageDecodeError(e.into())))?;
+    let mut animation = decode_animation(input)?;
 
-    if frames.is_empty() {
-        return Err(anyhow!(CompressorError::GifCompressError(
-            "No frames found in GIF file".to_string()
-        )));
+    // NOTE: Animation resolves trim and content-aware crops on the first frame,
+    //       so every frame is cropped to the same region
+    if let Some(ops) = ops {
+        animation = animation.apply_ops(ops)?;
     }
 
-    if frames.len() > 1000 {
-        println!(
-            "Warning: Large number of frames ({}). Processing may take time.",
-            frames.len()
-        );
+    // リサイズが必要な場合
+    if let Some(size_config) = size {
+        animation = animation.resize(size_config)?;
+    }
+
+    if let Some(filters_config) = filters {
+        animation = animation.apply_filters(filters_config)?;
     }
 
     // gifski の設定
@@ -66,14 +67,9 @@ pub fn compress(config: Option<&GifConfig>, input_path: &String) -> Result<Vec<u
             None => Repeat::Infinite,
         },
         fast: fast.unwrap_or_else(|| false),
-        width: match size {
-            Some(size) => Some(size.width),
-            None => None,
-        },
-        height: match size {
-            Some(size) => Some(size.height),
-            None => None,
-        },
+        // NOTE: Frames are resized by Animation::resize, so gifski must not fit them again
+        width: None,
+        height: None,
     };
 
     // 結果を保存するバッファ
@@ -98,24 +94,15 @@ pub fn compress(config: Option<&GifConfig>, input_path: &String) -> Result<Vec<u
     let mut current_presentation_timestamp = 0.0;
 
     // 各フレームの処理と収集
-    for i in 0..frames.len() {
-        let frame = &frames[i];
-
-        if frame.buffer().is_empty() {
-            println!("Skipping empty frame at index {}", i);
+    for (i, frame) in animation.frames.iter().enumerate() {
+        if frame.image.width() == 0 || frame.image.height() == 0 {
+            eprintln!("Skipping empty frame at index {}", i);
             continue;
         }
 
         {
-            let mut dynamic_image = DynamicImage::from(frame.buffer().clone());
-
-            // リサイズが必要な場合
-            if let Some(size_config) = size {
-                dynamic_image = transform::resize_image(&dynamic_image, size_config);
-            }
-
             // RGBA画像を取得
-            let rgba_image = dynamic_image.to_rgba8();
+            let rgba_image = frame.image.to_rgba8();
 
             // フレームデータの準備
             let frame_data = prepare_frame_data(&rgba_image);
@@ -124,7 +111,7 @@ pub fn compress(config: Option<&GifConfig>, input_path: &String) -> Result<Vec<u
                 Some(loop_speed) => loop_speed,
                 None => {
                     // GIFの遅延時間を正確に解釈する
-                    let delay_ms = frame.delay().numer_denom_ms().0 as f64;
+                    let delay_ms = frame.delay_ms as f64;
                     (delay_ms / 1000.0).max(0.1)
                 }
             };
@@ -133,7 +120,7 @@ pub fn compress(config: Option<&GifConfig>, input_path: &String) -> Result<Vec<u
             // println!(
             //     "Adding frame {}/{}: width={}, height={}, delay_ms={}, frame_delay={}",
             //     i,
-            //     frames.len() - 1,
+            //     animation.frames.len() - 1,
             //     rgba_image.width(),
             //     rgba_image.height(),
             //     delay_ms,
@@ -179,6 +166,70 @@ pub fn compress(config: Option<&GifConfig>, input_path: &String) -> Result<Vec<u
     }
 }
 
+/// GIF をアニメーションとして読み込み（フレームはキャンバス全体に合成済み）
+pub fn decode_animation(input: &[u8]) -> Result<Animation> {
+    let frames = decode_frames(input)?
+        .into_iter()
+        .map(|frame| {
+            let (numer, denom) = frame.delay().numer_denom_ms();
+            let delay_ms = numer / denom.max(1);
+
+            Frame {
+                image: DynamicImage::ImageRgba8(frame.into_buffer()),
+                // NOTE: Browsers play delays of 10ms or less at 100ms
+                delay_ms: if delay_ms <= MIN_DELAY_MS {
+                    DEFAULT_DELAY_MS
+                } else {
+                    delay_ms
+                },
+            }
+        })
+        .collect();
+
+    Ok(Animation {
+        frames,
+        loop_count: loop_count(input),
+    })
+}
+
+/// GIF の全フレームをデコード
+fn decode_frames(input: &[u8]) -> Result<Vec<image::Frame>> {
+    let reader = Cursor::new(input);
+    let decoder = GifDecoder::new(reader).map_err(|e| anyhow!(CompressorError::ImageDecodeError(e)))?;
+    let frames = decoder
+        .into_frames()
+        .collect_frames()
+        .map_err(|e| anyhow!(CompressorError::ImageDecodeError(e)))?;
+
+    if frames.is_empty() {
+        return Err(anyhow!(CompressorError::GifCompressError(
+            "No frames found in GIF file".to_string()
+        )));
+    }
+
+    if frames.len() > 1000 {
+        eprintln!(
+            "Warning: Large number of frames ({}). Processing may take time.",
+            frames.len()
+        );
+    }
+
+    Ok(frames)
+}
+
+/// NETSCAPE2.0 拡張から再生回数を取得（0: 無限ループ）
+///
+/// 拡張のループ回数は繰り返し回数のため、再生回数は +1（拡張がない場合は1回）
+fn loop_count(input: &[u8]) -> u32 {
+    input
+        .windows(NETSCAPE_EXTENSION.len() + 4)
+        .find(|window| window.starts_with(NETSCAPE_EXTENSION) && window[11] == 3 && window[12] == 1)
+        .map_or(1, |window| match u16::from_le_bytes([window[13], window[14]]) {
+            0 => 0,
+            count => count as u32 + 1,
+        })
+}
+
 /// RGBA画像を gifski に適した形式に変換
 fn prepare_frame_data(rgba_image: &RgbaImage) -> Vec<RGBA8> {
     let width = rgba_image.width() as usize;
@@ -192,3 +243,67 @@ fn prepare_frame_data(rgba_image: &RgbaImage) -> Vec<RGBA8> {
 
     frame_data
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::codecs::gif::{GifEncoder, Repeat as GifRepeat};
+    use image::{Delay, Rgba};
+
+    /// 赤・青の2フレーム（表示時間 50ms, 200ms）の GIF
+    fn animated_gif(repeat: Option<GifRepeat>) -> Vec<u8> {
+        let frames = [(Rgba([255, 0, 0, 255]), 50), (Rgba([0, 0, 255, 255]), 200)].map(|(color, delay_ms)| {
+            image::Frame::from_parts(
+                RgbaImage::from_pixel(4, 4, color),
+                0,
+                0,
+                Delay::from_numer_denom_ms(delay_ms, 1),
+            )
+        });
+
+        let mut bytes = Vec::new();
+        {
+            let mut encoder = GifEncoder::new(&mut bytes);
+            if let Some(repeat) = repeat {
+                encoder.set_repeat(repeat).unwrap();
+            }
+            encoder.encode_frames(frames).unwrap();
+        }
+        bytes
+    }
+
+    #[test]
+    fn loop_count_maps_netscape_repetitions_to_plays() {
+        assert_eq!(loop_count(&animated_gif(Some(GifRepeat::Infinite))), 0);
+        assert_eq!(loop_count(&animated_gif(Some(GifRepeat::Finite(2)))), 3);
+        assert_eq!(loop_count(&animated_gif(None)), 1);
+    }
+
+    #[test]
+    fn decode_animation_keeps_frames_and_delays() {
+        let animation = decode_animation(&animated_gif(Some(GifRepeat::Infinite))).unwrap();
+
+        assert_eq!(animation.loop_count, 0);
+        assert_eq!(
+            animation.frames.iter().map(|frame| frame.delay_ms).collect::<Vec<_>>(),
+            vec![50, 200]
+        );
+        assert_eq!(animation.frames[1].image.to_rgba8().get_pixel(0, 0), &Rgba([0, 0, 255, 255]));
+    }
+
+    #[test]
+    fn decode_animation_raises_short_delays() {
+        let frame = image::Frame::from_parts(
+            RgbaImage::from_pixel(1, 1, Rgba([0, 0, 0, 255])),
+            0,
+            0,
+            Delay::from_numer_denom_ms(10, 1),
+        );
+        let mut bytes = Vec::new();
+        GifEncoder::new(&mut bytes).encode_frames([frame.clone(), frame]).unwrap();
+
+        let animation = decode_animation(&bytes).unwrap();
+
+        assert!(animation.frames.iter().all(|frame| frame.delay_ms == DEFAULT_DELAY_MS));
+    }
+}
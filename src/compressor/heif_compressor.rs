@@ -1,40 +1,95 @@
-use crate::config_json::HeifConfig;
+use crate::config_json::{HeifConfig, SizeConfig};
 use crate::error::CompressorError;
+use crate::imaging::{filter, transform};
 use anyhow::anyhow;
+use image::imageops::FilterType;
+use image::{DynamicImage, RgbaImage};
 use libheif_rs::{
-    ColorSpace, CompressionFormat, EncoderQuality, HeifContext, LibHeif, RgbChroma, StreamReader,
+    Channel, ColorSpace, CompressionFormat, EncoderQuality, HeifContext, Image, LibHeif, RgbChroma,
 };
-use std::fs::File;
-use std::io::{BufReader, Seek, SeekFrom};
 
-pub fn compress(config: Option<&HeifConfig>, input_path: &String) -> anyhow::Result<Vec<u8>> {
-    let default_config = HeifConfig::default();
-    let (quality, size) = match config {
-        Some(config) => (config.quality, config.size.as_ref()),
-        None => (default_config.quality, default_config.size.as_ref()),
-    };
+pub fn compress(config: Option<&HeifConfig>, input: &[u8]) -> anyhow::Result<Vec<u8>> {
+    // NOTE: ops and filters are applied to the decoded pixels, so re-encode from a DynamicImage
+    if config.is_some_and(|config| config.ops.is_some() || config.filters.is_some()) {
+        return compress_image(config, decode_image(input)?);
+    }
 
-    let input_file = File::open(input_path)
-        .map_err(|e| anyhow!(CompressorError::IoError(e)))?;
+    let ctx = HeifContext::read_from_bytes(input)?;
+    let handle = ctx.primary_image_handle()?;
 
-    let mut buf_reader = BufReader::new(input_file);
-    let total_size = buf_reader.seek(SeekFrom::End(0))?;
-    buf_reader.rewind()?;
-    let stream_reader = StreamReader::new(buf_reader, total_size);
+    let lib_heif = LibHeif::new();
+
+    let image = lib_heif.decode(&handle, ColorSpace::Rgb(RgbChroma::Rgba), None)?;
+    let size = config.and_then(|config| config.size.as_ref());
 
-    let ctx = HeifContext::read_from_reader(Box::new(stream_reader))?;
+    encode(&lib_heif, config, size, image)
+}
+
+pub fn decode_image(input: &[u8]) -> anyhow::Result<DynamicImage> {
+    let ctx = HeifContext::read_from_bytes(input)?;
     let handle = ctx.primary_image_handle()?;
 
     let lib_heif = LibHeif::new();
 
-    let mut image = lib_heif.decode(&handle, ColorSpace::Rgb(RgbChroma::Rgba), None)?;
+    // NOTE: libheif applies the irot/imir transformations while decoding
+    let image = lib_heif.decode(&handle, ColorSpace::Rgb(RgbChroma::Rgba), None)?;
+
+    to_dynamic_image(&image)
+}
+
+/// デコードせずに幅と高さを取得
+pub fn dimensions(input: &[u8]) -> anyhow::Result<(u32, u32)> {
+    let ctx = HeifContext::read_from_bytes(input)?;
+    let handle = ctx.primary_image_handle()?;
+
+    Ok((handle.width(), handle.height()))
+}
+
+pub fn compress_image(
+    config: Option<&HeifConfig>,
+    mut dynamic_image: DynamicImage,
+) -> anyhow::Result<Vec<u8>> {
+    if let Some(ops) = config.and_then(|config| config.ops.as_deref()) {
+        dynamic_image = transform::apply_ops(dynamic_image, ops)?;
+    }
+
+    let mut size = config.and_then(|config| config.size.as_ref());
+
+    // NOTE: Filters run after the resize, so scale the pixels here instead of in libheif
+    if let Some(filters_config) = config.and_then(|config| config.filters.as_ref()) {
+        if let Some(size_config) = size.take() {
+            dynamic_image =
+                dynamic_image.resize_exact(size_config.width, size_config.height, FilterType::Lanczos3);
+        }
+        dynamic_image = filter::apply_filters(dynamic_image, filters_config)?;
+    }
+
+    let lib_heif = LibHeif::new();
+
+    let image = to_heif_image(&dynamic_image, 8)?;
+
+    encode(&lib_heif, config, size, image)
+}
+
+/// size を指定した場合は libheif で拡大・縮小してからエンコード
+fn encode(
+    lib_heif: &LibHeif,
+    config: Option<&HeifConfig>,
+    size: Option<&SizeConfig>,
+    mut image: Image,
+) -> anyhow::Result<Vec<u8>> {
+    let default_config = HeifConfig::default();
+    let quality = match config {
+        Some(config) => config.quality,
+        None => default_config.quality,
+    };
 
     if let Some(size) = size {
         image = image.scale(size.width, size.height, None)?;
     }
 
     let mut encoder = lib_heif.encoder_for_format(CompressionFormat::Hevc)?;
-    
+
     if let Some(quality) = quality {
         encoder.set_quality(EncoderQuality::Lossy(quality))?;
     }
@@ -48,3 +103,78 @@ pub fn compress(config: Option<&HeifConfig>, input_path: &String) -> anyhow::Res
 
     Ok(bytes)
 }
+
+/// DynamicImage を libheif のインターリーブ RGBA 画像に変換（bit_depth: 8, 10, 12）
+pub(crate) fn to_heif_image(dynamic_image: &DynamicImage, bit_depth: u8) -> anyhow::Result<Image> {
+    if ![8, 10, 12].contains(&bit_depth) {
+        return Err(anyhow!(CompressorError::ConfigError(format!(
+            "bit_depth must be 8, 10 or 12 (got {})",
+            bit_depth
+        ))));
+    }
+
+    let (width, height) = (dynamic_image.width(), dynamic_image.height());
+
+    if bit_depth == 8 {
+        let rgba_image = dynamic_image.to_rgba8();
+
+        let mut image = Image::new(width, height, ColorSpace::Rgb(RgbChroma::Rgba))?;
+        image.create_plane(Channel::Interleaved, width, height, 8)?;
+
+        let planes = image.planes_mut();
+        let plane = planes
+            .interleaved
+            .ok_or_else(|| anyhow!(CompressorError::HeifCompressError("No interleaved plane".to_string())))?;
+
+        let row_bytes = width as usize * 4;
+        for (y, row) in rgba_image.as_raw().chunks_exact(row_bytes).enumerate() {
+            let offset = y * plane.stride;
+            plane.data[offset..offset + row_bytes].copy_from_slice(row);
+        }
+
+        return Ok(image);
+    }
+
+    // NOTE: High bit depth is stored as little endian u16 per channel
+    let rgba_image = dynamic_image.to_rgba16();
+    let shift = 16 - bit_depth as u32;
+
+    let mut image = Image::new(width, height, ColorSpace::Rgb(RgbChroma::HdrRgbaLe))?;
+    image.create_plane(Channel::Interleaved, width, height, bit_depth)?;
+
+    let planes = image.planes_mut();
+    let plane = planes
+        .interleaved
+        .ok_or_else(|| anyhow!(CompressorError::HeifCompressError("No interleaved plane".to_string())))?;
+
+    let row_samples = width as usize * 4;
+    for (y, row) in rgba_image.as_raw().chunks_exact(row_samples).enumerate() {
+        let offset = y * plane.stride;
+        for (x, sample) in row.iter().enumerate() {
+            let value = (sample >> shift).to_le_bytes();
+            plane.data[offset + x * 2..offset + x * 2 + 2].copy_from_slice(&value);
+        }
+    }
+
+    Ok(image)
+}
+
+/// libheif のインターリーブ RGBA 画像を DynamicImage に変換
+fn to_dynamic_image(image: &Image) -> anyhow::Result<DynamicImage> {
+    let planes = image.planes();
+    let plane = planes
+        .interleaved
+        .ok_or_else(|| anyhow!(CompressorError::ImageFormatError("No interleaved plane".to_string())))?;
+
+    let row_bytes = plane.width as usize * 4;
+    let mut buffer = Vec::with_capacity(row_bytes * plane.height as usize);
+    for y in 0..plane.height as usize {
+        let offset = y * plane.stride;
+        buffer.extend_from_slice(&plane.data[offset..offset + row_bytes]);
+    }
+
+    let rgba_image = RgbaImage::from_raw(plane.width, plane.height, buffer)
+        .ok_or_else(|| anyhow!(CompressorError::ImageFormatError("Invalid image buffer".to_string())))?;
+
+    Ok(DynamicImage::ImageRgba8(rgba_image))
+}
@@ -0,0 +1,247 @@
+use crate::compressor::png_compressor;
+use crate::config_json::{IcoConfig, PngConfig, SizeFilterConfig};
+use crate::error::CompressorError;
+use crate::imaging::transform;
+use anyhow::anyhow;
+use image::{imageops, DynamicImage, ImageFormat, RgbaImage};
+use std::io::Cursor;
+
+/// ICONDIR（reserved, type, count）
+const HEADER_SIZE: usize = 6;
+/// ICONDIRENTRY
+const ENTRY_SIZE: usize = 16;
+/// ICO に埋め込める画像の最大サイズ
+const MAX_ICON_SIZE: u32 = 256;
+
+/// ICO に埋め込まれた画像（PNG または BMP）
+struct IconEntry {
+    /// 0: 256
+    width: u8,
+    /// 0: 256
+    height: u8,
+    color_count: u8,
+    planes: u16,
+    bit_count: u16,
+    data: Vec<u8>,
+}
+
+/// 埋め込まれた PNG を oxipng で再圧縮（BMP はそのまま）
+pub fn compress(png_config: Option<&PngConfig>, input: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let entries = parse_entries(input)?
+        .into_iter()
+        .map(|entry| {
+            if !infer::image::is_png(&entry.data) {
+                return Ok(entry);
+            }
+
+            Ok(IconEntry {
+                data: png_compressor::optimize_png(png_config, &entry.data)?,
+                ..entry
+            })
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    Ok(write_ico(&entries))
+}
+
+/// 1つの画像から複数サイズの ICO を作成（各サイズは PNG として埋め込み）
+pub fn compress_image(
+    config: Option<&IcoConfig>,
+    png_config: Option<&PngConfig>,
+    dynamic_image: DynamicImage,
+) -> anyhow::Result<Vec<u8>> {
+    let default_config = IcoConfig::default();
+    let config = config.unwrap_or(&default_config);
+    let sizes = config
+        .sizes
+        .as_deref()
+        .or(default_config.sizes.as_deref())
+        .unwrap_or_default();
+    let filter = config
+        .filter
+        .as_deref()
+        .or(default_config.filter.as_deref())
+        .unwrap_or_default();
+
+    if sizes.is_empty() {
+        return Err(encode_error("No icon sizes specified"));
+    }
+
+    let mut entries = Vec::with_capacity(sizes.len());
+
+    for &size in sizes {
+        if size == 0 || size > MAX_ICON_SIZE {
+            return Err(encode_error(format!(
+                "Icon size must be between 1 and {}: {}",
+                MAX_ICON_SIZE, size
+            )));
+        }
+
+        let icon = DynamicImage::ImageRgba8(icon_image(&dynamic_image, size, filter)?);
+        let mut png_data = Vec::new();
+        icon.write_to(&mut Cursor::new(&mut png_data), ImageFormat::Png)?;
+
+        // NOTE: A size of 256 is stored as 0
+        entries.push(IconEntry {
+            width: size as u8,
+            height: size as u8,
+            color_count: 0,
+            planes: 1,
+            bit_count: 32,
+            data: png_compressor::optimize_png(png_config, &png_data)?,
+        });
+    }
+
+    Ok(write_ico(&entries))
+}
+
+/// 正方形に収まるように縮小し、余白は透過で埋める
+fn icon_image(dynamic_image: &DynamicImage, size: u32, filter: &str) -> anyhow::Result<RgbaImage> {
+    let size_config = SizeFilterConfig {
+        width: Some(size),
+        height: Some(size),
+        filter: filter.to_string(),
+        mode: None,
+        no_upscale: None,
+        gravity: None,
+        focus: None,
+    };
+    let resized = transform::resize_image(dynamic_image, &size_config)?.to_rgba8();
+    let (width, height) = resized.dimensions();
+
+    let mut canvas = RgbaImage::new(size, size);
+    imageops::replace(
+        &mut canvas,
+        &resized,
+        (size.saturating_sub(width) / 2) as i64,
+        (size.saturating_sub(height) / 2) as i64,
+    );
+
+    Ok(canvas)
+}
+
+fn parse_entries(input: &[u8]) -> anyhow::Result<Vec<IconEntry>> {
+    let header = input
+        .get(..HEADER_SIZE)
+        .ok_or_else(|| decode_error("ICO header is truncated"))?;
+    let count = u16::from_le_bytes([header[4], header[5]]) as usize;
+
+    (0..count)
+        .map(|index| {
+            let offset = HEADER_SIZE + index * ENTRY_SIZE;
+            let entry = input
+                .get(offset..offset + ENTRY_SIZE)
+                .ok_or_else(|| decode_error("ICO directory is truncated"))?;
+
+            let size = u32::from_le_bytes([entry[8], entry[9], entry[10], entry[11]]) as usize;
+            let data_offset =
+                u32::from_le_bytes([entry[12], entry[13], entry[14], entry[15]]) as usize;
+            let data = input
+                .get(data_offset..data_offset.saturating_add(size))
+                .ok_or_else(|| decode_error("ICO image data is truncated"))?;
+
+            Ok(IconEntry {
+                width: entry[0],
+                height: entry[1],
+                color_count: entry[2],
+                planes: u16::from_le_bytes([entry[4], entry[5]]),
+                bit_count: u16::from_le_bytes([entry[6], entry[7]]),
+                data: data.to_vec(),
+            })
+        })
+        .collect()
+}
+
+fn write_ico(entries: &[IconEntry]) -> Vec<u8> {
+    let mut output = Vec::new();
+    output.extend_from_slice(&0u16.to_le_bytes());
+    output.extend_from_slice(&1u16.to_le_bytes());
+    output.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+
+    // NOTE: Image data follows the directory in the same order as the entries
+    let mut data_offset = HEADER_SIZE + entries.len() * ENTRY_SIZE;
+
+    for entry in entries {
+        output.extend_from_slice(&[entry.width, entry.height, entry.color_count, 0]);
+        output.extend_from_slice(&entry.planes.to_le_bytes());
+        output.extend_from_slice(&entry.bit_count.to_le_bytes());
+        output.extend_from_slice(&(entry.data.len() as u32).to_le_bytes());
+        output.extend_from_slice(&(data_offset as u32).to_le_bytes());
+        data_offset += entry.data.len();
+    }
+
+    for entry in entries {
+        output.extend_from_slice(&entry.data);
+    }
+
+    output
+}
+
+fn encode_error(e: impl ToString) -> anyhow::Error {
+    anyhow!(CompressorError::IcoCompressError(e.to_string()))
+}
+
+fn decode_error(e: impl ToString) -> anyhow::Error {
+    anyhow!(CompressorError::ImageFormatError(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::GenericImageView;
+
+    #[test]
+    fn write_and_parse_round_trip() {
+        let entries = vec![
+            IconEntry { width: 16, height: 16, color_count: 0, planes: 1, bit_count: 32, data: vec![1, 2, 3] },
+            IconEntry { width: 0, height: 0, color_count: 4, planes: 1, bit_count: 8, data: vec![4, 5, 6, 7, 8] },
+        ];
+        let ico = write_ico(&entries);
+        let parsed = parse_entries(&ico).unwrap();
+
+        assert_eq!(ico.len(), HEADER_SIZE + 2 * ENTRY_SIZE + 8);
+        assert_eq!(parsed.len(), entries.len());
+        for (parsed, entry) in parsed.iter().zip(&entries) {
+            assert_eq!(
+                (parsed.width, parsed.height, parsed.color_count, parsed.planes, parsed.bit_count),
+                (entry.width, entry.height, entry.color_count, entry.planes, entry.bit_count)
+            );
+            assert_eq!(parsed.data, entry.data);
+        }
+    }
+
+    #[test]
+    fn compress_image_writes_each_size() {
+        let config = IcoConfig {
+            sizes: Some(vec![16, 32, 256]),
+            ..IcoConfig::default()
+        };
+        let image = DynamicImage::ImageRgba8(RgbaImage::from_pixel(40, 20, image::Rgba([255, 0, 0, 255])));
+        let ico = compress_image(Some(&config), None, image).unwrap();
+        let entries = parse_entries(&ico).unwrap();
+
+        assert_eq!(entries.iter().map(|entry| entry.width).collect::<Vec<_>>(), [16, 32, 0]);
+        for (entry, size) in entries.iter().zip([16, 32, 256]) {
+            let icon = image::load_from_memory(&entry.data).unwrap();
+            assert_eq!(icon.dimensions(), (size, size));
+            // NOTE: The wide image is centered, so the top and bottom are transparent
+            assert_eq!(icon.to_rgba8().get_pixel(0, 0)[3], 0);
+            assert_eq!(icon.to_rgba8().get_pixel(size / 2, size / 2)[3], 255);
+        }
+
+        // NOTE: Recompressing keeps the directory and the images readable
+        let entries = parse_entries(&compress(None, &ico).unwrap()).unwrap();
+        assert_eq!(entries.len(), 3);
+        assert!(entries.iter().all(|entry| image::load_from_memory(&entry.data).is_ok()));
+        assert!(image::load_from_memory_with_format(&ico, ImageFormat::Ico).is_ok());
+    }
+
+    #[test]
+    fn truncated_ico_is_rejected() {
+        let ico = write_ico(&[IconEntry { width: 16, height: 16, color_count: 0, planes: 1, bit_count: 32, data: vec![0; 8] }]);
+
+        assert!(parse_entries(&ico[..4]).is_err());
+        assert!(parse_entries(&ico[..HEADER_SIZE + 8]).is_err());
+        assert!(parse_entries(&ico[..ico.len() - 1]).is_err());
+    }
+}
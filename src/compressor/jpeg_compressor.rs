@@ -1,16 +1,62 @@
 use crate::config_json::JpegConfig;
 use crate::error::CompressorError;
-use crate::imaging::transform;
-use crate::io::file::read_image_from_file;
+use crate::file_type::FileType;
+use crate::imaging::color::parse_hex_color;
+use crate::imaging::{filter, orientation, transform};
+use crate::io::file::read_image_from_bytes;
 use anyhow::anyhow;
-use image::GenericImageView;
+use image::{DynamicImage, GenericImageView, Rgba};
 use little_exif::exif_tag::ExifTag;
+use little_exif::filetype::FileExtension;
 use little_exif::metadata::Metadata;
 
-pub fn compress(
+pub fn compress(config: Option<&JpegConfig>, input: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let exif_marker: [u8; 6] = [0x45, 0x78, 0x69, 0x66, 0x00, 0x00];
+    let exif_exists = input[..input.len().min(50)]
+        .windows(6)
+        .any(|window| window == exif_marker);
+    let metadata = if exif_exists {
+        Metadata::new_from_vec(&input.to_vec(), FileExtension::JPEG)?
+    } else {
+        Metadata::new()
+    };
+
+    let dynamic_image = orientation::apply(
+        read_image_from_bytes(input)?,
+        orientation::read(&FileType::JPEG, input),
+    );
+
+    let mut data = compress_image(config, dynamic_image)?;
+
+    // NOTE: The orientation is already applied to the pixels, so the tag is reset to avoid rotating twice
+    let has_orientation = metadata.get_tag(&ExifTag::Orientation(vec![])).next().is_some();
+
+    if let Some(jpeg_config) = config {
+        match jpeg_config.exif.as_str() {
+            "all" => {
+                // NOTE: Write "all" exif
+                let mut metadata = metadata;
+                if has_orientation {
+                    metadata.set_tag(ExifTag::Orientation(vec![1]));
+                }
+                metadata.write_to_vec(&mut data, FileExtension::JPEG)?;
+            }
+            "orientation" if has_orientation => {
+                // NOTE: Write "orientation" exif
+                let mut new_metadata = Metadata::new();
+                new_metadata.set_tag(ExifTag::Orientation(vec![1]));
+                new_metadata.write_to_vec(&mut data, FileExtension::JPEG)?;
+            }
+            _ => {}
+        }
+    }
+
+    Ok(data)
+}
+
+pub fn compress_image(
     config: Option<&JpegConfig>,
-    input_path: &String,
-    metadata: &Metadata,
+    mut dynamic_image: DynamicImage,
 ) -> anyhow::Result<Vec<u8>> {
     let default_config = JpegConfig::default();
     let (
@@ -21,6 +67,9 @@ pub fn compress(
         use_scans_in_trellis,
         smoothing_factor,
         size,
+        ops,
+        filters,
+        background,
     ) = match config {
         Some(config) => (
             config.quality,
@@ -30,6 +79,9 @@ pub fn compress(
             config.use_scans_in_trellis,
             config.smoothing_factor,
             config.size.as_ref(),
+            config.ops.as_deref(),
+            config.filters.as_ref(),
+            config.background.as_ref(),
         ),
         None => (
             default_config.quality,
@@ -39,42 +91,28 @@ pub fn compress(
             default_config.use_scans_in_trellis,
             default_config.smoothing_factor,
             default_config.size.as_ref(),
+            default_config.ops.as_deref(),
+            default_config.filters.as_ref(),
+            default_config.background.as_ref(),
         ),
     };
 
-    let mut dynamic_image = read_image_from_file(input_path)?;
-
-    if let Some(jpeg_config) = config {
-        match jpeg_config.exif.as_str() {
-            "none" => {
-                let mut tag_iterator = metadata.get_tag(&ExifTag::Orientation(vec![]));
-                if let Some(exif_tag) = tag_iterator.next() {
-                    match exif_tag {
-                        ExifTag::Orientation(values) => {
-                            if let Some(value) = values.first() {
-                                // NOTE: Rotation image by "orientation" exif
-                                dynamic_image = match value {
-                                    2 => dynamic_image.fliph(),
-                                    3 => dynamic_image.rotate180(),
-                                    4 => dynamic_image.flipv(),
-                                    5 => dynamic_image.rotate90().fliph(),
-                                    6 => dynamic_image.rotate90(),
-                                    7 => dynamic_image.rotate270().fliph(),
-                                    8 => dynamic_image.rotate270(),
-                                    _ => dynamic_image,
-                                }
-                            }
-                        }
-                        _ => {}
-                    }
-                }
-            }
-            _ => {}
-        }
+    if let Some(ops) = ops {
+        dynamic_image = transform::apply_ops(dynamic_image, ops)?;
     }
 
     if let Some(size_config) = size {
-        dynamic_image = transform::resize_image(&dynamic_image, size_config);
+        dynamic_image = transform::resize_image(&dynamic_image, size_config)?;
+    }
+
+    if let Some(filters_config) = filters {
+        dynamic_image = filter::apply_filters(dynamic_image, filters_config)?;
+    }
+
+    // NOTE: JPEG has no alpha channel, so flatten onto the background colour
+    let background = background_color(background)?;
+    if dynamic_image.color().has_alpha() {
+        dynamic_image = transform::flatten(&dynamic_image, background);
     }
 
     let (width, height) = dynamic_image.dimensions();
@@ -84,7 +122,6 @@ pub fn compress(
     let color_space = mozjpeg::ColorSpace::JCS_RGB;
     let mut compress = mozjpeg::Compress::new(color_space);
     compress.set_size(width as usize, height as usize);
-    compress.set_quality(quality as f32);
     if let Some(scan_optimization_mode) = scan_optimization_mode {
         compress.set_scan_optimization_mode(match scan_optimization_mode.as_str() {
             "all_components_together" => mozjpeg::ScanMode::AllComponentsTogether,
@@ -92,6 +129,8 @@ pub fn compress(
             _ => mozjpeg::ScanMode::Auto,
         });
     }
+    // NOTE: set_scan_optimization_mode resets the defaults (quality 75), so the quality is set after it
+    compress.set_quality(quality as f32);
     if progressive_mode {
         compress.set_progressive_mode();
     }
@@ -113,3 +152,136 @@ pub fn compress(
 
     Ok(writer)
 }
+
+/// 画素を変更せずに EXIF・XMP（APP1）、IPTC（APP13）、コメントを削除（元のデータを維持する場合）
+pub fn strip_metadata(config: Option<&JpegConfig>, input: &[u8]) -> Vec<u8> {
+    if config.is_some_and(|config| config.exif == "all") || !input.starts_with(&[0xff, 0xd8]) {
+        return input.to_vec();
+    }
+
+    let mut data = input[..2].to_vec();
+    let mut offset = 2;
+
+    while offset + 4 <= input.len() && input[offset] == 0xff {
+        let marker = input[offset + 1];
+
+        // NOTE: The entropy-coded data follows SOS, so everything from there is copied as is
+        if marker == 0xda {
+            break;
+        }
+
+        let length = u16::from_be_bytes([input[offset + 2], input[offset + 3]]) as usize;
+        let end = offset + 2 + length;
+        if length < 2 || end > input.len() {
+            return input.to_vec();
+        }
+
+        if !matches!(marker, 0xe1 | 0xed | 0xfe) {
+            data.extend_from_slice(&input[offset..end]);
+        }
+        offset = end;
+    }
+
+    data.extend_from_slice(&input[offset..]);
+    data
+}
+
+/// 透過部分を合成する背景色（未指定の場合は白）
+pub fn background_color(background: Option<&String>) -> anyhow::Result<Rgba<u8>> {
+    match background {
+        Some(background) => parse_hex_color(background).ok_or_else(|| {
+            anyhow!(CompressorError::ConfigError(format!("Invalid jpeg background: {}", background)))
+        }),
+        None => Ok(Rgba([255, 255, 255, 255])),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::RgbaImage;
+
+    fn jpeg_config(background: &str) -> JpegConfig {
+        JpegConfig {
+            background: Some(background.to_string()),
+            ..JpegConfig::default()
+        }
+    }
+
+    fn transparent_image() -> DynamicImage {
+        DynamicImage::ImageRgba8(RgbaImage::from_pixel(8, 8, Rgba([0, 0, 0, 0])))
+    }
+
+    #[test]
+    fn transparent_pixels_are_flattened_onto_background() {
+        let data = compress_image(Some(&jpeg_config("#f00")), transparent_image()).unwrap();
+        let pixel = read_image_from_bytes(&data).unwrap().to_rgb8().get_pixel(4, 4).0;
+
+        assert!(pixel[0] > 240 && pixel[1] < 16 && pixel[2] < 16, "{:?}", pixel);
+    }
+
+    #[test]
+    fn invalid_background_is_a_config_error() {
+        let error = compress_image(Some(&jpeg_config("red")), transparent_image()).unwrap_err();
+
+        assert!(matches!(
+            error.downcast_ref::<CompressorError>(),
+            Some(CompressorError::ConfigError(_))
+        ));
+        // NOTE: The background is validated even when the image has no alpha channel
+        let opaque = DynamicImage::ImageRgb8(transparent_image().to_rgb8());
+        assert!(compress_image(Some(&jpeg_config("#12345")), opaque).is_err());
+    }
+
+    #[test]
+    fn quality_is_applied_with_scan_optimization_mode() {
+        let image = DynamicImage::ImageRgb8(image::RgbImage::from_fn(64, 64, |x, y| {
+            let value = (128.0 + 60.0 * (x as f32 / 3.0).sin() * (y as f32 / 5.0).cos()) as u8;
+            image::Rgb([value, value, value])
+        }));
+        let size = |quality: u8| {
+            let config = JpegConfig {
+                quality,
+                ..JpegConfig::default()
+            };
+            compress_image(Some(&config), image.clone()).unwrap().len()
+        };
+
+        assert!(size(30) < size(70));
+        assert!(size(70) < size(100));
+    }
+
+    /// SOI の直後に EXIF（APP1）とコメントを挿入した JPEG
+    fn jpeg_with_metadata() -> Vec<u8> {
+        let data = compress_image(None, transparent_image()).unwrap();
+        let mut input = data[..2].to_vec();
+        input.extend_from_slice(&[0xff, 0xe1, 0x00, 0x0a]);
+        input.extend_from_slice(b"Exif\0\0MM");
+        input.extend_from_slice(&[0xff, 0xfe, 0x00, 0x06]);
+        input.extend_from_slice(b"note");
+        input.extend_from_slice(&data[2..]);
+        input
+    }
+
+    #[test]
+    fn strip_metadata_removes_exif_and_comments() {
+        let input = jpeg_with_metadata();
+        let stripped = strip_metadata(None, &input);
+
+        assert_eq!(stripped, compress_image(None, transparent_image()).unwrap());
+        assert_eq!(strip_metadata(Some(&JpegConfig::default()), &input), stripped);
+    }
+
+    #[test]
+    fn strip_metadata_keeps_exif_when_configured() {
+        let config = JpegConfig {
+            exif: "all".to_string(),
+            ..JpegConfig::default()
+        };
+        let input = jpeg_with_metadata();
+
+        assert_eq!(strip_metadata(Some(&config), &input), input);
+        // NOTE: Data that does not parse as JPEG segments is returned as is
+        assert_eq!(strip_metadata(None, &input[..7]), input[..7].to_vec());
+    }
+}
@@ -0,0 +1,226 @@
+use crate::config_json::{JpegConfig, JxlConfig};
+use crate::error::CompressorError;
+use crate::imaging::{filter, transform};
+use anyhow::anyhow;
+use image::{DynamicImage, GenericImageView};
+use jpegxl_rs::decode::Data;
+use jpegxl_rs::encode::{EncoderFrame, EncoderResult, EncoderSpeed, JxlEncoderBuilder};
+use jpegxl_rs::image::ToDynamic;
+use jpegxl_rs::{decoder_builder, encoder_builder, ThreadsRunner};
+
+pub fn compress(config: Option<&JxlConfig>, input: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let dynamic_image = decode_image(input)?;
+
+    compress_image(config, dynamic_image)
+}
+
+pub fn compress_image(
+    config: Option<&JxlConfig>,
+    mut dynamic_image: DynamicImage,
+) -> anyhow::Result<Vec<u8>> {
+    let default_config = JxlConfig::default();
+    let config = config.unwrap_or(&default_config);
+    let lossless = config.lossless.unwrap_or(false);
+
+    if let Some(ops) = config.ops.as_deref() {
+        dynamic_image = transform::apply_ops(dynamic_image, ops)?;
+    }
+
+    if let Some(size_config) = config.size.as_ref() {
+        dynamic_image = transform::resize_image(&dynamic_image, size_config)?;
+    }
+
+    if let Some(filters_config) = config.filters.as_ref() {
+        dynamic_image = filter::apply_filters(dynamic_image, filters_config)?;
+    }
+
+    let (width, height) = dynamic_image.dimensions();
+    let has_alpha = dynamic_image.color().has_alpha();
+
+    let runner = ThreadsRunner::default();
+    let mut builder = encoder_builder();
+    builder.parallel_runner(&runner).has_alpha(has_alpha).lossless(lossless);
+    // NOTE: Lossless encoding requires the original color profile
+    builder.uses_original_profile(lossless);
+    set_effort(&mut builder, config);
+
+    match (config.quality, config.distance) {
+        (Some(quality), _) => {
+            builder.jpeg_quality(quality as f32);
+        }
+        (None, Some(distance)) => {
+            builder.quality(distance);
+        }
+        (None, None) => {}
+    }
+
+    let mut encoder = builder.build().map_err(encode_error)?;
+
+    let result: EncoderResult<u8> = if has_alpha {
+        let rgba_image = dynamic_image.to_rgba8();
+        let frame = EncoderFrame::new(rgba_image.as_raw()).num_channels(4);
+        encoder.encode_frame(&frame, width, height)
+    } else {
+        let rgb_image = dynamic_image.to_rgb8();
+        encoder.encode(rgb_image.as_raw(), width, height)
+    }
+    .map_err(encode_error)?;
+
+    Ok(result.data)
+}
+
+/// JPEG を画素に戻さずに JPEG XL へ可逆変換（元の JPEG をビット単位で復元可能）
+pub fn transcode_jpeg(config: Option<&JxlConfig>, input: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let default_config = JxlConfig::default();
+    let config = config.unwrap_or(&default_config);
+
+    let runner = ThreadsRunner::default();
+    let mut builder = encoder_builder();
+    // NOTE: The JPEG reconstruction data is stored in a box, so the container format is required
+    builder
+        .parallel_runner(&runner)
+        .use_container(true)
+        .uses_original_profile(true);
+    set_effort(&mut builder, config);
+
+    let mut encoder = builder.build().map_err(encode_error)?;
+    let result = encoder.encode_jpeg(input).map_err(encode_error)?;
+
+    Ok(result.data)
+}
+
+/// JPEG から変換した JPEG XL であれば元の JPEG を復元
+pub fn reconstruct_jpeg(input: &[u8]) -> anyhow::Result<Option<Vec<u8>>> {
+    let runner = ThreadsRunner::default();
+    let decoder = decoder_builder()
+        .parallel_runner(&runner)
+        .build()
+        .map_err(decode_error)?;
+
+    match decoder.reconstruct(input).map_err(decode_error)? {
+        (_, Data::Jpeg(jpeg)) => Ok(Some(jpeg)),
+        (_, Data::Pixels(_)) => Ok(None),
+    }
+}
+
+/// JPEG 入力を可逆変換するかどうか（リサイズ・ops・filters・target_bytes を指定した場合は画素から再エンコード）
+pub fn can_transcode(config: Option<&JxlConfig>) -> bool {
+    let default_config = JxlConfig::default();
+    let config = config.unwrap_or(&default_config);
+
+    // NOTE: The transcoded size does not depend on the quality, so target_bytes needs a lossy encode
+    config.jpeg_transcode.unwrap_or(true)
+        && config.size.is_none()
+        && config.ops.is_none()
+        && config.filters.is_none()
+        && config.target_bytes.is_none()
+}
+
+/// 元の JPEG を復元するかどうか（JPEG のリサイズ・ops・filters・target_bytes を指定した場合は画素から再エンコード）
+pub fn can_reconstruct(config: Option<&JpegConfig>) -> bool {
+    config.is_none_or(|config| {
+        config.size.is_none()
+            && config.ops.is_none()
+            && config.filters.is_none()
+            && config.target_bytes.is_none()
+    })
+}
+
+pub fn decode_image(input: &[u8]) -> anyhow::Result<DynamicImage> {
+    let runner = ThreadsRunner::default();
+    let decoder = decoder_builder()
+        .parallel_runner(&runner)
+        .build()
+        .map_err(decode_error)?;
+
+    decoder
+        .decode_to_image(input)
+        .map_err(decode_error)?
+        .ok_or_else(|| decode_error("Unsupported pixel format"))
+}
+
+fn set_effort(builder: &mut JxlEncoderBuilder, config: &JxlConfig) {
+    let speed = match config.effort {
+        Some(1) => EncoderSpeed::Lightning,
+        Some(2) => EncoderSpeed::Thunder,
+        Some(3) => EncoderSpeed::Falcon,
+        Some(4) => EncoderSpeed::Cheetah,
+        Some(5) => EncoderSpeed::Hare,
+        Some(6) => EncoderSpeed::Wombat,
+        Some(8) => EncoderSpeed::Kitten,
+        Some(9) => EncoderSpeed::Tortoise,
+        Some(10) => EncoderSpeed::Glacier,
+        _ => EncoderSpeed::Squirrel,
+    };
+
+    builder.speed(speed);
+}
+
+fn encode_error(e: impl ToString) -> anyhow::Error {
+    anyhow!(CompressorError::JxlCompressError(e.to_string()))
+}
+
+fn decode_error(e: impl ToString) -> anyhow::Error {
+    anyhow!(CompressorError::ImageFormatError(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn jxl_config(json: &str) -> JxlConfig {
+        let mut config = serde_json::to_value(JxlConfig::default()).unwrap();
+        for (key, value) in serde_json::from_str::<serde_json::Map<_, _>>(json).unwrap() {
+            config[key] = value;
+        }
+        serde_json::from_value(config).unwrap()
+    }
+
+    #[test]
+    fn transcode_is_skipped_when_filters_are_set() {
+        assert!(can_transcode(None));
+        assert!(can_transcode(Some(&jxl_config("{}"))));
+        assert!(!can_transcode(Some(&jxl_config(r#"{"filters": {"grayscale": true}}"#))));
+        assert!(!can_transcode(Some(&jxl_config(r#"{"filters": {"unsharp": {"radius": 1.0}}}"#))));
+    }
+
+    #[test]
+    fn transcode_is_skipped_when_searching_for_a_size() {
+        assert!(!can_transcode(Some(&jxl_config(r#"{"target_bytes": 10000}"#))));
+        assert!(!can_transcode(Some(&jxl_config(r#"{"jpeg_transcode": false}"#))));
+        // NOTE: The transcode is lossless, so it always satisfies min_ssim
+        assert!(can_transcode(Some(&jxl_config(r#"{"min_ssim": 0.99}"#))));
+    }
+
+    #[test]
+    fn reconstruct_is_skipped_when_the_jpeg_is_transformed() {
+        let jpeg_config = |json: &str| {
+            let mut config = serde_json::to_value(JpegConfig::default()).unwrap();
+            for (key, value) in serde_json::from_str::<serde_json::Map<_, _>>(json).unwrap() {
+                config[key] = value;
+            }
+            serde_json::from_value::<JpegConfig>(config).unwrap()
+        };
+
+        assert!(can_reconstruct(None));
+        assert!(can_reconstruct(Some(&jpeg_config("{}"))));
+        assert!(!can_reconstruct(Some(&jpeg_config(r#"{"size": {"width": 100, "filter": "lanczos3"}}"#))));
+        assert!(!can_reconstruct(Some(&jpeg_config(r#"{"ops": [{"rotate": 90}]}"#))));
+        assert!(!can_reconstruct(Some(&jpeg_config(r#"{"filters": {"grayscale": true}}"#))));
+        assert!(!can_reconstruct(Some(&jpeg_config(r#"{"target_bytes": 10000}"#))));
+    }
+
+    #[test]
+    fn transcoded_jpeg_is_reconstructed_bit_exactly() {
+        let image = image::RgbImage::from_fn(32, 24, |x, y| image::Rgb([(x * 8) as u8, (y * 10) as u8, 128]));
+        let mut jpeg = Vec::new();
+        image::codecs::jpeg::JpegEncoder::new_with_quality(&mut jpeg, 85)
+            .encode_image(&image)
+            .unwrap();
+
+        let jxl = transcode_jpeg(None, &jpeg).unwrap();
+
+        assert_eq!(reconstruct_jpeg(&jxl).unwrap(), Some(jpeg));
+        assert_eq!(decode_image(&jxl).unwrap().dimensions(), (32, 24));
+    }
+}
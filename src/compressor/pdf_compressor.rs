@@ -1,18 +1,15 @@
-use crate::config_json::PdfConfig;
+use crate::config_json::{PdfConfig, SizeFilterConfig};
 use crate::error::CompressorError;
-use crate::io::file::read_file_bytes;
+use crate::imaging::transform;
 use anyhow::anyhow;
-use image::imageops::FilterType;
 use image::{DynamicImage, ImageFormat, RgbImage};
 use lopdf::{Dictionary, Document, Object, Stream};
 use std::io::{Cursor, Read, Write};
 
 const CMYK_ICC: &'static [u8] = include_bytes!("../../assets/icc/USWebCoatedSWOP.icc");
 
-pub fn compress(input_path: &String, config: Option<&PdfConfig>) -> anyhow::Result<Vec<u8>> {
-    let buffer = read_file_bytes(input_path)?;
-
-    let mut doc = Document::load_mem(&buffer)?;
+pub fn compress(input: &[u8], config: Option<&PdfConfig>) -> anyhow::Result<Vec<u8>> {
+    let mut doc = Document::load_mem(input)?;
 
     // NOTE: 未使用オブジェクトの削除
     doc.prune_objects();
@@ -120,21 +117,34 @@ fn remove_unused_fonts(doc: &mut Document) -> anyhow::Result<()> {
 
 fn compress_images(doc: &mut Document, config: Option<&PdfConfig>) -> anyhow::Result<()> {
     let default_config = PdfConfig::default();
-    let (png_min_quality, png_max_quality, jpeg_quality, jpeg_max_length) = match config {
+    let (png_min_quality, png_max_quality, jpeg_quality, jpeg_max_length, jpeg_size) = match config {
         Some(config) => (
             config.png.quality_min,
             config.png.quality_max,
             config.jpeg.quality,
             config.jpeg.max_length,
+            config.jpeg.size.as_ref(),
         ),
         None => (
             default_config.png.quality_min,
             default_config.png.quality_max,
             default_config.jpeg.quality,
             default_config.jpeg.max_length,
+            default_config.jpeg.size.as_ref(),
         ),
     };
 
+    // NOTE: max_length fits the longer side in the same way as size without upscaling
+    let jpeg_size = jpeg_size.cloned().unwrap_or(SizeFilterConfig {
+        width: Some(jpeg_max_length.max(1) as u32),
+        height: Some(jpeg_max_length.max(1) as u32),
+        filter: "catmull_rom".to_string(),
+        mode: None,
+        no_upscale: Some(true),
+        gravity: None,
+        focus: None,
+    });
+
     let mut objects = Vec::new();
 
     for (object_id, object) in doc.objects.iter() {
@@ -243,20 +253,13 @@ fn compress_images(doc: &mut Document, config: Option<&PdfConfig>) -> anyhow::Re
                                 }
                             };
 
-                            let resized_img = if width > jpeg_max_length || height > jpeg_max_length
-                            {
-                                let ratio = jpeg_max_length as f32 / width.max(height) as f32;
-                                width = (width as f32 * ratio).round() as i64;
-                                height = (height as f32 * ratio).round() as i64;
-                                image::imageops::resize(
-                                    &decoded_img,
-                                    width as u32,
-                                    height as u32,
-                                    FilterType::CatmullRom,
-                                )
-                            } else {
-                                decoded_img
-                            };
+                            let resized_img = transform::resize_image(
+                                &DynamicImage::ImageRgb8(decoded_img),
+                                &jpeg_size,
+                            )?
+                            .to_rgb8();
+                            width = resized_img.width() as i64;
+                            height = resized_img.height() as i64;
 
                             let rgb_data = resized_img.as_raw();
 
@@ -1,64 +1,86 @@
-use crate::config_json::PngConfig;
+use crate::config_json::{LossyConfig, PngConfig};
 use crate::error::CompressorError;
-use crate::imaging::transform;
-use crate::io::file::read_image_from_file;
+use crate::file_type::{self, FileType};
+use crate::imaging::animation::{Animation, Frame};
+use crate::imaging::{filter, orientation, transform};
+use crate::io::file::read_image_from_bytes;
 use anyhow::anyhow;
-use image::{DynamicImage, GenericImageView, ImageFormat};
+use image::{
+    imageops, DynamicImage, GenericImageView, GrayAlphaImage, GrayImage, ImageFormat, RgbImage,
+    RgbaImage,
+};
+use png::{BlendOp, DisposeOp, FrameControl};
+use std::collections::HashMap;
 use std::io::Cursor;
 use std::num::NonZeroU8;
 
-pub fn compress(config: Option<&PngConfig>, input_path: &String) -> anyhow::Result<Vec<u8>> {
+/// APNG のフレーム（合成前のフレーム領域）
+struct ApngFrame {
+    image: RgbaImage,
+    control: FrameControl,
+}
+
+/// APNG（フレーム領域・表示時間・dispose/blend 操作を保持）
+struct Apng {
+    width: u32,
+    height: u32,
+    /// アニメーションに含まれない既定画像（APNG 非対応のビューアで表示）
+    default_image: Option<RgbaImage>,
+    frames: Vec<ApngFrame>,
+    /// 0: 無限ループ
+    loop_count: u32,
+}
+
+pub fn compress(config: Option<&PngConfig>, input: &[u8]) -> anyhow::Result<Vec<u8>> {
+    if file_type::is_apng(input) {
+        let apng = decode_apng(input)?;
+
+        // NOTE: Frame regions no longer line up after size, ops or filters, so the frames are composited first
+        if config.is_some_and(|config| {
+            config.size.is_some() || config.ops.is_some() || config.filters.is_some()
+        }) {
+            return compress_animation(config, compose(apng));
+        }
+
+        let default_config = PngConfig::default();
+        return encode_apng(config.unwrap_or(&default_config), &apng);
+    }
+
+    // NOTE: The eXIf chunk is not written again, so the orientation is applied to the pixels
+    let dynamic_image = orientation::apply(
+        read_image_from_bytes(input)?,
+        orientation::read(&FileType::PNG, input),
+    );
+
+    compress_image(config, dynamic_image)
+}
+
+pub fn compress_image(
+    config: Option<&PngConfig>,
+    mut dynamic_image: DynamicImage,
+) -> anyhow::Result<Vec<u8>> {
     let default_config = PngConfig::default();
-    let (quality, strip, interlacing, optimize_alpha, size, libdeflater, zopfli, lossy) =
-        match config {
-            Some(config) => (
-                config.quality,
-                config.strip.as_str(),
-                config.interlacing.as_str(),
-                config.optimize_alpha,
-                config.size.as_ref(),
-                config.libdeflater.as_ref(),
-                config.zopfli.as_ref(),
-                config.lossy.as_ref(),
-            ),
-            None => (
-                default_config.quality,
-                default_config.strip.as_str(),
-                default_config.interlacing.as_str(),
-                default_config.optimize_alpha,
-                default_config.size.as_ref(),
-                default_config.libdeflater.as_ref(),
-                default_config.zopfli.as_ref(),
-                default_config.lossy.as_ref(),
-            ),
-        };
+    let config = config.unwrap_or(&default_config);
+    let (size, lossy) = (config.size.as_ref(), config.lossy.as_ref());
 
-    let mut dynamic_image = read_image_from_file(input_path)?;
+    if let Some(ops) = config.ops.as_deref() {
+        dynamic_image = transform::apply_ops(dynamic_image, ops)?;
+    }
 
     if let Some(size_config) = size {
-        dynamic_image = transform::resize_image(&dynamic_image, size_config);
+        dynamic_image = transform::resize_image(&dynamic_image, size_config)?;
+    }
+
+    if let Some(filters_config) = config.filters.as_ref() {
+        dynamic_image = filter::apply_filters(dynamic_image, filters_config)?;
     }
 
     let (width, height) = dynamic_image.dimensions();
 
     if let Some(lossy) = lossy {
-        let bitmap = dynamic_image
-            .to_rgba8()
-            .pixels()
-            .map(|p| imagequant::RGBA::new(p.0[0], p.0[1], p.0[2], p.0[3]))
-            .collect::<Vec<imagequant::RGBA>>();
-
-        let mut attr = imagequant::new();
-
-        attr.set_quality(lossy.quality_min, lossy.quality_max)?;
+        let bitmap = rgba_pixels(&dynamic_image.to_rgba8());
 
-        if let Some(colors) = lossy.colors {
-            attr.set_max_colors(colors)?;
-        }
-
-        if let Some(speed) = lossy.speed {
-            attr.set_speed(speed)?;
-        }
+        let attr = quantizer(lossy)?;
 
         let mut liq_image = attr.new_image(&bitmap[..], width as usize, height as usize, 0.0)?;
         let mut res = attr.quantize(&mut liq_image)?;
@@ -77,31 +99,678 @@ pub fn compress(config: Option<&PngConfig>, input_path: &String) -> anyhow::Resu
     let mut bytes = Vec::new();
     dynamic_image.write_to(&mut Cursor::new(&mut bytes), ImageFormat::Png)?;
 
-    let mut options = oxipng::Options::from_preset(quality);
-    options.strip = match strip {
+    optimize(&bytes, &oxipng_options(config))
+}
+
+/// エンコード済みの PNG を oxipng で最適化（size・lossy は適用しない）
+pub fn optimize_png(config: Option<&PngConfig>, input: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let default_config = PngConfig::default();
+
+    optimize(input, &oxipng_options(config.unwrap_or(&default_config)))
+}
+
+/// 画素を変更せずに strip で指定したチャンクだけを削除（元のデータを維持する場合）
+pub fn strip_metadata(config: Option<&PngConfig>, input: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let default_config = PngConfig::default();
+    let config = config.unwrap_or(&default_config);
+
+    let mut options = oxipng::Options::from_preset(0);
+    options.strip = oxipng_options(config).strip;
+    // NOTE: Stripping all chunks would remove acTL/fcTL and leave only the first frame
+    if matches!(options.strip, oxipng::StripChunks::All) && file_type::is_apng(input) {
+        options.strip = oxipng::StripChunks::Safe;
+    }
+    if matches!(options.strip, oxipng::StripChunks::None) {
+        return Ok(input.to_vec());
+    }
+    options.idat_recoding = false;
+    options.interlace = None;
+    options.optimize_alpha = false;
+
+    optimize(input, &options)
+}
+
+/// APNG をアニメーションとして読み込み（フレームはキャンバス全体に合成済み）
+pub fn decode_animation(input: &[u8]) -> anyhow::Result<Animation> {
+    Ok(compose(decode_apng(input)?))
+}
+
+/// アニメーションを APNG としてエンコード（フレームはキャンバス全体を上書き）
+pub fn compress_animation(
+    config: Option<&PngConfig>,
+    mut animation: Animation,
+) -> anyhow::Result<Vec<u8>> {
+    let default_config = PngConfig::default();
+    let config = config.unwrap_or(&default_config);
+
+    if let Some(ops) = config.ops.as_deref() {
+        animation = animation.apply_ops(ops)?;
+    }
+
+    if let Some(size_config) = config.size.as_ref() {
+        animation = animation.resize(size_config)?;
+    }
+
+    if let Some(filters_config) = config.filters.as_ref() {
+        animation = animation.apply_filters(filters_config)?;
+    }
+
+    let (width, height) = match animation.frames.first() {
+        Some(frame) => frame.image.dimensions(),
+        None => {
+            return Err(anyhow!(CompressorError::PngOptimizeError(
+                "No frames found in animation".to_string()
+            )));
+        }
+    };
+
+    let frames = animation
+        .frames
+        .iter()
+        .map(|frame| {
+            // NOTE: The delay numerator is 16-bit, so long delays are stored in centiseconds
+            let (delay_num, delay_den) = match u16::try_from(frame.delay_ms) {
+                Ok(delay_ms) => (delay_ms, 1000),
+                Err(_) => ((frame.delay_ms / 10).min(u16::MAX as u32) as u16, 100),
+            };
+
+            ApngFrame {
+                image: frame.image.to_rgba8(),
+                control: FrameControl {
+                    width,
+                    height,
+                    delay_num,
+                    delay_den,
+                    dispose_op: DisposeOp::None,
+                    blend_op: BlendOp::Source,
+                    ..FrameControl::default()
+                },
+            }
+        })
+        .collect();
+
+    let apng = Apng {
+        width,
+        height,
+        default_image: None,
+        frames,
+        loop_count: animation.loop_count,
+    };
+
+    encode_apng(config, &apng)
+}
+
+/// APNG の各フレーム領域と fcTL をデコード
+fn decode_apng(input: &[u8]) -> anyhow::Result<Apng> {
+    let mut decoder = png::Decoder::new(Cursor::new(input));
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info().map_err(decode_error)?;
+
+    let (width, height) = reader.info().size();
+    let (num_frames, loop_count) = match reader.info().animation_control() {
+        Some(control) => (control.num_frames as usize, control.num_plays),
+        None => return Err(decode_error("No acTL chunk found")),
+    };
+
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let mut default_image = None;
+    let mut frames = Vec::with_capacity(num_frames);
+
+    while frames.len() < num_frames {
+        let output = reader.next_frame(&mut buffer).map_err(decode_error)?;
+        let image = to_rgba(&buffer[..output.buffer_size()], &output)?;
+
+        // NOTE: An IDAT without a preceding fcTL is a default image outside the animation
+        match reader.info().frame_control() {
+            Some(control) => frames.push(ApngFrame {
+                image,
+                control: *control,
+            }),
+            None => default_image = Some(image),
+        }
+    }
+
+    Ok(Apng {
+        width,
+        height,
+        default_image,
+        frames,
+        loop_count,
+    })
+}
+
+fn to_rgba(data: &[u8], output: &png::OutputInfo) -> anyhow::Result<RgbaImage> {
+    let (width, height, data) = (output.width, output.height, data.to_vec());
+
+    let dynamic_image = match output.color_type {
+        png::ColorType::Rgba => RgbaImage::from_raw(width, height, data).map(DynamicImage::ImageRgba8),
+        png::ColorType::Rgb => RgbImage::from_raw(width, height, data).map(DynamicImage::ImageRgb8),
+        png::ColorType::GrayscaleAlpha => {
+            GrayAlphaImage::from_raw(width, height, data).map(DynamicImage::ImageLumaA8)
+        }
+        png::ColorType::Grayscale => GrayImage::from_raw(width, height, data).map(DynamicImage::ImageLuma8),
+        png::ColorType::Indexed => None,
+    };
+
+    dynamic_image
+        .map(|dynamic_image| dynamic_image.to_rgba8())
+        .ok_or_else(|| decode_error("Unsupported frame data"))
+}
+
+/// dispose/blend 操作に従ってフレームをキャンバス全体に合成
+fn compose(apng: Apng) -> Animation {
+    let mut canvas = RgbaImage::new(apng.width, apng.height);
+    let mut frames = Vec::with_capacity(apng.frames.len());
+
+    for frame in apng.frames {
+        let control = frame.control;
+        let (x, y) = (control.x_offset as i64, control.y_offset as i64);
+
+        // NOTE: DisposeOp::Previous on the first frame restores the cleared canvas, as the spec requires
+        let previous = (control.dispose_op == DisposeOp::Previous).then(|| canvas.clone());
+
+        match control.blend_op {
+            BlendOp::Source => imageops::replace(&mut canvas, &frame.image, x, y),
+            BlendOp::Over => blend_over(&mut canvas, &frame.image, control.x_offset, control.y_offset),
+        }
+
+        frames.push(Frame {
+            image: DynamicImage::ImageRgba8(canvas.clone()),
+            delay_ms: delay_ms(&control),
+        });
+
+        match control.dispose_op {
+            DisposeOp::None => {}
+            DisposeOp::Background => {
+                let clear = RgbaImage::new(control.width, control.height);
+                imageops::replace(&mut canvas, &clear, x, y);
+            }
+            DisposeOp::Previous => {
+                if let Some(previous) = previous {
+                    canvas = previous;
+                }
+            }
+        }
+    }
+
+    Animation {
+        frames,
+        loop_count: apng.loop_count,
+    }
+}
+
+/// フレーム領域をキャンバスにアルファ合成（APNG_BLEND_OP_OVER）
+fn blend_over(canvas: &mut RgbaImage, image: &RgbaImage, x_offset: u32, y_offset: u32) {
+    for (x, y, source) in image.enumerate_pixels() {
+        let Some(target) = canvas.get_pixel_mut_checked(x_offset + x, y_offset + y) else {
+            continue;
+        };
+
+        let source_alpha = source.0[3] as u32;
+        if source_alpha == u8::MAX as u32 {
+            *target = *source;
+            continue;
+        }
+        if source_alpha == 0 {
+            continue;
+        }
+
+        // NOTE: Porter-Duff "over" on non-premultiplied 8-bit colors, as defined by the APNG spec
+        let target_alpha = target.0[3] as u32 * (255 - source_alpha) / 255;
+        let alpha = source_alpha + target_alpha;
+        for channel in 0..3 {
+            target.0[channel] = ((source.0[channel] as u32 * source_alpha
+                + target.0[channel] as u32 * target_alpha
+                + alpha / 2)
+                / alpha) as u8;
+        }
+        target.0[3] = alpha as u8;
+    }
+}
+
+/// fcTL の表示時間（ミリ秒、分母が 0 の場合は 1/100 秒単位）
+fn delay_ms(control: &FrameControl) -> u32 {
+    let delay_den = match control.delay_den {
+        0 => 100,
+        delay_den => delay_den as u32,
+    };
+
+    control.delay_num as u32 * 1000 / delay_den
+}
+
+/// APNG をエンコードし、acTL/fcTL/fdAT を残したまま oxipng で最適化
+fn encode_apng(config: &PngConfig, apng: &Apng) -> anyhow::Result<Vec<u8>> {
+    let images: Vec<&RgbaImage> = apng
+        .default_image
+        .iter()
+        .chain(apng.frames.iter().map(|frame| &frame.image))
+        .collect();
+
+    // NOTE: oxipng does not reduce the color type of APNG, so frames are indexed here
+    let indexed = match config.lossy.as_ref() {
+        Some(lossy) => Some(quantize(lossy, &images)?),
+        None => exact_palette(&images, config.optimize_alpha),
+    };
+
+    let mut bytes = Vec::new();
+    let mut encoder = png::Encoder::new(&mut bytes, apng.width, apng.height);
+    encoder.set_depth(png::BitDepth::Eight);
+
+    let data: Vec<Vec<u8>> = match indexed {
+        Some((palette, indices)) => {
+            encoder.set_color(png::ColorType::Indexed);
+            encoder.set_palette(
+                palette
+                    .iter()
+                    .flat_map(|color| [color.r, color.g, color.b])
+                    .collect::<Vec<u8>>(),
+            );
+            if palette.iter().any(|color| color.a < u8::MAX) {
+                encoder.set_trns(palette.iter().map(|color| color.a).collect::<Vec<u8>>());
+            }
+            indices
+        }
+        None => {
+            encoder.set_color(png::ColorType::Rgba);
+            images.iter().map(|image| image.as_raw().clone()).collect()
+        }
+    };
+
+    encoder
+        .set_animated(apng.frames.len() as u32, apng.loop_count)
+        .map_err(encode_error)?;
+    if apng.default_image.is_some() {
+        encoder.set_sep_def_img(true).map_err(encode_error)?;
+    }
+
+    let mut writer = encoder.write_header().map_err(encode_error)?;
+    let mut data = data.iter();
+
+    if apng.default_image.is_some()
+        && let Some(default_image) = data.next()
+    {
+        writer.write_image_data(default_image).map_err(encode_error)?;
+    }
+
+    for (frame, frame_data) in apng.frames.iter().zip(data) {
+        let control = &frame.control;
+
+        writer.reset_frame_position().map_err(encode_error)?;
+        writer
+            .set_frame_dimension(control.width, control.height)
+            .and_then(|_| writer.set_frame_position(control.x_offset, control.y_offset))
+            .and_then(|_| writer.set_frame_delay(control.delay_num, control.delay_den))
+            .and_then(|_| writer.set_dispose_op(control.dispose_op))
+            .and_then(|_| writer.set_blend_op(control.blend_op))
+            .and_then(|_| writer.write_image_data(frame_data))
+            .map_err(encode_error)?;
+    }
+
+    writer.finish().map_err(encode_error)?;
+
+    let mut options = oxipng_options(config);
+    // NOTE: Stripping all chunks would remove acTL/fcTL and leave only the first frame
+    if matches!(options.strip, oxipng::StripChunks::All) {
+        options.strip = oxipng::StripChunks::Safe;
+    }
+    // NOTE: Keep the interlacing as written, frames are not re-interlaced
+    options.interlace = None;
+
+    optimize(&bytes, &options)
+}
+
+/// 全フレームを共通パレットで減色（imagequant）
+fn quantize(
+    lossy: &LossyConfig,
+    images: &[&RgbaImage],
+) -> anyhow::Result<(Vec<imagequant::RGBA>, Vec<Vec<u8>>)> {
+    let attr = quantizer(lossy)?;
+    let mut histogram = imagequant::Histogram::new(&attr);
+
+    let mut liq_images = Vec::with_capacity(images.len());
+    for image in images {
+        let mut liq_image = attr.new_image(
+            rgba_pixels(image),
+            image.width() as usize,
+            image.height() as usize,
+            0.0,
+        )?;
+        histogram.add_image(&attr, &mut liq_image)?;
+        liq_images.push(liq_image);
+    }
+
+    let mut res = histogram.quantize(&attr)?;
+    // NOTE: Dithering refines the palette for each remapped image, so frames are remapped without it
+    res.set_dithering_level(0.0)?;
+
+    let mut palette = Vec::new();
+    let mut indices = Vec::with_capacity(liq_images.len());
+    for liq_image in &mut liq_images {
+        let (frame_palette, frame_indices) = res.remapped(liq_image)?;
+        palette = frame_palette;
+        indices.push(frame_indices);
+    }
+
+    Ok((palette, indices))
+}
+
+/// 全フレームの色が 256 色以下の場合は共通パレットでインデックス化（可逆）
+fn exact_palette(
+    images: &[&RgbaImage],
+    optimize_alpha: bool,
+) -> Option<(Vec<imagequant::RGBA>, Vec<Vec<u8>>)> {
+    let mut palette = Vec::new();
+    let mut palette_indices: HashMap<[u8; 4], u8> = HashMap::new();
+    let mut indices = Vec::with_capacity(images.len());
+
+    for image in images {
+        let mut frame_indices = Vec::with_capacity(image.as_raw().len() / 4);
+
+        for pixel in image.pixels() {
+            let color = if optimize_alpha && pixel.0[3] == 0 {
+                [0; 4]
+            } else {
+                pixel.0
+            };
+
+            let index = match palette_indices.get(&color) {
+                Some(index) => *index,
+                None => {
+                    if palette.len() > u8::MAX as usize {
+                        return None;
+                    }
+
+                    let index = palette.len() as u8;
+                    palette.push(imagequant::RGBA::new(color[0], color[1], color[2], color[3]));
+                    palette_indices.insert(color, index);
+                    index
+                }
+            };
+
+            frame_indices.push(index);
+        }
+
+        indices.push(frame_indices);
+    }
+
+    Some((palette, indices))
+}
+
+fn quantizer(lossy: &LossyConfig) -> anyhow::Result<imagequant::Attributes> {
+    let mut attr = imagequant::new();
+
+    attr.set_quality(lossy.quality_min, lossy.quality_max)?;
+
+    if let Some(colors) = lossy.colors {
+        attr.set_max_colors(colors)?;
+    }
+
+    if let Some(speed) = lossy.speed {
+        attr.set_speed(speed)?;
+    }
+
+    Ok(attr)
+}
+
+fn rgba_pixels(image: &RgbaImage) -> Vec<imagequant::RGBA> {
+    image
+        .pixels()
+        .map(|p| imagequant::RGBA::new(p.0[0], p.0[1], p.0[2], p.0[3]))
+        .collect()
+}
+
+fn decode_error(e: impl ToString) -> anyhow::Error {
+    anyhow!(CompressorError::ImageFormatError(e.to_string()))
+}
+
+fn encode_error(e: impl ToString) -> anyhow::Error {
+    anyhow!(CompressorError::PngOptimizeError(e.to_string()))
+}
+
+fn oxipng_options(config: &PngConfig) -> oxipng::Options {
+    let mut options = oxipng::Options::from_preset(config.quality);
+    options.strip = match config.strip.as_str() {
         "safe" => oxipng::StripChunks::Safe,
         "all" => oxipng::StripChunks::All,
         _ => oxipng::StripChunks::None,
     };
-    options.interlace = match interlacing {
+    options.interlace = match config.interlacing.as_str() {
         "adam7" => Some(oxipng::Interlacing::Adam7),
         _ => Some(oxipng::Interlacing::None),
     };
-    options.optimize_alpha = optimize_alpha;
+    options.optimize_alpha = config.optimize_alpha;
 
-    if let Some(libdeflater) = libdeflater {
+    if let Some(libdeflater) = config.libdeflater.as_ref() {
         options.deflate = oxipng::Deflaters::Libdeflater {
             compression: libdeflater.compression,
         };
-    } else if let Some(zopfli) = zopfli {
+    } else if let Some(zopfli) = config.zopfli.as_ref() {
         options.deflate = oxipng::Deflaters::Zopfli {
             iterations: NonZeroU8::new(zopfli.iterations).unwrap(),
         };
     }
 
-    let png_result = oxipng::optimize_from_memory(&bytes, &options);
+    options
+}
+
+fn optimize(bytes: &[u8], options: &oxipng::Options) -> anyhow::Result<Vec<u8>> {
+    let png_result = oxipng::optimize_from_memory(bytes, options);
     match png_result {
         Ok(data) => Ok(data),
         Err(e) => Err(anyhow!(CompressorError::PngOptimizeError(e.to_string()))),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    const RED: Rgba<u8> = Rgba([255, 0, 0, 255]);
+    const BLUE: Rgba<u8> = Rgba([0, 0, 255, 255]);
+    const CLEAR: Rgba<u8> = Rgba([0, 0, 0, 0]);
+
+    fn frame(image: RgbaImage, x_offset: u32, y_offset: u32, dispose_op: DisposeOp, blend_op: BlendOp) -> ApngFrame {
+        ApngFrame {
+            control: FrameControl {
+                width: image.width(),
+                height: image.height(),
+                x_offset,
+                y_offset,
+                delay_num: 1,
+                delay_den: 10,
+                dispose_op,
+                blend_op,
+                ..FrameControl::default()
+            },
+            image,
+        }
+    }
+
+    fn apng(width: u32, height: u32, frames: Vec<ApngFrame>) -> Apng {
+        Apng {
+            width,
+            height,
+            default_image: None,
+            frames,
+            loop_count: 0,
+        }
+    }
+
+    /// 4x2 の赤いフレームと、右上 1x1 を青にするフレームの APNG
+    fn sample_apng() -> Vec<u8> {
+        let frames = vec![
+            frame(RgbaImage::from_pixel(4, 2, RED), 0, 0, DisposeOp::None, BlendOp::Source),
+            frame(RgbaImage::from_pixel(1, 1, BLUE), 3, 0, DisposeOp::None, BlendOp::Source),
+        ];
+
+        encode_apng(&PngConfig::default(), &apng(4, 2, frames)).unwrap()
+    }
+
+    fn png_config(json: &str) -> PngConfig {
+        let mut config: serde_json::Value = serde_json::to_value(PngConfig::default()).unwrap();
+        for (key, value) in serde_json::from_str::<serde_json::Map<_, _>>(json).unwrap() {
+            config[key] = value;
+        }
+        serde_json::from_value(config).unwrap()
+    }
+
+    #[test]
+    fn blend_over_mixes_translucent_pixels() {
+        let mut canvas = RgbaImage::from_pixel(2, 1, BLUE);
+        let image = RgbaImage::from_fn(2, 1, |x, _| if x == 0 { Rgba([255, 0, 0, 128]) } else { CLEAR });
+
+        blend_over(&mut canvas, &image, 0, 0);
+
+        assert_eq!(canvas.get_pixel(0, 0), &Rgba([128, 0, 127, 255]));
+        assert_eq!(canvas.get_pixel(1, 0), &BLUE);
+    }
+
+    #[test]
+    fn blend_over_onto_transparent_canvas_keeps_source() {
+        let mut canvas = RgbaImage::new(1, 1);
+        let source = Rgba([10, 20, 30, 100]);
+
+        blend_over(&mut canvas, &RgbaImage::from_pixel(1, 1, source), 0, 0);
+
+        assert_eq!(canvas.get_pixel(0, 0), &source);
+    }
+
+    #[test]
+    fn blend_over_skips_pixels_outside_canvas() {
+        let mut canvas = RgbaImage::new(2, 2);
+
+        blend_over(&mut canvas, &RgbaImage::from_pixel(2, 2, RED), 1, 1);
+
+        assert_eq!(canvas.get_pixel(1, 1), &RED);
+        assert_eq!(canvas.get_pixel(0, 0), &CLEAR);
+    }
+
+    #[test]
+    fn compose_applies_offsets_and_dispose_ops() {
+        let frames = vec![
+            frame(RgbaImage::from_pixel(2, 2, RED), 0, 0, DisposeOp::None, BlendOp::Source),
+            frame(RgbaImage::from_pixel(1, 1, BLUE), 1, 1, DisposeOp::Background, BlendOp::Source),
+            frame(RgbaImage::from_pixel(1, 1, BLUE), 0, 0, DisposeOp::Previous, BlendOp::Over),
+            frame(RgbaImage::from_pixel(1, 1, CLEAR), 1, 0, DisposeOp::None, BlendOp::Over),
+        ];
+
+        let animation = compose(apng(2, 2, frames));
+        let pixels: Vec<RgbaImage> = animation.frames.iter().map(|frame| frame.image.to_rgba8()).collect();
+
+        assert_eq!(animation.frames.len(), 4);
+        assert!(animation.frames.iter().all(|frame| frame.delay_ms == 100));
+        assert!(pixels[0].pixels().all(|pixel| pixel == &RED));
+        assert_eq!(pixels[1].get_pixel(1, 1), &BLUE);
+        assert_eq!(pixels[1].get_pixel(0, 0), &RED);
+        // NOTE: Background clears the region of frame 2, Previous restores the canvas before frame 3
+        assert_eq!(pixels[2].get_pixel(1, 1), &CLEAR);
+        assert_eq!(pixels[2].get_pixel(0, 0), &BLUE);
+        assert_eq!(pixels[3].get_pixel(0, 0), &RED);
+        assert_eq!(pixels[3].get_pixel(1, 0), &RED);
+        assert_eq!(pixels[3].get_pixel(1, 1), &CLEAR);
+    }
+
+    #[test]
+    fn delay_uses_centiseconds_without_denominator() {
+        let mut control = FrameControl {
+            delay_num: 25,
+            delay_den: 0,
+            ..FrameControl::default()
+        };
+        assert_eq!(delay_ms(&control), 250);
+
+        control.delay_den = 1000;
+        assert_eq!(delay_ms(&control), 25);
+    }
+
+    #[test]
+    fn exact_palette_shares_colors_across_frames() {
+        let first = RgbaImage::from_fn(2, 1, |x, _| if x == 0 { RED } else { BLUE });
+        let second = RgbaImage::from_pixel(2, 1, BLUE);
+
+        let (palette, indices) = exact_palette(&[&first, &second], false).unwrap();
+
+        assert_eq!(palette.len(), 2);
+        assert_eq!(indices, vec![vec![0, 1], vec![1, 1]]);
+        assert_eq!(palette[1], imagequant::RGBA::new(0, 0, 255, 255));
+    }
+
+    #[test]
+    fn exact_palette_merges_transparent_colors_with_optimize_alpha() {
+        let image = RgbaImage::from_fn(2, 1, |x, _| if x == 0 { Rgba([255, 0, 0, 0]) } else { Rgba([0, 255, 0, 0]) });
+
+        assert_eq!(exact_palette(&[&image], false).unwrap().0.len(), 2);
+        assert_eq!(exact_palette(&[&image], true).unwrap().0, vec![imagequant::RGBA::new(0, 0, 0, 0)]);
+    }
+
+    #[test]
+    fn exact_palette_gives_up_above_256_colors() {
+        let colors_256 = RgbaImage::from_fn(16, 16, |x, y| Rgba([x as u8, y as u8, 0, 255]));
+        let colors_257 = RgbaImage::from_fn(257, 1, |x, _| Rgba([(x % 256) as u8, (x / 256) as u8, 0, 255]));
+
+        assert_eq!(exact_palette(&[&colors_256], false).unwrap().0.len(), 256);
+        assert!(exact_palette(&[&colors_257], false).is_none());
+    }
+
+    #[test]
+    fn apng_round_trips_without_transforms() {
+        let animation = decode_animation(&compress(None, &sample_apng()).unwrap()).unwrap();
+
+        assert_eq!(animation.frames.len(), 2);
+        assert_eq!(animation.frames[1].image.to_rgba8().get_pixel(3, 0), &BLUE);
+        assert_eq!(animation.frames[1].image.to_rgba8().get_pixel(0, 0), &RED);
+    }
+
+    #[test]
+    fn apng_applies_ops_to_every_frame() {
+        let config = png_config(r#"{"ops": [{"rotate": 90}]}"#);
+        let animation = decode_animation(&compress(Some(&config), &sample_apng()).unwrap()).unwrap();
+
+        assert_eq!(animation.frames.len(), 2);
+        for frame in &animation.frames {
+            assert_eq!(frame.image.dimensions(), (2, 4));
+        }
+        // NOTE: The top right pixel moves to the bottom right after rotating clockwise
+        assert_eq!(animation.frames[1].image.to_rgba8().get_pixel(1, 3), &BLUE);
+    }
+
+    #[test]
+    fn apng_applies_filters_to_every_frame() {
+        let config = png_config(r#"{"filters": {"grayscale": true}}"#);
+        let animation = decode_animation(&compress(Some(&config), &sample_apng()).unwrap()).unwrap();
+
+        assert_eq!(animation.frames.len(), 2);
+        for frame in &animation.frames {
+            assert!(frame.image.to_rgba8().pixels().all(|pixel| pixel[0] == pixel[1] && pixel[1] == pixel[2]));
+        }
+    }
+
+    #[test]
+    fn strip_metadata_removes_text_chunks_losslessly() {
+        let image = RgbaImage::from_fn(8, 8, |x, y| Rgba([(x * 30) as u8, (y * 30) as u8, 0, 255]));
+        let mut input = Vec::new();
+        {
+            let mut encoder = png::Encoder::new(&mut input, 8, 8);
+            encoder.set_color(png::ColorType::Rgba);
+            encoder.set_depth(png::BitDepth::Eight);
+            encoder.add_text_chunk("Comment".to_string(), "private".to_string()).unwrap();
+            encoder.write_header().unwrap().write_image_data(image.as_raw()).unwrap();
+        }
+        let contains = |data: &[u8]| data.windows(4).any(|window| window == b"tEXt");
+
+        let stripped = strip_metadata(None, &input).unwrap();
+        assert!(!contains(&stripped));
+        assert_eq!(read_image_from_bytes(&stripped).unwrap().to_rgba8(), image);
+
+        assert!(contains(&strip_metadata(Some(&png_config(r#"{"strip": "none"}"#)), &input).unwrap()));
+    }
+
+    #[test]
+    fn strip_metadata_keeps_apng_frames() {
+        let stripped = strip_metadata(None, &sample_apng()).unwrap();
+
+        assert!(file_type::is_apng(&stripped));
+        assert_eq!(decode_animation(&stripped).unwrap().frames.len(), 2);
+    }
+}
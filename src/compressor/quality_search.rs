@@ -0,0 +1,130 @@
+use anyhow::{anyhow, Result};
+
+/// 品質パラメータ (min..=max) を二分探索し、target_bytes 以下に収まる最高品質の結果を返す
+///
+/// どの品質でも収まらない場合は最低品質の結果を返す
+pub fn search<F>(min: u8, max: u8, target_bytes: u64, mut encode: F) -> Result<(u8, Vec<u8>)>
+where
+    F: FnMut(u8) -> Result<Vec<u8>>,
+{
+    let (mut low, mut high) = (min.min(max), max);
+    let mut best: Option<(u8, Vec<u8>)> = None;
+    let mut smallest: Option<(u8, Vec<u8>)> = None;
+
+    while low <= high {
+        let quality = low + (high - low) / 2;
+        let data = encode(quality)?;
+
+        if data.len() as u64 <= target_bytes {
+            best = Some((quality, data));
+            if quality == u8::MAX {
+                break;
+            }
+            low = quality + 1;
+        } else {
+            if smallest.as_ref().is_none_or(|(q, _)| quality < *q) {
+                smallest = Some((quality, data));
+            }
+            if quality == 0 {
+                break;
+            }
+            high = quality - 1;
+        }
+    }
+
+    best.or(smallest)
+        .ok_or_else(|| anyhow!("No quality in range {}..={}", min, max))
+}
+
+/// 品質パラメータ (min..=max) を二分探索し、accept が値を返す最低品質の結果を返す
+///
+/// 品質が高いほど条件を満たしやすいこと（単調性）を前提とする
+pub fn search_lowest<T, F>(min: u8, max: u8, mut accept: F) -> Result<Option<(u8, T)>>
+where
+    F: FnMut(u8) -> Result<Option<T>>,
+{
+    let (mut low, mut high) = (min.min(max), max);
+    let mut best: Option<(u8, T)> = None;
+
+    while low <= high {
+        let quality = low + (high - low) / 2;
+
+        match accept(quality)? {
+            Some(value) => {
+                best = Some((quality, value));
+                if quality == 0 {
+                    break;
+                }
+                high = quality - 1;
+            }
+            None => {
+                if quality == u8::MAX {
+                    break;
+                }
+                low = quality + 1;
+            }
+        }
+    }
+
+    Ok(best)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 品質に比例してサイズが増えるエンコーダー
+    fn encode(quality: u8) -> Result<Vec<u8>> {
+        Ok(vec![0; quality as usize * 10])
+    }
+
+    #[test]
+    fn search_returns_highest_quality_within_target() {
+        let (quality, data) = search(1, 100, 505, encode).unwrap();
+
+        assert_eq!(quality, 50);
+        assert_eq!(data.len(), 500);
+    }
+
+    #[test]
+    fn search_returns_lowest_quality_when_nothing_fits() {
+        let (quality, data) = search(20, 80, 10, encode).unwrap();
+
+        assert_eq!(quality, 20);
+        assert_eq!(data.len(), 200);
+    }
+
+    #[test]
+    fn search_covers_full_range() {
+        assert_eq!(search(0, u8::MAX, u64::MAX, encode).unwrap().0, u8::MAX);
+        assert_eq!(search(0, u8::MAX, 0, encode).unwrap().0, 0);
+    }
+
+    #[test]
+    fn search_propagates_errors() {
+        assert!(search(1, 100, 100, |_| Err(anyhow!("failed"))).is_err());
+    }
+
+    #[test]
+    fn search_lowest_returns_lowest_accepted_quality() {
+        let result = search_lowest(1, 100, |quality| Ok((quality >= 73).then_some(quality * 2)));
+
+        assert_eq!(result.unwrap(), Some((73, 146)));
+    }
+
+    #[test]
+    fn search_lowest_returns_none_when_nothing_is_accepted() {
+        let result = search_lowest(1, 100, |_| Ok(None::<()>));
+
+        assert_eq!(result.unwrap(), None);
+    }
+
+    #[test]
+    fn search_lowest_covers_full_range() {
+        assert_eq!(search_lowest(0, u8::MAX, |_| Ok(Some(()))).unwrap(), Some((0, ())));
+        assert_eq!(
+            search_lowest(0, u8::MAX, |quality| Ok((quality == u8::MAX).then_some(()))).unwrap(),
+            Some((u8::MAX, ()))
+        );
+    }
+}
@@ -0,0 +1,362 @@
+use super::{encode_image, format_name, quality_search, set_quality, target_range, Dimensions};
+use crate::config_json::{Config, OpConfig, ResponsiveConfig, SizeFilterConfig, VariantConfig};
+use crate::error::CompressorError;
+use crate::file_type::{self, FileType};
+use crate::imaging::transform;
+use anyhow::{anyhow, Result};
+use image::{DynamicImage, GenericImageView};
+use std::path::Path;
+
+/// 幅・形式ごとの出力
+pub struct Variant {
+    pub path: String,
+    pub file_type: FileType,
+    pub dimensions: Dimensions,
+    /// target_bytes による探索で選択された品質
+    pub quality: Option<u8>,
+    pub data: Vec<u8>,
+}
+
+/// responsive.variants が指定されているかどうか
+pub fn is_enabled(config: &Config) -> bool {
+    config
+        .responsive
+        .as_ref()
+        .is_some_and(|responsive_config| !responsive_config.variants.is_empty())
+}
+
+/// デコード済みの画像から各幅・各形式の画像を作成（元の幅を超える幅は元の幅で出力）
+///
+/// 出力形式の ops は縮小前の画像に1度だけ適用し、全ての形式で共通とする
+pub fn encode_variants(
+    config: &Config,
+    dynamic_image: &DynamicImage,
+    output_path: &str,
+    output_type: &FileType,
+) -> Result<Vec<Variant>> {
+    let default_config = ResponsiveConfig::default();
+    let responsive_config = config.responsive.as_ref().unwrap_or(&default_config);
+    let file_name = responsive_config
+        .file_name
+        .as_deref()
+        .or(default_config.file_name.as_deref())
+        .unwrap_or_default();
+    let filter = responsive_config
+        .filter
+        .as_deref()
+        .or(default_config.filter.as_deref())
+        .unwrap_or_default();
+
+    // NOTE: Every format is checked first, so an unsupported entry fails before any variant is encoded
+    for variant_config in &responsive_config.variants {
+        variant_types(variant_formats(responsive_config, variant_config), output_type)?;
+    }
+
+    let source = match format_ops(config, output_type) {
+        Some(ops) => transform::apply_ops(dynamic_image.clone(), ops)?,
+        None => dynamic_image.clone(),
+    };
+
+    // NOTE: The image is transformed here, so the ops and size of each format must not be applied again
+    let config = &without_transforms(config);
+    let source_width = source.width();
+
+    let mut widths = Vec::new();
+    let mut variants: Vec<Variant> = Vec::new();
+
+    for variant_config in &responsive_config.variants {
+        // NOTE: Never upscale, and output the original width only once
+        let width = variant_config.width.min(source_width);
+        if widths.contains(&width) {
+            continue;
+        }
+        widths.push(width);
+
+        let size_config = SizeFilterConfig {
+            width: Some(width),
+            height: None,
+            filter: filter.to_string(),
+            mode: Some("width-only".into()),
+            no_upscale: None,
+            gravity: None,
+            focus: None,
+        };
+        let resized = transform::resize_image(&source, &size_config)?;
+        let (width, height) = resized.dimensions();
+        let dimensions = Dimensions { width, height };
+
+        for file_type in variant_types(variant_formats(responsive_config, variant_config), output_type)? {
+            let path = variant_path(output_path, file_name, &dimensions, &file_type);
+            if variants.iter().any(|variant| variant.path == path) {
+                return Err(anyhow!("Variant file name is not unique: {}", path));
+            }
+
+            let (data, quality) = encode_variant(config, &file_type, &resized)?;
+
+            variants.push(Variant {
+                path,
+                file_type,
+                dimensions,
+                quality,
+                data,
+            });
+        }
+    }
+
+    Ok(variants)
+}
+
+/// 幅ごとの formats（未指定の場合は responsive.formats）
+fn variant_formats<'a>(
+    responsive_config: &'a ResponsiveConfig,
+    variant_config: &'a VariantConfig,
+) -> Option<&'a [String]> {
+    variant_config
+        .formats
+        .as_deref()
+        .or(responsive_config.formats.as_deref())
+}
+
+/// 出力形式（未指定の場合は出力形式のみ）
+fn variant_types(formats: Option<&[String]>, output_type: &FileType) -> Result<Vec<FileType>> {
+    let file_types = match formats {
+        Some(formats) => formats
+            .iter()
+            .map(|format| {
+                file_type::from_extension(format).ok_or_else(|| unsupported_variant(format))
+            })
+            .collect::<Result<Vec<_>>>()?,
+        None => vec![output_type.clone()],
+    };
+
+    if let Some(file_type) = file_types.iter().find(|file_type| !is_variant_type(file_type)) {
+        return Err(unsupported_variant(format_name(file_type)));
+    }
+
+    Ok(file_types)
+}
+
+/// 幅・形式ごとに出力できる形式（静止画として出力できる形式のみ）
+fn is_variant_type(file_type: &FileType) -> bool {
+    match file_type {
+        FileType::PNG
+        | FileType::JPEG
+        | FileType::WEBP
+        | FileType::HEIF
+        | FileType::AVIF
+        | FileType::JXL
+        | FileType::TIFF => true,
+        FileType::GIF
+        | FileType::BMP
+        | FileType::ICO
+        | FileType::TGA
+        | FileType::PDF
+        | FileType::XML => false,
+    }
+}
+
+fn unsupported_variant(format: &str) -> anyhow::Error {
+    anyhow!(CompressorError::UnsupportedConversion(format!(
+        "Unsupported variant format: {}",
+        format
+    )))
+}
+
+/// target_bytes が設定されている場合は品質を二分探索
+fn encode_variant(
+    config: &Config,
+    file_type: &FileType,
+    dynamic_image: &DynamicImage,
+) -> Result<(Vec<u8>, Option<u8>)> {
+    let Some((target_bytes, quality_min, quality_max)) = target_range(config, file_type) else {
+        return Ok((encode_image(config, file_type, dynamic_image.clone())?, None));
+    };
+
+    let (quality, data) = quality_search::search(quality_min, quality_max, target_bytes, |quality| {
+        let mut config = config.clone();
+        set_quality(&mut config, file_type, quality);
+
+        encode_image(&config, file_type, dynamic_image.clone())
+    })?;
+
+    Ok((data, Some(quality)))
+}
+
+fn format_ops<'a>(config: &'a Config, file_type: &FileType) -> Option<&'a [OpConfig]> {
+    match file_type {
+        FileType::PNG => config.png.as_ref()?.ops.as_deref(),
+        FileType::JPEG => config.jpeg.as_ref()?.ops.as_deref(),
+        FileType::WEBP => config.webp.as_ref()?.ops.as_deref(),
+        FileType::GIF => config.gif.as_ref()?.ops.as_deref(),
+        FileType::HEIF => config.heif.as_ref()?.ops.as_deref(),
+        FileType::AVIF => config.avif.as_ref()?.ops.as_deref(),
+        FileType::JXL => config.jxl.as_ref()?.ops.as_deref(),
+        FileType::TIFF => config.tiff.as_ref()?.ops.as_deref(),
+        FileType::BMP
+        | FileType::ICO
+        | FileType::TGA
+        | FileType::PDF
+        | FileType::XML => None,
+    }
+}
+
+fn without_transforms(config: &Config) -> Config {
+    let mut config = config.clone();
+
+    if let Some(png_config) = config.png.as_mut() {
+        png_config.size = None;
+        png_config.ops = None;
+    }
+    if let Some(jpeg_config) = config.jpeg.as_mut() {
+        jpeg_config.size = None;
+        jpeg_config.ops = None;
+    }
+    if let Some(webp_config) = config.webp.as_mut() {
+        webp_config.size = None;
+        webp_config.ops = None;
+    }
+    if let Some(heif_config) = config.heif.as_mut() {
+        heif_config.size = None;
+        heif_config.ops = None;
+    }
+    if let Some(avif_config) = config.avif.as_mut() {
+        avif_config.size = None;
+        avif_config.ops = None;
+    }
+    if let Some(jxl_config) = config.jxl.as_mut() {
+        jxl_config.size = None;
+        jxl_config.ops = None;
+    }
+    if let Some(tiff_config) = config.tiff.as_mut() {
+        tiff_config.size = None;
+        tiff_config.ops = None;
+    }
+
+    config
+}
+
+/// 出力先と同じディレクトリに file_name（{stem}, {width}, {height}, {ext}）で出力
+fn variant_path(
+    output_path: &str,
+    file_name: &str,
+    dimensions: &Dimensions,
+    file_type: &FileType,
+) -> String {
+    let path = Path::new(output_path);
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let file_name = file_name
+        .replace("{stem}", &stem)
+        .replace("{width}", &dimensions.width.to_string())
+        .replace("{height}", &dimensions.height.to_string())
+        .replace("{ext}", file_type.extension());
+
+    path.with_file_name(file_name).to_string_lossy().into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn variant_path_replaces_placeholders() {
+        let dimensions = Dimensions { width: 640, height: 480 };
+
+        assert_eq!(
+            variant_path(
+                "dist/photo.jpg",
+                "{stem}-{width}x{height}.{ext}",
+                &dimensions,
+                &FileType::WEBP
+            ),
+            "dist/photo-640x480.webp"
+        );
+        assert_eq!(
+            variant_path("photo.jpg", "{stem}@{width}w.{ext}", &dimensions, &FileType::JPEG),
+            "photo@640w.jpg"
+        );
+    }
+
+    #[test]
+    fn variant_path_keeps_literal_names() {
+        let dimensions = Dimensions { width: 320, height: 240 };
+
+        assert_eq!(
+            variant_path("dist/photo.png", "thumbnail.png", &dimensions, &FileType::PNG),
+            "dist/thumbnail.png"
+        );
+    }
+
+    #[test]
+    fn without_transforms_clears_size_and_ops() {
+        let config: Config = serde_json::from_str(
+            r#"{
+                "png": {"quality": 80, "strip": "all", "interlacing": "none", "optimize_alpha": false,
+                        "size": {"width": 100, "filter": "lanczos3"}, "ops": [{"rotate": 90}],
+                        "filters": {"grayscale": true}},
+                "heif": {"quality": 50, "size": {"width": 100, "height": 100}}
+            }"#,
+        )
+        .unwrap();
+        let config = without_transforms(&config);
+        let png_config = config.png.as_ref().unwrap();
+
+        assert!(png_config.size.is_none());
+        assert!(png_config.ops.is_none());
+        assert!(png_config.filters.is_some());
+        assert!(config.heif.as_ref().unwrap().size.is_none());
+    }
+
+    fn responsive_config(variants: &[(u32, Option<&[&str]>)]) -> Config {
+        let variants = variants
+            .iter()
+            .map(|(width, formats)| VariantConfig {
+                width: *width,
+                formats: formats.map(|formats| formats.iter().map(|format| format.to_string()).collect()),
+            })
+            .collect();
+
+        Config {
+            responsive: Some(ResponsiveConfig {
+                variants,
+                ..ResponsiveConfig::default()
+            }),
+            ..Config::default()
+        }
+    }
+
+    #[test]
+    fn variant_types_accept_still_image_formats() {
+        let formats = ["png", "jpg", "webp", "heic", "avif", "jxl", "tif"].map(String::from);
+
+        assert_eq!(
+            variant_types(Some(&formats), &FileType::GIF).unwrap(),
+            [FileType::PNG, FileType::JPEG, FileType::WEBP, FileType::HEIF, FileType::AVIF, FileType::JXL, FileType::TIFF]
+        );
+        assert_eq!(variant_types(None, &FileType::WEBP).unwrap(), [FileType::WEBP]);
+    }
+
+    #[test]
+    fn variant_types_reject_unsupported_formats() {
+        for format in ["gif", "bmp", "tga", "pdf", "ico", "svg", "unknown"] {
+            let error = variant_types(Some(&[format.to_string()]), &FileType::PNG).unwrap_err();
+
+            assert!(
+                matches!(error.downcast_ref::<CompressorError>(), Some(CompressorError::UnsupportedConversion(_))),
+                "{}",
+                format
+            );
+        }
+        assert!(variant_types(None, &FileType::GIF).is_err());
+    }
+
+    #[test]
+    fn unsupported_format_fails_before_encoding() {
+        let image = DynamicImage::ImageRgba8(image::RgbaImage::new(8, 8));
+        let config = responsive_config(&[(8, Some(&["png"])), (4, Some(&["webp", "gif"]))]);
+
+        assert!(encode_variants(&config, &image, "dist/photo.png", &FileType::PNG).is_err());
+
+        let config = responsive_config(&[(8, Some(&["png"])), (4, None)]);
+        assert_eq!(encode_variants(&config, &image, "dist/photo.png", &FileType::PNG).unwrap().len(), 2);
+    }
+}
@@ -1,16 +1,13 @@
 use crate::error::CompressorError;
-use crate::io::file::read_file_bytes;
 use anyhow::anyhow;
 use usvg::{Indent, WriteOptions};
 
-pub fn compress(input_path: &String) -> anyhow::Result<Vec<u8>> {
+pub fn compress(input: &[u8]) -> anyhow::Result<Vec<u8>> {
     let options = usvg::Options {
         ..Default::default()
     };
 
-    let buffer = read_file_bytes(input_path)?;
-
-    let tree = usvg::Tree::from_data(&buffer, &options)
+    let tree = usvg::Tree::from_data(input, &options)
         .map_err(|e| anyhow!(CompressorError::SvgCompressError(e.to_string())))?;
 
     let xml = tree.to_string(&WriteOptions{
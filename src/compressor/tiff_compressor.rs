@@ -0,0 +1,390 @@
+use crate::config_json::TiffConfig;
+use crate::error::CompressorError;
+use crate::imaging::{color, filter, transform};
+use anyhow::anyhow;
+use image::{DynamicImage, ImageBuffer, Pixel};
+use std::io::{Cursor, Seek, Write};
+use tiff::decoder::{Decoder, DecodingResult, Limits};
+use tiff::encoder::colortype::{self, ColorType};
+use tiff::encoder::compression::{Compression, Deflate, DeflateLevel, Lzw, Packbits, Uncompressed};
+use tiff::encoder::{TiffEncoder, TiffValue};
+use tiff::tags::Tag;
+
+/// InterColorProfile（埋め込み ICC プロファイル）
+const ICC_PROFILE_TAG: Tag = Tag::Unknown(34675);
+
+/// TIFF の1ページ
+struct Page {
+    width: u32,
+    height: u32,
+    pixels: Pixels,
+    icc_profile: Option<Vec<u8>>,
+}
+
+/// ページの画素（ビット深度と CMYK を変換せずに保持）
+enum Pixels {
+    Gray8(Vec<u8>),
+    Gray16(Vec<u16>),
+    Rgb8(Vec<u8>),
+    Rgb16(Vec<u16>),
+    Rgba8(Vec<u8>),
+    Rgba16(Vec<u16>),
+    Cmyk8(Vec<u8>),
+    Cmyk16(Vec<u16>),
+}
+
+/// 全ページを再圧縮（ビット深度・CMYK・ICC プロファイルを保持）
+pub fn compress(config: Option<&TiffConfig>, input: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let default_config = TiffConfig::default();
+    let config = config.unwrap_or(&default_config);
+
+    let mut pages = decode_pages(input)?;
+
+    // NOTE: Pages are converted only when transformed to keep their bit depth and CMYK
+    if config.ops.is_some() || config.size.is_some() || config.filters.is_some() {
+        pages = pages
+            .into_iter()
+            .map(|page| Ok(Page::from_image(transform_image(config, page.into_image()?)?)))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+    }
+
+    encode_pages(&pages, config.compression.as_deref())
+}
+
+pub fn compress_image(
+    config: Option<&TiffConfig>,
+    dynamic_image: DynamicImage,
+) -> anyhow::Result<Vec<u8>> {
+    let default_config = TiffConfig::default();
+    let config = config.unwrap_or(&default_config);
+
+    let dynamic_image = transform_image(config, dynamic_image)?;
+
+    encode_pages(&[Page::from_image(dynamic_image)], config.compression.as_deref())
+}
+
+/// ops・size・filters を適用
+fn transform_image(config: &TiffConfig, mut dynamic_image: DynamicImage) -> anyhow::Result<DynamicImage> {
+    if let Some(ops) = config.ops.as_deref() {
+        dynamic_image = transform::apply_ops(dynamic_image, ops)?;
+    }
+
+    if let Some(size_config) = config.size.as_ref() {
+        dynamic_image = transform::resize_image(&dynamic_image, size_config)?;
+    }
+
+    if let Some(filters_config) = config.filters.as_ref() {
+        dynamic_image = filter::apply_filters(dynamic_image, filters_config)?;
+    }
+
+    Ok(dynamic_image)
+}
+
+/// 先頭ページをデコード（CMYK は sRGB に変換）
+pub fn decode_image(input: &[u8]) -> anyhow::Result<DynamicImage> {
+    let mut decoder = decoder(input)?;
+
+    read_page(&mut decoder)?.into_image()
+}
+
+/// 先頭ページの幅と高さ
+pub fn dimensions(input: &[u8]) -> anyhow::Result<(u32, u32)> {
+    decoder(input)?.dimensions().map_err(decode_error)
+}
+
+pub fn page_count(input: &[u8]) -> anyhow::Result<u32> {
+    let mut decoder = decoder(input)?;
+    let mut page_count = 1;
+
+    while decoder.more_images() {
+        decoder.next_image().map_err(decode_error)?;
+        page_count += 1;
+    }
+
+    Ok(page_count)
+}
+
+/// 指定したページ（1 から）を非圧縮の単一ページ TIFF として取り出す
+pub fn extract_page(input: &[u8], page: u32) -> anyhow::Result<Vec<u8>> {
+    let page_count = page_count(input)?;
+    if page == 0 || page > page_count {
+        return Err(decode_error(format!(
+            "Page {} not found ({} page(s))",
+            page, page_count
+        )));
+    }
+
+    let mut decoder = decoder(input)?;
+    decoder
+        .seek_to_image(page as usize - 1)
+        .map_err(decode_error)?;
+
+    encode_pages(&[read_page(&mut decoder)?], Some("none"))
+}
+
+fn decoder(input: &[u8]) -> anyhow::Result<Decoder<Cursor<&[u8]>>> {
+    // NOTE: The default limits reject large photographs (e.g. 16-bit RGB over 256 MB)
+    Ok(Decoder::new(Cursor::new(input))
+        .map_err(decode_error)?
+        .with_limits(Limits::unlimited()))
+}
+
+fn decode_pages(input: &[u8]) -> anyhow::Result<Vec<Page>> {
+    let mut decoder = decoder(input)?;
+    let mut pages = vec![read_page(&mut decoder)?];
+
+    while decoder.more_images() {
+        decoder.next_image().map_err(decode_error)?;
+        pages.push(read_page(&mut decoder)?);
+    }
+
+    Ok(pages)
+}
+
+fn read_page(decoder: &mut Decoder<Cursor<&[u8]>>) -> anyhow::Result<Page> {
+    let (width, height) = decoder.dimensions().map_err(decode_error)?;
+    let color_type = decoder.colortype().map_err(decode_error)?;
+    let icc_profile = decoder
+        .find_tag(ICC_PROFILE_TAG)
+        .ok()
+        .flatten()
+        .and_then(|value| value.into_u8_vec().ok());
+
+    let pixels = match (color_type, decoder.read_image().map_err(decode_error)?) {
+        (tiff::ColorType::Gray(8), DecodingResult::U8(data)) => Pixels::Gray8(data),
+        (tiff::ColorType::Gray(16), DecodingResult::U16(data)) => Pixels::Gray16(data),
+        (tiff::ColorType::RGB(8), DecodingResult::U8(data)) => Pixels::Rgb8(data),
+        (tiff::ColorType::RGB(16), DecodingResult::U16(data)) => Pixels::Rgb16(data),
+        (tiff::ColorType::RGBA(8), DecodingResult::U8(data)) => Pixels::Rgba8(data),
+        (tiff::ColorType::RGBA(16), DecodingResult::U16(data)) => Pixels::Rgba16(data),
+        (tiff::ColorType::CMYK(8), DecodingResult::U8(data)) => Pixels::Cmyk8(data),
+        (tiff::ColorType::CMYK(16), DecodingResult::U16(data)) => Pixels::Cmyk16(data),
+        // NOTE: Gray with alpha is kept as RGBA (the encoder has no gray-alpha color type)
+        (tiff::ColorType::GrayA(8), DecodingResult::U8(data)) => {
+            let image = DynamicImage::ImageLumaA8(image_buffer(width, height, data)?);
+            Pixels::Rgba8(image.to_rgba8().into_raw())
+        }
+        (tiff::ColorType::GrayA(16), DecodingResult::U16(data)) => {
+            let image = DynamicImage::ImageLumaA16(image_buffer(width, height, data)?);
+            Pixels::Rgba16(image.to_rgba16().into_raw())
+        }
+        (color_type, _) => {
+            return Err(decode_error(format!(
+                "Unsupported color type: {:?}",
+                color_type
+            )));
+        }
+    };
+
+    Ok(Page {
+        width,
+        height,
+        pixels,
+        icc_profile,
+    })
+}
+
+fn image_buffer<P: Pixel>(
+    width: u32,
+    height: u32,
+    data: Vec<P::Subpixel>,
+) -> anyhow::Result<ImageBuffer<P, Vec<P::Subpixel>>> {
+    ImageBuffer::from_raw(width, height, data)
+        .ok_or_else(|| decode_error("Image data is shorter than its dimensions"))
+}
+
+impl Page {
+    fn from_image(image: DynamicImage) -> Self {
+        let (width, height) = (image.width(), image.height());
+        let pixels = match image {
+            DynamicImage::ImageLuma8(image) => Pixels::Gray8(image.into_raw()),
+            DynamicImage::ImageLuma16(image) => Pixels::Gray16(image.into_raw()),
+            DynamicImage::ImageRgb8(image) => Pixels::Rgb8(image.into_raw()),
+            DynamicImage::ImageRgb16(image) => Pixels::Rgb16(image.into_raw()),
+            DynamicImage::ImageRgba16(image) => Pixels::Rgba16(image.into_raw()),
+            DynamicImage::ImageLumaA16(_) => Pixels::Rgba16(image.to_rgba16().into_raw()),
+            image => Pixels::Rgba8(image.to_rgba8().into_raw()),
+        };
+
+        Self {
+            width,
+            height,
+            pixels,
+            icc_profile: None,
+        }
+    }
+
+    fn into_image(self) -> anyhow::Result<DynamicImage> {
+        let (width, height) = (self.width, self.height);
+        let icc_profile = self.icc_profile.as_deref();
+
+        Ok(match self.pixels {
+            Pixels::Gray8(data) => DynamicImage::ImageLuma8(image_buffer(width, height, data)?),
+            Pixels::Gray16(data) => DynamicImage::ImageLuma16(image_buffer(width, height, data)?),
+            Pixels::Rgb8(data) => DynamicImage::ImageRgb8(image_buffer(width, height, data)?),
+            Pixels::Rgb16(data) => DynamicImage::ImageRgb16(image_buffer(width, height, data)?),
+            Pixels::Rgba8(data) => DynamicImage::ImageRgba8(image_buffer(width, height, data)?),
+            Pixels::Rgba16(data) => DynamicImage::ImageRgba16(image_buffer(width, height, data)?),
+            Pixels::Cmyk8(data) => {
+                let rgb_data = color::cmyk8_to_rgb8(&data, icc_profile)?;
+                DynamicImage::ImageRgb8(image_buffer(width, height, rgb_data)?)
+            }
+            Pixels::Cmyk16(data) => {
+                let rgb_data = color::cmyk16_to_rgb16(&data, icc_profile)?;
+                DynamicImage::ImageRgb16(image_buffer(width, height, rgb_data)?)
+            }
+        })
+    }
+}
+
+/// 全ページを指定した圧縮方式（deflate, lzw, packbits, none）で書き出す
+fn encode_pages(pages: &[Page], compression: Option<&str>) -> anyhow::Result<Vec<u8>> {
+    let mut output = Cursor::new(Vec::new());
+    let mut encoder = TiffEncoder::new(&mut output).map_err(encode_error)?;
+
+    for page in pages {
+        match compression {
+            Some("lzw") => write_page(&mut encoder, page, Lzw),
+            Some("packbits") => write_page(&mut encoder, page, Packbits),
+            Some("none") => write_page(&mut encoder, page, Uncompressed),
+            _ => write_page(&mut encoder, page, Deflate::with_level(DeflateLevel::Best)),
+        }?;
+    }
+
+    Ok(output.into_inner())
+}
+
+fn write_page<W: Write + Seek, D: Compression>(
+    encoder: &mut TiffEncoder<W>,
+    page: &Page,
+    compression: D,
+) -> anyhow::Result<()> {
+    match &page.pixels {
+        Pixels::Gray8(data) => write_image::<_, colortype::Gray8, _>(encoder, page, data, compression),
+        Pixels::Gray16(data) => write_image::<_, colortype::Gray16, _>(encoder, page, data, compression),
+        Pixels::Rgb8(data) => write_image::<_, colortype::RGB8, _>(encoder, page, data, compression),
+        Pixels::Rgb16(data) => write_image::<_, colortype::RGB16, _>(encoder, page, data, compression),
+        Pixels::Rgba8(data) => write_image::<_, colortype::RGBA8, _>(encoder, page, data, compression),
+        Pixels::Rgba16(data) => write_image::<_, colortype::RGBA16, _>(encoder, page, data, compression),
+        Pixels::Cmyk8(data) => write_image::<_, colortype::CMYK8, _>(encoder, page, data, compression),
+        Pixels::Cmyk16(data) => write_image::<_, colortype::CMYK16, _>(encoder, page, data, compression),
+    }
+}
+
+fn write_image<W: Write + Seek, C: ColorType, D: Compression>(
+    encoder: &mut TiffEncoder<W>,
+    page: &Page,
+    data: &[C::Inner],
+    compression: D,
+) -> anyhow::Result<()>
+where
+    [C::Inner]: TiffValue,
+{
+    let mut image = encoder
+        .new_image_with_compression::<C, D>(page.width, page.height, compression)
+        .map_err(encode_error)?;
+
+    if let Some(icc_profile) = page.icc_profile.as_deref() {
+        image
+            .encoder()
+            .write_tag(ICC_PROFILE_TAG, icc_profile)
+            .map_err(encode_error)?;
+    }
+
+    image.write_data(data).map_err(encode_error)
+}
+
+fn encode_error(e: impl ToString) -> anyhow::Error {
+    anyhow!(CompressorError::TiffCompressError(e.to_string()))
+}
+
+fn decode_error(e: impl ToString) -> anyhow::Error {
+    anyhow!(CompressorError::ImageFormatError(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::GenericImageView;
+
+    /// 8bit グレー（4x2）、16bit RGB（3x3）、8bit CMYK（2x2）の3ページ
+    fn pages() -> Vec<Page> {
+        let page = |width, height, pixels| Page { width, height, pixels, icc_profile: None };
+
+        vec![
+            page(4, 2, Pixels::Gray8((0..8).map(|value| value * 30).collect())),
+            page(3, 3, Pixels::Rgb16((0..27).map(|value| value * 2000).collect())),
+            page(2, 2, Pixels::Cmyk8(vec![0; 16])),
+        ]
+    }
+
+    fn color_types(input: &[u8]) -> Vec<tiff::ColorType> {
+        let mut decoder = decoder(input).unwrap();
+        let mut color_types = vec![decoder.colortype().unwrap()];
+        while decoder.more_images() {
+            decoder.next_image().unwrap();
+            color_types.push(decoder.colortype().unwrap());
+        }
+        color_types
+    }
+
+    #[test]
+    fn compress_keeps_every_page_bit_depth_and_cmyk() {
+        let input = encode_pages(&pages(), Some("none")).unwrap();
+
+        for compression in ["deflate", "lzw", "packbits", "none"] {
+            let config = TiffConfig {
+                compression: Some(compression.to_string()),
+                ..TiffConfig::default()
+            };
+            let output = compress(Some(&config), &input).unwrap();
+
+            assert_eq!(
+                color_types(&output),
+                [tiff::ColorType::Gray(8), tiff::ColorType::RGB(16), tiff::ColorType::CMYK(8)],
+                "{}",
+                compression
+            );
+            let pages = decode_pages(&output).unwrap();
+            assert!(matches!(&pages[1].pixels, Pixels::Rgb16(data) if data[26] == 52000), "{}", compression);
+        }
+    }
+
+    #[test]
+    fn compress_transforms_every_page() {
+        let input = encode_pages(&pages(), None).unwrap();
+        let config = TiffConfig {
+            ops: serde_json::from_str(r#"[{"rotate": 90}]"#).unwrap(),
+            ..TiffConfig::default()
+        };
+        let pages = decode_pages(&compress(Some(&config), &input).unwrap()).unwrap();
+
+        let sizes: Vec<(u32, u32)> = pages.iter().map(|page| (page.width, page.height)).collect();
+        assert_eq!(sizes, [(2, 4), (3, 3), (2, 2)]);
+    }
+
+    #[test]
+    fn extract_page_selects_a_single_page() {
+        let input = encode_pages(&pages(), None).unwrap();
+
+        assert_eq!(page_count(&input).unwrap(), 3);
+        let page = extract_page(&input, 2).unwrap();
+        assert_eq!(page_count(&page).unwrap(), 1);
+        assert_eq!(dimensions(&page).unwrap(), (3, 3));
+        assert_eq!(decode_image(&page).unwrap().to_rgb16().get_pixel(2, 2).0, [48000, 50000, 52000]);
+
+        assert!(extract_page(&input, 0).is_err());
+        assert!(extract_page(&input, 4).is_err());
+    }
+
+    #[test]
+    fn cmyk_page_is_decoded_as_rgb() {
+        let input = encode_pages(&pages(), None).unwrap();
+        let image = decode_image(&extract_page(&input, 3).unwrap()).unwrap();
+
+        assert!(matches!(image, DynamicImage::ImageRgb8(_)));
+        assert_eq!(image.dimensions(), (2, 2));
+        // NOTE: No ink is paper white
+        assert!(image.to_rgb8().pixels().all(|pixel| pixel.0.iter().all(|&value| value > 240)), "{:?}", image.to_rgb8().get_pixel(0, 0));
+    }
+}
@@ -1,15 +1,197 @@
 use crate::config_json::WebpConfig;
 use crate::error::CompressorError;
-use crate::imaging::transform;
-use crate::io::file::read_image_from_file;
+use crate::file_type::FileType;
+use crate::imaging::animation::{Animation, Frame};
+use crate::imaging::{filter, orientation, transform};
+use crate::io::file::read_image_from_bytes;
 use anyhow::anyhow;
+use image::{DynamicImage, GenericImageView};
 use std::ffi::c_int;
+use webp::{AnimDecoder, AnimEncoder, AnimFrame};
 
-pub fn compress(config: Option<&WebpConfig>, input_path: &String) -> anyhow::Result<Vec<u8>> {
+pub fn compress(config: Option<&WebpConfig>, input: &[u8]) -> anyhow::Result<Vec<u8>> {
+    if is_animated(input) {
+        let animation = decode_animation(input)?;
+
+        return compress_animation(config, animation);
+    }
+
+    // NOTE: The EXIF chunk is not written again, so the orientation is applied to the pixels
+    let dynamic_image = orientation::apply(
+        read_image_from_bytes(input)?,
+        orientation::read(&FileType::WEBP, input),
+    );
+
+    compress_image(config, dynamic_image)
+}
+
+pub fn compress_image(
+    config: Option<&WebpConfig>,
+    mut dynamic_image: DynamicImage,
+) -> anyhow::Result<Vec<u8>> {
+    if let Some(ops) = config.and_then(|config| config.ops.as_deref()) {
+        dynamic_image = transform::apply_ops(dynamic_image, ops)?;
+    }
+
+    if let Some(size_config) = config.and_then(|config| config.size.as_ref()) {
+        dynamic_image = transform::resize_image(&dynamic_image, size_config)?;
+    }
+
+    if let Some(filters_config) = config.and_then(|config| config.filters.as_ref()) {
+        dynamic_image = filter::apply_filters(dynamic_image, filters_config)?;
+    }
+
+    // NOTE: libwebp only accepts 8-bit RGB/RGBA (e.g. 16-bit or grayscale TIFF input)
+    if !matches!(dynamic_image, DynamicImage::ImageRgb8(_) | DynamicImage::ImageRgba8(_)) {
+        dynamic_image = if dynamic_image.color().has_alpha() {
+            DynamicImage::ImageRgba8(dynamic_image.to_rgba8())
+        } else {
+            DynamicImage::ImageRgb8(dynamic_image.to_rgb8())
+        };
+    }
+
+    let encoder = webp::Encoder::from_image(&dynamic_image)
+        .map_err(|e| anyhow!(CompressorError::WebpCompressError(e.into())))?;
+
+    let webp_config = webp_config(config)?;
+
+    let webp_data = encoder.encode_advanced(&webp_config);
+    match webp_data {
+        Ok(webp_data) => Ok(webp_data.to_vec()),
+        Err(e) => Err(anyhow!(CompressorError::WebpCompressError(format!("Failed to encode: {:?}", e)))),
+    }
+}
+
+/// VP8X チャンクのアニメーションフラグを確認
+pub fn is_animated(input: &[u8]) -> bool {
+    input.len() > 20 && &input[12..16] == b"VP8X" && input[20] & 0x02 != 0
+}
+
+/// アニメーション WebP の全フレームをデコード
+pub fn decode_animation(input: &[u8]) -> anyhow::Result<Animation> {
+    let decoded = AnimDecoder::new(input)
+        .decode()
+        .map_err(|e| anyhow!(CompressorError::WebpCompressError(e)))?;
+
+    // NOTE: The decoder returns the end time of each frame
+    let mut previous_timestamp = 0;
+    let frames = decoded
+        .into_iter()
+        .map(|frame| {
+            let delay_ms = (frame.get_time_ms() - previous_timestamp).max(0) as u32;
+            previous_timestamp = frame.get_time_ms();
+
+            Frame {
+                image: (&frame).into(),
+                delay_ms,
+            }
+        })
+        .collect();
+
+    Ok(Animation {
+        frames,
+        loop_count: decoded.loop_count,
+    })
+}
+
+/// アニメーション WebP としてエンコード（loop_count が未指定の場合は元のループ回数を維持）
+pub fn compress_animation(
+    config: Option<&WebpConfig>,
+    mut animation: Animation,
+) -> anyhow::Result<Vec<u8>> {
+    if let Some(ops) = config.and_then(|config| config.ops.as_deref()) {
+        animation = animation.apply_ops(ops)?;
+    }
+
+    if let Some(size_config) = config.and_then(|config| config.size.as_ref()) {
+        animation = animation.resize(size_config)?;
+    }
+
+    if let Some(filters_config) = config.and_then(|config| config.filters.as_ref()) {
+        animation = animation.apply_filters(filters_config)?;
+    }
+
+    let (width, height) = match animation.frames.first() {
+        Some(frame) => frame.image.dimensions(),
+        None => {
+            return Err(anyhow!(CompressorError::WebpCompressError(
+                "No frames found in animation".to_string()
+            )));
+        }
+    };
+
+    let images: Vec<DynamicImage> = animation
+        .frames
+        .iter()
+        .map(|frame| DynamicImage::ImageRgba8(frame.image.to_rgba8()))
+        .collect();
+
+    let webp_config = webp_config(config)?;
+    let mut encoder = AnimEncoder::new(width, height, &webp_config);
+
+    let loop_count = config
+        .and_then(|config| config.loop_count)
+        .map_or(animation.loop_count, u32::from);
+    encoder.set_loop_count(loop_count as i32);
+
+    // NOTE: The encoder takes the start time of each frame
+    let mut timestamp = 0;
+    for (frame, image) in animation.frames.iter().zip(images.iter()) {
+        let anim_frame = AnimFrame::from_image(image, timestamp)
+            .map_err(|e| anyhow!(CompressorError::WebpCompressError(e.to_string())))?;
+        encoder.add_frame(anim_frame);
+        timestamp += frame.delay_ms as i32;
+    }
+
+    let mut webp_data = match encoder.try_encode() {
+        Ok(webp_data) => webp_data.to_vec(),
+        Err(e) => return Err(anyhow!(CompressorError::WebpCompressError(format!("Failed to encode: {:?}", e)))),
+    };
+
+    set_last_frame_duration(&mut webp_data, timestamp as u32);
+
+    Ok(webp_data)
+}
+
+/// 最後のフレームの表示時間を設定（全フレームの合計が total_ms になるように）
+///
+/// NOTE: The webp crate finishes the animation with a zero timestamp, so libwebp falls back to
+/// the average duration for the last frame
+fn set_last_frame_duration(webp_data: &mut [u8], total_ms: u32) {
+    let mut offset = 12;
+    let mut durations = Vec::new();
+
+    while offset + 8 <= webp_data.len() {
+        let size = u32::from_le_bytes(webp_data[offset + 4..offset + 8].try_into().unwrap()) as usize;
+
+        if &webp_data[offset..offset + 4] == b"ANMF" && offset + 8 + 15 <= webp_data.len() {
+            let position = offset + 8 + 12;
+            let duration = u32::from_le_bytes([
+                webp_data[position],
+                webp_data[position + 1],
+                webp_data[position + 2],
+                0,
+            ]);
+            durations.push((position, duration));
+        }
+
+        offset += 8 + size + (size & 1);
+    }
+
+    let Some(((position, _), previous)) = durations.split_last() else {
+        return;
+    };
+
+    let elapsed: u32 = previous.iter().map(|(_, duration)| duration).sum();
+    let duration = total_ms.saturating_sub(elapsed).min(0xff_ffff);
+
+    webp_data[*position..*position + 3].copy_from_slice(&duration.to_le_bytes()[..3]);
+}
+
+fn webp_config(config: Option<&WebpConfig>) -> anyhow::Result<webp::WebPConfig> {
     let default_config = WebpConfig::default();
     let (
         quality,
-        size,
         method,
         target_size,
         target_psnr,
@@ -22,7 +204,6 @@ pub fn compress(config: Option<&WebpConfig>, input_path: &String) -> anyhow::Res
     ) = match config {
         Some(config) => (
             config.quality,
-            config.size.as_ref(),
             config.method,
             config.target_size,
             config.target_psnr,
@@ -35,7 +216,6 @@ pub fn compress(config: Option<&WebpConfig>, input_path: &String) -> anyhow::Res
         ),
         None => (
             default_config.quality,
-            default_config.size.as_ref(),
             default_config.method,
             default_config.target_size,
             default_config.target_psnr,
@@ -48,16 +228,8 @@ pub fn compress(config: Option<&WebpConfig>, input_path: &String) -> anyhow::Res
         ),
     };
 
-    let mut dynamic_image = read_image_from_file(input_path)?;
-
-    if let Some(size_config) = size {
-        dynamic_image = transform::resize_image(&dynamic_image, size_config);
-    }
-
-    let encoder = webp::Encoder::from_image(&dynamic_image)
-        .map_err(|e| anyhow!(CompressorError::WebpCompressError(e.into())))?;
-
-    let mut webp_config = webp::WebPConfig::new().unwrap();
+    let mut webp_config = webp::WebPConfig::new()
+        .map_err(|_| anyhow!(CompressorError::WebpCompressError("Failed to create config".to_string())))?;
     webp_config.quality = quality as f32;
 
     if let Some(method) = method {
@@ -96,9 +268,88 @@ pub fn compress(config: Option<&WebpConfig>, input_path: &String) -> anyhow::Res
         webp_config.autofilter = autofilter as c_int;
     }
 
-    let webp_data = encoder.encode_advanced(&webp_config);
-    match webp_data {
-        Ok(webp_data) => Ok(webp_data.to_vec()),
-        Err(e) => Err(anyhow!(CompressorError::WebpCompressError(format!("Failed to encode: {:?}", e)))),
+    Ok(webp_config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgba, RgbaImage};
+
+    fn animation(delays: &[u32]) -> Animation {
+        let frames = delays
+            .iter()
+            .enumerate()
+            .map(|(index, delay_ms)| Frame {
+                image: DynamicImage::ImageRgba8(RgbaImage::from_pixel(4, 4, Rgba([index as u8 * 80, 0, 0, 255]))),
+                delay_ms: *delay_ms,
+            })
+            .collect();
+
+        Animation { frames, loop_count: 0 }
+    }
+
+    /// RIFF 内のチャンク（名前、データの範囲）
+    fn chunks(webp_data: &[u8]) -> Vec<(String, usize, usize)> {
+        let mut offset = 12;
+        let mut chunks = Vec::new();
+
+        while offset < webp_data.len() {
+            let size = u32::from_le_bytes(webp_data[offset + 4..offset + 8].try_into().unwrap()) as usize;
+            let name = String::from_utf8_lossy(&webp_data[offset..offset + 4]).into_owned();
+            chunks.push((name, offset + 8, offset + 8 + size));
+            offset += 8 + size + (size & 1);
+        }
+
+        // NOTE: The chunks must end exactly at the end of the RIFF data
+        assert_eq!(offset, webp_data.len());
+        chunks
+    }
+
+    fn frame_durations(webp_data: &[u8]) -> Vec<u32> {
+        chunks(webp_data)
+            .into_iter()
+            .filter(|(name, _, _)| name == "ANMF")
+            .map(|(_, start, _)| u32::from_le_bytes([webp_data[start + 12], webp_data[start + 13], webp_data[start + 14], 0]))
+            .collect()
+    }
+
+    #[test]
+    fn last_frame_keeps_its_duration() {
+        let webp_data = compress_animation(None, animation(&[50, 200, 300])).unwrap();
+
+        assert_eq!(frame_durations(&webp_data), [50, 200, 300]);
+        assert_eq!(
+            u32::from_le_bytes(webp_data[4..8].try_into().unwrap()) as usize,
+            webp_data.len() - 8
+        );
+
+        let delays: Vec<u32> = decode_animation(&webp_data).unwrap().frames.iter().map(|frame| frame.delay_ms).collect();
+        assert_eq!(delays, [50, 200, 300]);
+    }
+
+    #[test]
+    fn set_last_frame_duration_patches_only_the_duration() {
+        let webp_data = compress_animation(None, animation(&[100, 100, 700])).unwrap();
+        let mut patched = webp_data.clone();
+        set_last_frame_duration(&mut patched, 1000);
+
+        assert_eq!(frame_durations(&patched), [100, 100, 800]);
+        assert_eq!(chunks(&patched), chunks(&webp_data));
+
+        // NOTE: Only the 24-bit duration of the last ANMF chunk changes
+        let (_, start, _) = chunks(&patched).into_iter().rfind(|(name, _, _)| name == "ANMF").unwrap();
+        let changed: Vec<usize> = (0..patched.len()).filter(|&index| patched[index] != webp_data[index]).collect();
+        assert!(changed.iter().all(|index| (start + 12..start + 15).contains(index)), "{:?}", changed);
+    }
+
+    #[test]
+    fn set_last_frame_duration_ignores_still_images() {
+        let image = DynamicImage::ImageRgba8(RgbaImage::from_pixel(4, 4, Rgba([0, 0, 255, 255])));
+        let webp_data = compress_image(None, image).unwrap();
+        let mut patched = webp_data.clone();
+        set_last_frame_duration(&mut patched, 1000);
+
+        assert_eq!(patched, webp_data);
     }
 }
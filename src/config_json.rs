@@ -1,46 +1,144 @@
 use crate::error::CompressorError;
+use crate::file_type::FileType;
 use anyhow::anyhow;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::fs::read_to_string;
 
 const JSON_SCHEMA_BYTES: &'static [u8] = include_bytes!("../schema/schema.json");
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SizeFilterConfig {
+    /// fit, cover, exact, width-only では必須
+    pub width: Option<u32>,
+    /// fit, cover, exact, height-only では必須
+    pub height: Option<u32>,
+    pub filter: String,
+    /// fit（既定）, cover, exact, width-only, height-only
+    pub mode: Option<String>,
+    /// true: 元のサイズより大きくしない
+    pub no_upscale: Option<bool>,
+    /// cover で切り抜く位置（center, north, northeast, .., entropy, saliency）
+    pub gravity: Option<String>,
+    /// cover で切り抜く中心（指定した場合は gravity より優先）
+    pub focus: Option<FocusConfig>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FocusConfig {
+    /// 0.0: 左端 .. 1.0: 右端
+    pub x: f64,
+    /// 0.0: 上端 .. 1.0: 下端
+    pub y: f64,
+}
+
+/// 画像に順に適用する操作（{"crop": {..}} のように操作名をキーとする）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OpConfig {
+    Crop(CropConfig),
+    /// 時計回りの角度（90, 180, 270）
+    Rotate(u16),
+    /// horizontal, vertical
+    Flip(String),
+    Pad(PadConfig),
+    Trim(TrimConfig),
+    Resize(SizeFilterConfig),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CropConfig {
+    pub x: u32,
+    pub y: u32,
+    /// 画像からはみ出す場合は右端まで
     pub width: u32,
+    /// 画像からはみ出す場合は下端まで
     pub height: u32,
-    pub filter: String,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PadConfig {
+    pub top: Option<u32>,
+    pub right: Option<u32>,
+    pub bottom: Option<u32>,
+    pub left: Option<u32>,
+    /// 最小の幅（足りない分は左右に均等に追加）
+    pub width: Option<u32>,
+    /// 最小の高さ（足りない分は上下に均等に追加）
+    pub height: Option<u32>,
+    /// true: 長辺に合わせて正方形にする
+    pub square: Option<bool>,
+    /// 余白の色（未指定の場合は透過）
+    pub background: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrimConfig {
+    /// 背景色とみなす色の差（0 .. 100 %）
+    pub fuzz: Option<f64>,
+    /// 取り除く背景色（未指定の場合は左上の画素の色）
+    pub background: Option<String>,
+}
+
+/// リサイズ後に適用するフィルター
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FiltersConfig {
+    /// ガウスぼかしの半径（標準偏差、px）
+    pub blur: Option<f32>,
+    pub unsharp: Option<UnsharpConfig>,
+    /// -100 .. 100（%）
+    pub brightness: Option<f32>,
+    /// -100 .. 100（%）
+    pub contrast: Option<f32>,
+    /// 1.0: 変更なし（大きいほど明るい）
+    pub gamma: Option<f32>,
+    pub grayscale: Option<bool>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UnsharpConfig {
+    /// ぼかしの半径（標準偏差、px）
+    pub radius: f32,
+    /// 差分を加算する強さ（1.0: 100%）
+    pub amount: Option<f32>,
+    /// 差がこの値（0 .. 255）未満の画素は変更しない
+    pub threshold: Option<u8>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SizeConfig {
     pub width: u32,
     pub height: u32,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LibdeflaterConfig {
     pub compression: u8,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ZopfliConfig {
     pub iterations: u8,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LossyConfig {
     pub quality_min: u8,
     pub quality_max: u8,
     pub speed: Option<i32>,
     pub colors: Option<u32>,
+    pub target_bytes: Option<u64>,
+    pub min_ssim: Option<f64>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PngConfig {
     pub quality: u8,
     pub size: Option<SizeFilterConfig>,
+    /// size の前に順に適用する操作
+    pub ops: Option<Vec<OpConfig>>,
+    /// size の後に適用するフィルター
+    pub filters: Option<FiltersConfig>,
     pub strip: String,
     pub interlacing: String,
     pub optimize_alpha: bool,
@@ -49,22 +147,33 @@ pub struct PngConfig {
     pub lossy: Option<LossyConfig>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct JpegConfig {
     pub quality: u8,
     pub size: Option<SizeFilterConfig>,
+    /// size の前に順に適用する操作
+    pub ops: Option<Vec<OpConfig>>,
+    /// size の後に適用するフィルター
+    pub filters: Option<FiltersConfig>,
     pub scan_optimization_mode: Option<String>,
     pub progressive_mode: bool,
     pub optimize_coding: bool,
     pub use_scans_in_trellis: bool,
     pub smoothing_factor: u8,
     pub exif: String,
+    pub background: Option<String>,
+    pub target_bytes: Option<u64>,
+    pub min_ssim: Option<f64>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct WebpConfig {
     pub quality: u8,
     pub size: Option<SizeFilterConfig>,
+    /// size の前に順に適用する操作
+    pub ops: Option<Vec<OpConfig>>,
+    /// size の後に適用するフィルター
+    pub filters: Option<FiltersConfig>,
     pub method: Option<u8>,
     pub target_size: Option<u8>,
     pub target_psnr: Option<f32>,
@@ -74,36 +183,133 @@ pub struct WebpConfig {
     pub pass: Option<u8>,
     pub preprocessing: Option<u8>,
     pub autofilter: Option<bool>,
+    pub loop_count: Option<u16>,
+    pub target_bytes: Option<u64>,
+    pub min_ssim: Option<f64>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GifConfig {
     pub quality: u8,
     pub size: Option<SizeFilterConfig>,
+    /// size の前に順に適用する操作
+    pub ops: Option<Vec<OpConfig>>,
+    /// size の後に適用するフィルター
+    pub filters: Option<FiltersConfig>,
     pub fast: Option<bool>,
     pub loop_count: Option<u16>,
     pub loop_speed: Option<f64>,
+    /// 出力形式が指定されていない場合の変換先（webp, avif, png）
+    pub convert_to: Option<String>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct HeifConfig {
     pub quality: Option<u8>,
     pub size: Option<SizeConfig>,
+    /// size の前に順に適用する操作
+    pub ops: Option<Vec<OpConfig>>,
+    /// size の後に適用するフィルター
+    pub filters: Option<FiltersConfig>,
+    pub target_bytes: Option<u64>,
+    pub min_ssim: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AvifConfig {
+    pub quality: u8,
+    pub size: Option<SizeFilterConfig>,
+    /// size の前に順に適用する操作
+    pub ops: Option<Vec<OpConfig>>,
+    /// size の後に適用するフィルター
+    pub filters: Option<FiltersConfig>,
+    pub alpha_quality: Option<u8>,
+    pub speed: Option<u8>,
+    pub bit_depth: Option<u8>,
+    pub chroma_subsampling: Option<String>,
+    pub target_bytes: Option<u64>,
+    pub min_ssim: Option<f64>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JxlConfig {
+    /// 1 .. 100（指定した場合は distance より優先）
+    pub quality: Option<u8>,
+    /// Butteraugli 距離（0.0: 数学的に可逆, 1.0: 視覚的に可逆）
+    pub distance: Option<f32>,
+    /// 1: 高速 .. 10: 高圧縮
+    pub effort: Option<u8>,
+    pub lossless: Option<bool>,
+    /// true: JPEG 入力を可逆変換（元の JPEG を復元可能）
+    pub jpeg_transcode: Option<bool>,
+    pub size: Option<SizeFilterConfig>,
+    /// size の前に順に適用する操作
+    pub ops: Option<Vec<OpConfig>>,
+    /// size の後に適用するフィルター
+    pub filters: Option<FiltersConfig>,
+    pub target_bytes: Option<u64>,
+    pub min_ssim: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TiffConfig {
+    /// TIFF に出力する場合の圧縮方式（deflate, lzw, packbits, none）
+    pub compression: Option<String>,
+    pub size: Option<SizeFilterConfig>,
+    /// size の前に順に適用する操作
+    pub ops: Option<Vec<OpConfig>>,
+    /// size の後に適用するフィルター
+    pub filters: Option<FiltersConfig>,
+    /// 変換するページ（1 から。未指定の場合は先頭ページ、TIFF への再圧縮では全ページ）
+    pub page: Option<u32>,
+    /// true: 全ページを `{stem}-{page}.{ext}` として個別に出力
+    pub all_pages: Option<bool>,
+    /// 出力形式が指定されていない場合の変換先（png, jpeg, webp, heif, avif, jxl）
+    pub convert_to: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IcoConfig {
+    /// 埋め込む画像の一辺のサイズ（1 .. 256）
+    pub sizes: Option<Vec<u32>>,
+    /// 縮小フィルター（nearest, triangle, catmull_rom, gaussian, lanczos3）
+    pub filter: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VariantConfig {
+    /// 出力する幅（元の幅より大きい場合は元の幅）
+    pub width: u32,
+    /// この幅で出力する形式（未指定の場合は responsive.formats）
+    pub formats: Option<Vec<String>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResponsiveConfig {
+    pub variants: Vec<VariantConfig>,
+    /// 各幅で出力する形式（未指定の場合は出力形式のみ）
+    pub formats: Option<Vec<String>>,
+    /// 出力ファイル名（{stem}, {width}, {height}, {ext}）
+    pub file_name: Option<String>,
+    /// 縮小フィルター（nearest, triangle, catmull_rom, gaussian, lanczos3）
+    pub filter: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PdfPngConfig {
     pub quality_min: u8,
     pub quality_max: u8,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PdfJpegConfig {
     pub quality: u8,
     pub max_length: i64,
+    /// 指定した場合は max_length の代わりに適用
+    pub size: Option<SizeFilterConfig>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PdfConfig {
     pub remove_info: bool,
     pub remove_metadata: bool,
@@ -112,14 +318,22 @@ pub struct PdfConfig {
     pub jpeg: PdfJpegConfig,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
     pub png: Option<PngConfig>,
     pub jpeg: Option<JpegConfig>,
     pub webp: Option<WebpConfig>,
     pub gif: Option<GifConfig>,
     pub heif: Option<HeifConfig>,
+    pub avif: Option<AvifConfig>,
+    pub jxl: Option<JxlConfig>,
+    pub tiff: Option<TiffConfig>,
+    pub ico: Option<IcoConfig>,
     pub pdf: Option<PdfConfig>,
+    /// 1つの入力から複数の幅・形式の画像を出力（srcset 用）
+    pub responsive: Option<ResponsiveConfig>,
+    /// true: 圧縮後のほうが大きくても出力する（既定では元のファイルを維持）
+    pub allow_larger: Option<bool>,
 }
 
 impl Default for PngConfig {
@@ -127,6 +341,8 @@ impl Default for PngConfig {
         Self {
             quality: 2,
             size: None,
+            ops: None,
+            filters: None,
             strip: "all".into(),
             interlacing: "none".into(),
             optimize_alpha: false,
@@ -142,12 +358,17 @@ impl Default for JpegConfig {
         Self {
             quality: 70,
             size: None,
+            ops: None,
+            filters: None,
             scan_optimization_mode: Some("all_components_together".into()),
             progressive_mode: false,
             optimize_coding: true,
             use_scans_in_trellis: false,
             smoothing_factor: 0,
             exif: "none".into(),
+            background: Some("#ffffff".into()),
+            target_bytes: None,
+            min_ssim: None,
         }
     }
 }
@@ -157,6 +378,8 @@ impl Default for WebpConfig {
         Self {
             quality: 70,
             size: None,
+            ops: None,
+            filters: None,
             method: None,
             target_size: None,
             target_psnr: None,
@@ -166,6 +389,9 @@ impl Default for WebpConfig {
             pass: None,
             preprocessing: None,
             autofilter: None,
+            loop_count: None,
+            target_bytes: None,
+            min_ssim: None,
         }
     }
 }
@@ -175,9 +401,12 @@ impl Default for GifConfig {
         Self {
             quality: 75,
             size: None,
+            ops: None,
+            filters: None,
             fast: Some(false),
             loop_count: None,
             loop_speed: None,
+            convert_to: None,
         }
     }
 }
@@ -187,6 +416,27 @@ impl Default for HeifConfig {
         Self {
             quality: Some(50),
             size: None,
+            ops: None,
+            filters: None,
+            target_bytes: None,
+            min_ssim: None,
+        }
+    }
+}
+
+impl Default for AvifConfig {
+    fn default() -> Self {
+        Self {
+            quality: 60,
+            size: None,
+            ops: None,
+            filters: None,
+            alpha_quality: None,
+            speed: None,
+            bit_depth: Some(8),
+            chroma_subsampling: Some("420".into()),
+            target_bytes: None,
+            min_ssim: None,
         }
     }
 }
@@ -204,11 +454,63 @@ impl Default for PdfConfig {
             jpeg: PdfJpegConfig {
                 quality: 70,
                 max_length: 1500,
+                size: None,
             },
         }
     }
 }
 
+impl Default for JxlConfig {
+    fn default() -> Self {
+        Self {
+            quality: None,
+            distance: Some(1.0),
+            effort: Some(7),
+            lossless: Some(false),
+            jpeg_transcode: Some(true),
+            size: None,
+            ops: None,
+            filters: None,
+            target_bytes: None,
+            min_ssim: None,
+        }
+    }
+}
+
+impl Default for TiffConfig {
+    fn default() -> Self {
+        Self {
+            compression: Some("deflate".into()),
+            size: None,
+            ops: None,
+            filters: None,
+            page: None,
+            all_pages: Some(false),
+            convert_to: None,
+        }
+    }
+}
+
+impl Default for IcoConfig {
+    fn default() -> Self {
+        Self {
+            sizes: Some(vec![16, 32, 48, 256]),
+            filter: Some("lanczos3".into()),
+        }
+    }
+}
+
+impl Default for ResponsiveConfig {
+    fn default() -> Self {
+        Self {
+            variants: Vec::new(),
+            formats: None,
+            file_name: Some("{stem}-{width}w.{ext}".into()),
+            filter: Some("lanczos3".into()),
+        }
+    }
+}
+
 impl Default for Config {
     fn default() -> Self {
         Self {
@@ -217,11 +519,39 @@ impl Default for Config {
             webp: Some(WebpConfig::default()),
             gif: Some(GifConfig::default()),
             heif: Some(HeifConfig::default()),
+            avif: Some(AvifConfig::default()),
+            jxl: Some(JxlConfig::default()),
+            tiff: Some(TiffConfig::default()),
+            ico: Some(IcoConfig::default()),
             pdf: Some(PdfConfig::default()),
+            responsive: None,
+            allow_larger: Some(false),
         }
     }
 }
 
+impl Config {
+    /// 形式ごとに実際に適用される設定（未指定の場合は既定値）
+    pub fn effective_options(&self, file_type: &FileType) -> Option<Value> {
+        let value = match file_type {
+            FileType::PNG => serde_json::to_value(self.png.clone().unwrap_or_default()),
+            FileType::JPEG => serde_json::to_value(self.jpeg.clone().unwrap_or_default()),
+            FileType::WEBP => serde_json::to_value(self.webp.clone().unwrap_or_default()),
+            FileType::GIF => serde_json::to_value(self.gif.clone().unwrap_or_default()),
+            FileType::HEIF => serde_json::to_value(self.heif.clone().unwrap_or_default()),
+            FileType::AVIF => serde_json::to_value(self.avif.clone().unwrap_or_default()),
+            FileType::JXL => serde_json::to_value(self.jxl.clone().unwrap_or_default()),
+            FileType::TIFF => serde_json::to_value(self.tiff.clone().unwrap_or_default()),
+            FileType::ICO => serde_json::to_value(self.ico.clone().unwrap_or_default()),
+            FileType::PDF => serde_json::to_value(self.pdf.clone().unwrap_or_default()),
+            // NOTE: BMP and TGA are converted to another format
+            FileType::BMP | FileType::TGA | FileType::XML => return None,
+        };
+
+        value.ok()
+    }
+}
+
 pub fn parse(json_path: &str) -> Result<Config, anyhow::Error> {
     let json_string = read_to_string(json_path)?;
     let json: Value = serde_json::from_str(&json_string)?;
@@ -236,3 +566,69 @@ pub fn parse(json_path: &str) -> Result<Config, anyhow::Error> {
 
     Ok(config)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_json(name: &str, json: &str) -> Result<Config, anyhow::Error> {
+        let path = std::env::temp_dir().join(format!("rs-image-compressor-{}-{}.json", name, std::process::id()));
+        std::fs::write(&path, json).unwrap();
+        let result = parse(path.to_str().unwrap());
+        std::fs::remove_file(&path).unwrap();
+        result
+    }
+
+    #[test]
+    fn gif_convert_to_accepts_animated_formats() {
+        for format in ["webp", "png"] {
+            let json = format!(r#"{{"gif": {{"quality": 75, "convert_to": "{}"}}}}"#, format);
+            let config = parse_json(format, &json).unwrap();
+
+            assert_eq!(config.gif.unwrap().convert_to.as_deref(), Some(format));
+        }
+    }
+
+    #[test]
+    fn gif_convert_to_rejects_avif() {
+        assert!(parse_json("avif", r#"{"gif": {"quality": 75, "convert_to": "avif"}}"#).is_err());
+    }
+
+    #[test]
+    fn avif_section_accepts_encoder_options() {
+        let config = parse_json(
+            "avif-options",
+            r#"{"avif": {"quality": 50, "alpha_quality": 80, "speed": 6, "bit_depth": 10, "chroma_subsampling": "444",
+                         "size": {"width": 640, "filter": "lanczos3"}, "target_bytes": 20000}}"#,
+        )
+        .unwrap();
+        let avif = config.avif.unwrap();
+
+        assert_eq!((avif.quality, avif.alpha_quality, avif.speed, avif.bit_depth), (50, Some(80), Some(6), Some(10)));
+        assert_eq!(avif.chroma_subsampling.as_deref(), Some("444"));
+        assert_eq!(avif.size.unwrap().width, Some(640));
+        assert_eq!(avif.target_bytes, Some(20000));
+    }
+
+    #[test]
+    fn avif_section_rejects_invalid_options() {
+        for (name, avif) in [
+            ("avif-quality", r#"{"quality": 101}"#),
+            ("avif-speed", r#"{"quality": 60, "speed": 11}"#),
+            ("avif-depth", r#"{"quality": 60, "bit_depth": 9}"#),
+            ("avif-chroma", r#"{"quality": 60, "chroma_subsampling": "411"}"#),
+            ("avif-missing", r#"{"speed": 6}"#),
+        ] {
+            assert!(parse_json(name, &format!(r#"{{"avif": {}}}"#, avif)).is_err(), "{}", avif);
+        }
+    }
+
+    #[test]
+    fn avif_defaults_match_the_schema() {
+        let avif = AvifConfig::default();
+
+        assert_eq!(avif.quality, 60);
+        assert_eq!(avif.bit_depth, Some(8));
+        assert_eq!(avif.chroma_subsampling.as_deref(), Some("420"));
+    }
+}
@@ -26,6 +26,18 @@ pub enum CompressorError {
     #[error("HEIF圧縮エラー: {0}")]
     HeifCompressError(String),
 
+    #[error("AVIF圧縮エラー: {0}")]
+    AvifCompressError(String),
+
+    #[error("JPEG XL圧縮エラー: {0}")]
+    JxlCompressError(String),
+
+    #[error("TIFF圧縮エラー: {0}")]
+    TiffCompressError(String),
+
+    #[error("ICO圧縮エラー: {0}")]
+    IcoCompressError(String),
+
     #[error("PDF圧縮エラー: {0}")]
     PdfCompressError(String),
 
@@ -35,6 +47,9 @@ pub enum CompressorError {
     #[error("設定エラー: {0}")]
     ConfigError(String),
 
+    #[error("非対応の形式変換: {0}")]
+    UnsupportedConversion(String),
+
     #[error("不明なファイル形式")]
     UnknownFileFormat,
 
@@ -1,48 +1,267 @@
 use std::fs::File;
 use std::io::{BufReader, Read};
+use serde::Serialize;
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone, Serialize)]
 pub enum FileType {
     GIF,
     PNG,
     JPEG,
     WEBP,
     HEIF,
+    AVIF,
+    JXL,
+    TIFF,
+    BMP,
+    ICO,
+    TGA,
     PDF,
     XML,
 }
 
+impl FileType {
+    /// 形式変換の対象となるラスター画像かどうか
+    pub fn is_image(&self) -> bool {
+        !matches!(self, FileType::PDF | FileType::XML)
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            FileType::GIF => "gif",
+            FileType::PNG => "png",
+            FileType::JPEG => "jpg",
+            FileType::WEBP => "webp",
+            FileType::HEIF => "heic",
+            FileType::AVIF => "avif",
+            FileType::JXL => "jxl",
+            FileType::TIFF => "tif",
+            FileType::BMP => "bmp",
+            FileType::ICO => "ico",
+            FileType::TGA => "tga",
+            FileType::PDF => "pdf",
+            FileType::XML => "svg",
+        }
+    }
+}
+
+pub fn from_extension(extension: &str) -> Option<FileType> {
+    match extension.to_ascii_lowercase().as_str() {
+        "gif" => Some(FileType::GIF),
+        "png" => Some(FileType::PNG),
+        "jpg" | "jpeg" => Some(FileType::JPEG),
+        "webp" => Some(FileType::WEBP),
+        "heic" | "heif" => Some(FileType::HEIF),
+        "avif" => Some(FileType::AVIF),
+        "jxl" => Some(FileType::JXL),
+        "tif" | "tiff" => Some(FileType::TIFF),
+        "bmp" => Some(FileType::BMP),
+        "ico" => Some(FileType::ICO),
+        "tga" => Some(FileType::TGA),
+        "pdf" => Some(FileType::PDF),
+        "svg" | "xml" => Some(FileType::XML),
+        _ => None,
+    }
+}
+
 pub fn detect(buf_reader: &mut BufReader<File>) -> Option<FileType> {
-    let mut buffer = [0; 24];
+    let mut buffer = [0; 64];
     buf_reader.read(&mut buffer).unwrap();
 
-    if infer::image::is_gif(&buffer) {
+    detect_from_bytes(&buffer)
+}
+
+pub fn detect_from_bytes(buffer: &[u8]) -> Option<FileType> {
+    if infer::image::is_gif(buffer) {
         return Some(FileType::GIF);
     }
 
-    if infer::image::is_png(&buffer) {
+    if infer::image::is_png(buffer) {
         return Some(FileType::PNG);
     }
 
-    if infer::image::is_jpeg(&buffer) {
+    if infer::image::is_jpeg(buffer) {
         return Some(FileType::JPEG);
     }
 
-    if infer::image::is_webp(&buffer) {
+    if infer::image::is_webp(buffer) {
         return Some(FileType::WEBP);
     }
 
-    if infer::image::is_heif(&buffer) {
+    if infer::image::is_avif(buffer) {
+        return Some(FileType::AVIF);
+    }
+
+    if infer::image::is_heif(buffer) {
         return Some(FileType::HEIF);
     }
+
+    if infer::image::is_jxl(buffer) {
+        return Some(FileType::JXL);
+    }
+
+    if infer::image::is_tiff(buffer) {
+        return Some(FileType::TIFF);
+    }
+
+    if infer::image::is_bmp(buffer) {
+        return Some(FileType::BMP);
+    }
+
+    if infer::image::is_ico(buffer) {
+        return Some(FileType::ICO);
+    }
     
-    if infer::archive::is_pdf(&buffer) {
+    if infer::archive::is_pdf(buffer) {
         return Some(FileType::PDF);
     }
 
-    if infer::text::is_xml(&buffer) {
+    if infer::text::is_xml(buffer) {
         return Some(FileType::XML);
     }
 
+    // NOTE: TGA has no magic number, so it is checked last
+    if is_tga(buffer) {
+        return Some(FileType::TGA);
+    }
+
     None
 }
+
+/// TGA（マジックナンバーがないためヘッダーの値が妥当かどうかで判定）
+pub fn is_tga(buffer: &[u8]) -> bool {
+    let Some(header) = buffer.get(..18) else {
+        return false;
+    };
+
+    let color_map_type = header[1];
+    let image_type = header[2];
+    let color_map_depth = header[7];
+    let width = u16::from_le_bytes([header[12], header[13]]);
+    let height = u16::from_le_bytes([header[14], header[15]]);
+    let pixel_depth = header[16];
+    let descriptor = header[17];
+
+    let valid_color_map = match color_map_type {
+        // NOTE: Without a color map, the color map specification must be empty
+        0 => matches!(image_type, 2 | 3 | 10 | 11) && header[3..8].iter().all(|&b| b == 0),
+        1 => matches!(image_type, 1 | 9) && matches!(color_map_depth, 15 | 16 | 24 | 32),
+        _ => false,
+    };
+
+    valid_color_map
+        && width > 0
+        && height > 0
+        && matches!(pixel_depth, 8 | 15 | 16 | 24 | 32)
+        && descriptor & 0xC0 == 0
+}
+
+/// APNG（IDAT より前に acTL チャンクがある PNG）かどうか
+pub fn is_apng(buffer: &[u8]) -> bool {
+    if !infer::image::is_png(buffer) {
+        return false;
+    }
+
+    // NOTE: Walk the chunks after the 8-byte signature (length, type, data, CRC)
+    let mut offset = 8;
+    while let Some(header) = buffer.get(offset..offset + 8) {
+        match &header[4..8] {
+            b"acTL" => return true,
+            b"IDAT" => return false,
+            _ => {}
+        }
+
+        let length = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as usize;
+        offset = offset.saturating_add(length).saturating_add(12);
+    }
+
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{DynamicImage, ImageFormat, RgbaImage};
+    use std::io::Cursor;
+
+    fn encode(format: ImageFormat) -> Vec<u8> {
+        let image = DynamicImage::ImageRgba8(RgbaImage::from_fn(16, 16, |x, y| image::Rgba([x as u8 * 16, y as u8 * 16, 0, 255])));
+        let image = match format {
+            // NOTE: JPEG has no alpha channel
+            ImageFormat::Jpeg => DynamicImage::ImageRgb8(image.to_rgb8()),
+            _ => image,
+        };
+
+        let mut bytes = Vec::new();
+        image.write_to(&mut Cursor::new(&mut bytes), format).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn tga_is_detected_last() {
+        let tga = encode(ImageFormat::Tga);
+
+        assert!(is_tga(&tga));
+        assert_eq!(detect_from_bytes(&tga[..64]), Some(FileType::TGA));
+    }
+
+    #[test]
+    fn other_headers_are_not_tga() {
+        let headers = [
+            (encode(ImageFormat::Png), FileType::PNG),
+            (encode(ImageFormat::Jpeg), FileType::JPEG),
+            (encode(ImageFormat::Gif), FileType::GIF),
+            (encode(ImageFormat::WebP), FileType::WEBP),
+            (encode(ImageFormat::Tiff), FileType::TIFF),
+            (encode(ImageFormat::Bmp), FileType::BMP),
+            (encode(ImageFormat::Ico), FileType::ICO),
+            (b"%PDF-1.7\n%\xe2\xe3\xcf\xd3\n1 0 obj\n<< /Type /Catalog >>\nendobj\n".to_vec(), FileType::PDF),
+            (b"<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<svg xmlns=\"http://www.w3.org/2000/svg\"/>".to_vec(), FileType::XML),
+        ];
+
+        for (bytes, file_type) in headers {
+            assert!(!is_tga(&bytes), "{:?}", file_type);
+            assert_eq!(detect_from_bytes(&bytes[..bytes.len().min(64)]), Some(file_type));
+        }
+    }
+
+    #[test]
+    fn unknown_data_is_not_tga() {
+        assert!(!is_tga(&[]));
+        assert!(!is_tga(&[0; 64]));
+        assert!(!is_tga(b"plain text that is long enough to fill a header"));
+        // NOTE: A truncated header is not enough to decide
+        assert!(!is_tga(&encode(ImageFormat::Tga)[..17]));
+        assert_eq!(detect_from_bytes(&[0; 64]), None);
+    }
+
+    /// major_brand と compatible_brands の ftyp ボックス
+    fn ftyp(major_brand: &[u8; 4], compatible_brands: &[&[u8; 4]]) -> Vec<u8> {
+        let size = 16 + compatible_brands.len() * 4;
+        let mut bytes = (size as u32).to_be_bytes().to_vec();
+        bytes.extend_from_slice(b"ftyp");
+        bytes.extend_from_slice(major_brand);
+        bytes.extend_from_slice(&[0; 4]);
+        for brand in compatible_brands {
+            bytes.extend_from_slice(*brand);
+        }
+        // NOTE: The meta box follows the ftyp box
+        bytes.extend_from_slice(&[0, 0, 0, 8]);
+        bytes.extend_from_slice(b"meta");
+        bytes
+    }
+
+    #[test]
+    fn avif_is_told_apart_from_heif() {
+        assert_eq!(detect_from_bytes(&ftyp(b"avif", &[b"mif1", b"miaf", b"avif"])), Some(FileType::AVIF));
+        assert_eq!(detect_from_bytes(&ftyp(b"avis", &[b"msf1", b"avis"])), Some(FileType::AVIF));
+        assert_eq!(detect_from_bytes(&ftyp(b"heic", &[b"mif1", b"heic"])), Some(FileType::HEIF));
+        assert!(!is_tga(&ftyp(b"avif", &[b"mif1"])));
+    }
+
+    #[test]
+    fn avif_extension_maps_to_avif() {
+        assert_eq!(from_extension("AVIF"), Some(FileType::AVIF));
+        assert_eq!(FileType::AVIF.extension(), "avif");
+        assert!(FileType::AVIF.is_image());
+    }
+}
@@ -1 +1,7 @@
-pub(crate) mod transform;
\ No newline at end of file
+pub mod animation;
+pub mod color;
+pub mod crop;
+pub mod filter;
+pub mod metrics;
+pub mod orientation;
+pub mod transform;
@@ -0,0 +1,82 @@
+use crate::config_json::{FiltersConfig, OpConfig, SizeFilterConfig};
+use crate::imaging::{filter, transform};
+use image::DynamicImage;
+
+/// アニメーションの1フレーム（キャンバス全体に合成済み）
+#[derive(Debug, Clone)]
+pub struct Frame {
+    pub image: DynamicImage,
+    /// 表示時間（ミリ秒）
+    pub delay_ms: u32,
+}
+
+/// アニメーション画像
+#[derive(Debug, Clone)]
+pub struct Animation {
+    pub frames: Vec<Frame>,
+    /// 0: 無限ループ
+    pub loop_count: u32,
+}
+
+impl Animation {
+    /// 各フレームをリサイズ（entropy・saliency の切り抜き位置は先頭フレームから求め、全フレームで同じ位置を切り抜く）
+    pub fn resize(self, config: &SizeFilterConfig) -> anyhow::Result<Self> {
+        let Some(first) = self.frames.first() else {
+            return Ok(self);
+        };
+        let config = &transform::resolve_gravity(&first.image, config)?;
+
+        Ok(Self {
+            frames: self
+                .frames
+                .into_iter()
+                .map(|frame| {
+                    Ok(Frame {
+                        image: transform::resize_image(&frame.image, config)?,
+                        delay_ms: frame.delay_ms,
+                    })
+                })
+                .collect::<anyhow::Result<Vec<_>>>()?,
+            loop_count: self.loop_count,
+        })
+    }
+
+    /// 各フレームに ops を適用（trim・resize の切り抜き範囲は先頭フレームから求め、全フレームで同じ範囲を切り抜く）
+    pub fn apply_ops(self, ops: &[OpConfig]) -> anyhow::Result<Self> {
+        let Some(first) = self.frames.first() else {
+            return Ok(self);
+        };
+        let ops = transform::resolve_ops(&first.image, ops)?;
+
+        Ok(Self {
+            frames: self
+                .frames
+                .into_iter()
+                .map(|frame| {
+                    Ok(Frame {
+                        image: transform::apply_ops(frame.image, &ops)?,
+                        delay_ms: frame.delay_ms,
+                    })
+                })
+                .collect::<anyhow::Result<Vec<_>>>()?,
+            loop_count: self.loop_count,
+        })
+    }
+
+    /// 各フレームにフィルターを適用
+    pub fn apply_filters(self, config: &FiltersConfig) -> anyhow::Result<Self> {
+        Ok(Self {
+            frames: self
+                .frames
+                .into_iter()
+                .map(|frame| {
+                    Ok(Frame {
+                        image: filter::apply_filters(frame.image, config)?,
+                        delay_ms: frame.delay_ms,
+                    })
+                })
+                .collect::<anyhow::Result<Vec<_>>>()?,
+            loop_count: self.loop_count,
+        })
+    }
+}
@@ -0,0 +1,95 @@
+use image::Rgba;
+use lcms2::{ColorSpaceSignature, Intent, PixelFormat, Profile, Transform};
+
+const CMYK_ICC: &[u8] = include_bytes!("../../assets/icc/USWebCoatedSWOP.icc");
+
+/// "#RGB", "#RRGGBB", "#RRGGBBAA" 形式の色を解析
+pub fn parse_hex_color(value: &str) -> Option<Rgba<u8>> {
+    let hex = value.strip_prefix('#').unwrap_or(value);
+    if !hex.is_ascii() {
+        return None;
+    }
+
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+
+    match hex.len() {
+        3 => {
+            let mut rgb = [0u8; 3];
+            for (i, c) in hex.chars().enumerate() {
+                let v = c.to_digit(16)? as u8;
+                rgb[i] = v * 16 + v;
+            }
+            Some(Rgba([rgb[0], rgb[1], rgb[2], 255]))
+        }
+        6 => Some(Rgba([channel(0)?, channel(2)?, channel(4)?, 255])),
+        8 => Some(Rgba([channel(0)?, channel(2)?, channel(4)?, channel(6)?])),
+        _ => None,
+    }
+}
+
+/// 8bit CMYK を sRGB に変換（CMYK の ICC プロファイルがない場合は U.S. Web Coated (SWOP)）
+pub fn cmyk8_to_rgb8(data: &[u8], icc_profile: Option<&[u8]>) -> anyhow::Result<Vec<u8>> {
+    let transform: Transform<u8, u8> = Transform::new(
+        &cmyk_profile(icc_profile)?,
+        PixelFormat::CMYK_8,
+        &Profile::new_srgb(),
+        PixelFormat::RGB_8,
+        Intent::Perceptual,
+    )?;
+
+    let mut rgb_data = vec![0u8; data.len() / 4 * 3];
+    transform.transform_pixels(data, &mut rgb_data);
+
+    Ok(rgb_data)
+}
+
+/// 16bit CMYK を sRGB に変換
+pub fn cmyk16_to_rgb16(data: &[u16], icc_profile: Option<&[u8]>) -> anyhow::Result<Vec<u16>> {
+    let transform: Transform<[u16; 4], [u16; 3]> = Transform::new(
+        &cmyk_profile(icc_profile)?,
+        PixelFormat::CMYK_16,
+        &Profile::new_srgb(),
+        PixelFormat::RGB_16,
+        Intent::Perceptual,
+    )?;
+
+    let cmyk_pixels: Vec<[u16; 4]> = data
+        .chunks_exact(4)
+        .map(|pixel| [pixel[0], pixel[1], pixel[2], pixel[3]])
+        .collect();
+    let mut rgb_pixels = vec![[0u16; 3]; cmyk_pixels.len()];
+    transform.transform_pixels(&cmyk_pixels, &mut rgb_pixels);
+
+    Ok(rgb_pixels.into_iter().flatten().collect())
+}
+
+fn cmyk_profile(icc_profile: Option<&[u8]>) -> anyhow::Result<Profile> {
+    // NOTE: Embedded profiles that are broken or not CMYK fall back to the default profile
+    if let Some(profile) = icc_profile.and_then(|icc_profile| Profile::new_icc(icc_profile).ok())
+        && profile.color_space() == ColorSpaceSignature::CmykData
+    {
+        return Ok(profile);
+    }
+
+    Ok(Profile::new_icc(CMYK_ICC)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_hex_color_accepts_short_long_and_alpha_forms() {
+        assert_eq!(parse_hex_color("#fff"), Some(Rgba([255, 255, 255, 255])));
+        assert_eq!(parse_hex_color("#1a2B3c"), Some(Rgba([0x1a, 0x2b, 0x3c, 255])));
+        assert_eq!(parse_hex_color("#11223380"), Some(Rgba([0x11, 0x22, 0x33, 0x80])));
+        assert_eq!(parse_hex_color("c0c"), Some(Rgba([0xcc, 0x00, 0xcc, 255])));
+    }
+
+    #[test]
+    fn parse_hex_color_rejects_invalid_values() {
+        for value in ["", "#", "#ff", "#fffff", "#ggg", "#12345g", "white", "#ｆｆｆ", "#ffé"] {
+            assert_eq!(parse_hex_color(value), None, "{}", value);
+        }
+    }
+}
@@ -0,0 +1,337 @@
+use crate::config_json::FocusConfig;
+use crate::error::CompressorError;
+use anyhow::anyhow;
+use image::imageops::FilterType;
+use image::{DynamicImage, GenericImageView, GrayImage, RgbImage};
+
+/// 解析用に縮小する画像の長辺
+const ANALYSIS_SIZE: u32 = 128;
+/// 1軸あたりに評価する切り抜き位置の最大数
+const MAX_POSITIONS: u32 = 32;
+/// saliency で肌色を優先する重み
+const SKIN_WEIGHT: f64 = 1.8;
+/// saliency で彩度を優先する重み
+const SATURATION_WEIGHT: f64 = 0.3;
+
+/// 切り抜く範囲（width x height）の左上の位置
+///
+/// focus を指定した場合はその位置を中心とし、それ以外は gravity（center, north, .., entropy, saliency）に従う
+pub fn crop_origin(
+    image: &DynamicImage,
+    width: u32,
+    height: u32,
+    gravity: Option<&str>,
+    focus: Option<&FocusConfig>,
+) -> anyhow::Result<(u32, u32)> {
+    let (image_width, image_height) = image.dimensions();
+    let (max_x, max_y) = (
+        image_width.saturating_sub(width),
+        image_height.saturating_sub(height),
+    );
+
+    if max_x == 0 && max_y == 0 {
+        return Ok((0, 0));
+    }
+
+    if let Some(focus) = focus {
+        let x = focus.x * image_width as f64 - width as f64 / 2.0;
+        let y = focus.y * image_height as f64 - height as f64 / 2.0;
+
+        return Ok((clamp(x, max_x), clamp(y, max_y)));
+    }
+
+    let (ratio_x, ratio_y) = match gravity.unwrap_or("center") {
+        "center" => (0.5, 0.5),
+        "north" => (0.5, 0.0),
+        "northeast" => (1.0, 0.0),
+        "east" => (1.0, 0.5),
+        "southeast" => (1.0, 1.0),
+        "south" => (0.5, 1.0),
+        "southwest" => (0.0, 1.0),
+        "west" => (0.0, 0.5),
+        "northwest" => (0.0, 0.0),
+        "entropy" => return Ok(entropy_origin(image, width, height)),
+        "saliency" => return Ok(saliency_origin(image, width, height)),
+        gravity => {
+            return Err(anyhow!(CompressorError::ConfigError(format!(
+                "Unknown crop gravity: {}",
+                gravity
+            ))));
+        }
+    };
+
+    Ok((
+        (max_x as f64 * ratio_x).round() as u32,
+        (max_y as f64 * ratio_y).round() as u32,
+    ))
+}
+
+/// 輝度ヒストグラムのエントロピー（情報量）が最も大きい範囲
+fn entropy_origin(image: &DynamicImage, width: u32, height: u32) -> (u32, u32) {
+    let (gray, scale) = analysis_image(image);
+    let gray = gray.to_luma8();
+
+    best_origin(image, &gray, width, height, scale, |x, y, window_width, window_height| {
+        let mut histogram = [0u32; 256];
+        for y in y..y + window_height {
+            for x in x..x + window_width {
+                histogram[gray.get_pixel(x, y)[0] as usize] += 1;
+            }
+        }
+
+        let total = (window_width * window_height) as f64;
+        histogram
+            .iter()
+            .filter(|count| **count > 0)
+            .map(|count| {
+                let p = *count as f64 / total;
+                -p * p.log2()
+            })
+            .sum()
+    })
+}
+
+/// エッジ・彩度・肌色から求めた注目度の合計が最も大きい範囲
+fn saliency_origin(image: &DynamicImage, width: u32, height: u32) -> (u32, u32) {
+    let (analysis, scale) = analysis_image(image);
+    let rgb = analysis.to_rgb8();
+    let gray = analysis.to_luma8();
+    let integral = integral_image(&saliency_map(&rgb, &gray), rgb.width(), rgb.height());
+    let stride = rgb.width() as usize + 1;
+
+    best_origin(image, &gray, width, height, scale, |x, y, window_width, window_height| {
+        let (x0, y0) = (x as usize, y as usize);
+        let (x1, y1) = (x0 + window_width as usize, y0 + window_height as usize);
+
+        integral[y1 * stride + x1] - integral[y0 * stride + x1] - integral[y1 * stride + x0]
+            + integral[y0 * stride + x0]
+    })
+}
+
+/// 解析用に縮小した画像と縮小率
+fn analysis_image(image: &DynamicImage) -> (DynamicImage, f64) {
+    let (width, height) = image.dimensions();
+    let scale = (ANALYSIS_SIZE as f64 / width.max(height) as f64).min(1.0);
+
+    if scale >= 1.0 {
+        return (image.clone(), 1.0);
+    }
+
+    let analysis = image.resize_exact(
+        ((width as f64 * scale).round() as u32).max(1),
+        ((height as f64 * scale).round() as u32).max(1),
+        FilterType::Triangle,
+    );
+
+    (analysis, scale)
+}
+
+/// 縮小した画像上で切り抜き位置を探索し、元の画像の座標に戻す（同じスコアの場合は中央に近い位置）
+fn best_origin<F>(
+    image: &DynamicImage,
+    analysis: &GrayImage,
+    width: u32,
+    height: u32,
+    scale: f64,
+    score: F,
+) -> (u32, u32)
+where
+    F: Fn(u32, u32, u32, u32) -> f64,
+{
+    let (image_width, image_height) = image.dimensions();
+    let window_width = ((width as f64 * scale).round() as u32).clamp(1, analysis.width());
+    let window_height = ((height as f64 * scale).round() as u32).clamp(1, analysis.height());
+    let (max_x, max_y) = (
+        analysis.width() - window_width,
+        analysis.height() - window_height,
+    );
+    let center = (max_x as f64 / 2.0, max_y as f64 / 2.0);
+
+    let mut best: Option<(f64, f64, u32, u32)> = None;
+
+    for y in positions(max_y) {
+        for x in positions(max_x) {
+            let value = score(x, y, window_width, window_height);
+            let distance = (x as f64 - center.0).abs() + (y as f64 - center.1).abs();
+
+            let is_better = match best {
+                None => true,
+                Some((best_value, best_distance, _, _)) => {
+                    value > best_value + f64::EPSILON
+                        || ((value - best_value).abs() <= f64::EPSILON && distance < best_distance)
+                }
+            };
+
+            if is_better {
+                best = Some((value, distance, x, y));
+            }
+        }
+    }
+
+    let (_, _, x, y) = best.unwrap_or_default();
+
+    (
+        clamp(x as f64 / scale, image_width.saturating_sub(width)),
+        clamp(y as f64 / scale, image_height.saturating_sub(height)),
+    )
+}
+
+/// 0 ..= max の評価位置（最大 MAX_POSITIONS + 1 個）
+fn positions(max: u32) -> impl Iterator<Item = u32> {
+    let step = max.div_ceil(MAX_POSITIONS).max(1);
+
+    (0..=max).step_by(step as usize).chain((!max.is_multiple_of(step)).then_some(max))
+}
+
+fn saliency_map(rgb: &RgbImage, gray: &GrayImage) -> Vec<f64> {
+    let (width, height) = rgb.dimensions();
+    let luma = |x: i64, y: i64| {
+        let x = x.clamp(0, width as i64 - 1) as u32;
+        let y = y.clamp(0, height as i64 - 1) as u32;
+        gray.get_pixel(x, y)[0] as f64
+    };
+
+    let mut map = Vec::with_capacity((width * height) as usize);
+
+    for y in 0..height as i64 {
+        for x in 0..width as i64 {
+            // NOTE: Sobel operator normalized to 0.0 .. 1.0
+            let gx = luma(x + 1, y - 1) + 2.0 * luma(x + 1, y) + luma(x + 1, y + 1)
+                - luma(x - 1, y - 1)
+                - 2.0 * luma(x - 1, y)
+                - luma(x - 1, y + 1);
+            let gy = luma(x - 1, y + 1) + 2.0 * luma(x, y + 1) + luma(x + 1, y + 1)
+                - luma(x - 1, y - 1)
+                - 2.0 * luma(x, y - 1)
+                - luma(x + 1, y - 1);
+            let edge = ((gx.abs() + gy.abs()) / (8.0 * 255.0)).min(1.0);
+
+            let [r, g, b] = rgb.get_pixel(x as u32, y as u32).0;
+            let (max, min) = (r.max(g).max(b) as f64, r.min(g).min(b) as f64);
+            let saturation = if max > 0.0 { (max - min) / max } else { 0.0 };
+
+            map.push(edge + SATURATION_WEIGHT * saturation + SKIN_WEIGHT * skin(r, g, b));
+        }
+    }
+
+    map
+}
+
+/// 肌色らしさ（RGB の経験則による判定）
+fn skin(r: u8, g: u8, b: u8) -> f64 {
+    let (r, g, b) = (r as i32, g as i32, b as i32);
+    let is_skin = r > 95
+        && g > 40
+        && b > 20
+        && r > g
+        && r > b
+        && r - g.min(b) > 15
+        && (r - g).abs() > 15;
+
+    if is_skin { 1.0 } else { 0.0 }
+}
+
+/// 累積和（(width + 1) x (height + 1)）
+fn integral_image(map: &[f64], width: u32, height: u32) -> Vec<f64> {
+    let (width, height) = (width as usize, height as usize);
+    let stride = width + 1;
+    let mut integral = vec![0.0; stride * (height + 1)];
+
+    for y in 0..height {
+        let mut row_sum = 0.0;
+        for x in 0..width {
+            row_sum += map[y * width + x];
+            integral[(y + 1) * stride + x + 1] = integral[y * stride + x + 1] + row_sum;
+        }
+    }
+
+    integral
+}
+
+fn clamp(value: f64, max: u32) -> u32 {
+    value.round().clamp(0.0, max as f64) as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgb, RgbImage};
+
+    /// 灰色の背景の (x, y) に size x size の模様がある画像
+    fn feature_image(width: u32, height: u32, x: u32, y: u32, size: u32) -> DynamicImage {
+        DynamicImage::ImageRgb8(RgbImage::from_fn(width, height, |px, py| {
+            if (x..x + size).contains(&px) && (y..y + size).contains(&py) {
+                let value = ((px * 37 + py * 91) % 256) as u8;
+                Rgb([value, 255 - value, (value / 2).wrapping_add(64)])
+            } else {
+                Rgb([128, 128, 128])
+            }
+        }))
+    }
+
+    #[test]
+    fn crop_origin_follows_gravity() {
+        let image = feature_image(200, 100, 0, 0, 0);
+        let origin = |gravity| crop_origin(&image, 100, 50, Some(gravity), None).unwrap();
+
+        assert_eq!(origin("center"), (50, 25));
+        assert_eq!(origin("northwest"), (0, 0));
+        assert_eq!(origin("southeast"), (100, 50));
+        assert_eq!(origin("east"), (100, 25));
+        assert_eq!(crop_origin(&image, 100, 50, None, None).unwrap(), (50, 25));
+        assert!(crop_origin(&image, 100, 50, Some("middle"), None).is_err());
+    }
+
+    #[test]
+    fn crop_origin_centers_and_clamps_focus() {
+        let image = feature_image(200, 100, 0, 0, 0);
+        let origin = |x, y| crop_origin(&image, 100, 50, None, Some(&FocusConfig { x, y })).unwrap();
+
+        assert_eq!(origin(0.5, 0.5), (50, 25));
+        assert_eq!(origin(0.3, 0.6), (10, 35));
+        assert_eq!(origin(0.0, 0.0), (0, 0));
+        assert_eq!(origin(1.0, 1.0), (100, 50));
+        assert_eq!(origin(-1.0, 2.0), (0, 50));
+    }
+
+    #[test]
+    fn crop_origin_without_overflow_is_zero() {
+        let image = feature_image(100, 50, 0, 0, 0);
+
+        assert_eq!(crop_origin(&image, 100, 50, Some("entropy"), None).unwrap(), (0, 0));
+        assert_eq!(crop_origin(&image, 120, 60, Some("southeast"), None).unwrap(), (0, 0));
+    }
+
+    #[test]
+    fn crop_origin_finds_detail() {
+        let image = feature_image(600, 200, 440, 40, 120);
+
+        for gravity in ["entropy", "saliency"] {
+            let (x, y) = crop_origin(&image, 200, 200, Some(gravity), None).unwrap();
+            assert!((360..=400).contains(&x), "{}: x = {}", gravity, x);
+            assert_eq!(y, 0);
+        }
+    }
+
+    #[test]
+    fn crop_origin_prefers_center_for_flat_images() {
+        let image = feature_image(300, 100, 0, 0, 0);
+        // NOTE: The search runs on a downscaled image, so the origin is only close to the center
+        let (x, y) = crop_origin(&image, 100, 100, Some("entropy"), None).unwrap();
+
+        assert!((95..=105).contains(&x), "x = {}", x);
+        assert_eq!(y, 0);
+    }
+
+    #[test]
+    fn positions_cover_both_ends() {
+        assert_eq!(positions(0).collect::<Vec<_>>(), vec![0]);
+        assert_eq!(positions(5).collect::<Vec<_>>(), vec![0, 1, 2, 3, 4, 5]);
+
+        let positions: Vec<u32> = positions(100).collect();
+        assert_eq!(positions.first(), Some(&0));
+        assert_eq!(positions.last(), Some(&100));
+        assert!(positions.len() <= MAX_POSITIONS as usize + 1);
+        assert!(positions.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+}
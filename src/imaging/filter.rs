@@ -0,0 +1,286 @@
+use crate::config_json::{FiltersConfig, UnsharpConfig};
+use crate::error::CompressorError;
+use anyhow::anyhow;
+use image::imageops;
+use image::{ColorType, DynamicImage, ImageBuffer, Rgba, Rgba32FImage};
+
+/// Rec. 709 の輝度係数（リニア RGB）
+const LUMA_COEFFICIENTS: [f32; 3] = [0.2126, 0.7152, 0.0722];
+
+/// リサイズ後のフィルターを適用
+///
+/// brightness → contrast → gamma は sRGB のまま、grayscale → blur → unsharp はリニア RGB で適用
+pub fn apply_filters(image: DynamicImage, config: &FiltersConfig) -> anyhow::Result<DynamicImage> {
+    validate(config)?;
+
+    let has_alpha = image.color().has_alpha();
+    let is_float = matches!(image.color(), ColorType::Rgb32F | ColorType::Rgba32F);
+    let is_16bit = !is_float && image.color().bytes_per_pixel() / image.color().channel_count() > 1;
+    let grayscale = config.grayscale.unwrap_or(false);
+
+    let mut buffer = image.into_rgba32f();
+
+    if config.brightness.is_some() || config.contrast.is_some() || config.gamma.is_some() {
+        let brightness = config.brightness.unwrap_or(0.0) / 100.0;
+        let contrast = (100.0 + config.contrast.unwrap_or(0.0)) / 100.0;
+        let gamma = config.gamma.unwrap_or(1.0);
+
+        map_rgb(&mut buffer, |value| {
+            let value = ((value + brightness - 0.5) * contrast + 0.5).clamp(0.0, 1.0);
+            value.powf(1.0 / gamma)
+        });
+    }
+
+    if grayscale || config.blur.is_some() || config.unsharp.is_some() {
+        map_rgb(&mut buffer, srgb_to_linear);
+
+        if grayscale {
+            for pixel in buffer.pixels_mut() {
+                let luma = (0..3).map(|i| pixel[i] * LUMA_COEFFICIENTS[i]).sum::<f32>();
+                pixel[0] = luma;
+                pixel[1] = luma;
+                pixel[2] = luma;
+            }
+        }
+
+        if let Some(sigma) = config.blur {
+            buffer = blur(&buffer, sigma);
+        }
+
+        if let Some(unsharp_config) = config.unsharp.as_ref() {
+            buffer = unsharp(&buffer, unsharp_config);
+        }
+
+        map_rgb(&mut buffer, linear_to_srgb);
+    }
+
+    // NOTE: There is no floating point grayscale image, so 32F inputs stay RGB(A)
+    if is_float {
+        return Ok(if has_alpha {
+            DynamicImage::ImageRgba32F(buffer)
+        } else {
+            DynamicImage::ImageRgb32F(DynamicImage::ImageRgba32F(buffer).to_rgb32f())
+        });
+    }
+
+    let image = DynamicImage::ImageRgba32F(buffer);
+
+    // NOTE: Keep the bit depth and alpha of the input so that encoders don't store extra channels
+    Ok(match (grayscale, has_alpha, is_16bit) {
+        (true, true, false) => DynamicImage::ImageLumaA8(image.to_luma_alpha8()),
+        (true, true, true) => DynamicImage::ImageLumaA16(image.to_luma_alpha16()),
+        (true, false, false) => DynamicImage::ImageLuma8(image.to_luma8()),
+        (true, false, true) => DynamicImage::ImageLuma16(image.to_luma16()),
+        (false, true, false) => DynamicImage::ImageRgba8(image.to_rgba8()),
+        (false, true, true) => DynamicImage::ImageRgba16(image.to_rgba16()),
+        (false, false, false) => DynamicImage::ImageRgb8(image.to_rgb8()),
+        (false, false, true) => DynamicImage::ImageRgb16(image.to_rgb16()),
+    })
+}
+
+fn validate(config: &FiltersConfig) -> anyhow::Result<()> {
+    let radius = config.unsharp.as_ref().map(|unsharp_config| unsharp_config.radius);
+
+    for (name, value) in [("blur", config.blur), ("unsharp.radius", radius), ("gamma", config.gamma)] {
+        if value.is_some_and(|value| value <= 0.0 || !value.is_finite()) {
+            return Err(anyhow!(CompressorError::ConfigError(format!(
+                "filters.{} must be greater than 0",
+                name
+            ))));
+        }
+    }
+
+    for (name, value) in [("brightness", config.brightness), ("contrast", config.contrast)] {
+        if value.is_some_and(|value| !(-100.0..=100.0).contains(&value)) {
+            return Err(anyhow!(CompressorError::ConfigError(format!(
+                "filters.{} must be between -100 and 100",
+                name
+            ))));
+        }
+    }
+
+    Ok(())
+}
+
+/// 透過部分の色がにじまないよう、アルファを乗算してからぼかす
+fn blur(buffer: &Rgba32FImage, sigma: f32) -> Rgba32FImage {
+    let mut premultiplied = buffer.clone();
+    for pixel in premultiplied.pixels_mut() {
+        for i in 0..3 {
+            pixel[i] *= pixel[3];
+        }
+    }
+
+    let mut blurred: Rgba32FImage = imageops::blur(&premultiplied, sigma);
+    for pixel in blurred.pixels_mut() {
+        for i in 0..3 {
+            pixel[i] = if pixel[3] > 0.0 { pixel[i] / pixel[3] } else { 0.0 };
+        }
+    }
+
+    blurred
+}
+
+/// 元の画像とぼかした画像の差を amount 倍して加算（sRGB での差が threshold 未満のチャンネルは変更しない）
+fn unsharp(buffer: &Rgba32FImage, config: &UnsharpConfig) -> Rgba32FImage {
+    let amount = config.amount.unwrap_or(1.0);
+    let threshold = config.threshold.unwrap_or(0) as f32 / 255.0;
+    let blurred = blur(buffer, config.radius);
+
+    ImageBuffer::from_fn(buffer.width(), buffer.height(), |x, y| {
+        let (original, blurred) = (buffer.get_pixel(x, y), blurred.get_pixel(x, y));
+        let mut pixel = *original;
+
+        for i in 0..3 {
+            let difference = original[i] - blurred[i];
+            // NOTE: threshold is given in 0 .. 255 sRGB units, so compare the encoded values
+            if (linear_to_srgb(original[i]) - linear_to_srgb(blurred[i])).abs() >= threshold {
+                pixel[i] = (original[i] + difference * amount).clamp(0.0, 1.0);
+            }
+        }
+
+        pixel
+    })
+}
+
+fn map_rgb<F>(buffer: &mut Rgba32FImage, f: F)
+where
+    F: Fn(f32) -> f32,
+{
+    for pixel in buffer.pixels_mut() {
+        let Rgba([r, g, b, a]) = *pixel;
+        *pixel = Rgba([f(r), f(g), f(b), a]);
+    }
+}
+
+fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(value: f32) -> f32 {
+    let value = value.clamp(0.0, 1.0);
+    if value <= 0.0031308 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{ImageBuffer, Luma, Rgb, Rgb32FImage, RgbImage, RgbaImage};
+
+    fn filters(json: &str) -> FiltersConfig {
+        serde_json::from_str(json).unwrap()
+    }
+
+    fn gray(value: u8) -> DynamicImage {
+        DynamicImage::ImageRgb8(RgbImage::from_pixel(4, 4, Rgb([value, value, value])))
+    }
+
+    #[test]
+    fn srgb_conversion_round_trips() {
+        for value in [0.0, 0.002, 0.04, 0.2, 0.5, 0.8, 1.0] {
+            assert!((linear_to_srgb(srgb_to_linear(value)) - value).abs() < 1e-5, "{}", value);
+        }
+        assert!((srgb_to_linear(0.5) - 0.214).abs() < 1e-3);
+    }
+
+    #[test]
+    fn tone_filters_apply_in_order() {
+        let config = filters(r#"{"brightness": 20, "contrast": -50, "gamma": 2}"#);
+        let image = apply_filters(gray(128), &config).unwrap();
+
+        // NOTE: (128/255 + 0.2 - 0.5) * 0.5 + 0.5 = 0.601, then 0.601^(1/2) = 0.775
+        assert_eq!(image.to_rgb8().get_pixel(0, 0), &Rgb([198, 198, 198]));
+    }
+
+    #[test]
+    fn neutral_filters_keep_pixels() {
+        let config = filters(r#"{"brightness": 0, "contrast": 0, "gamma": 1}"#);
+        let image = RgbImage::from_fn(4, 4, |x, y| Rgb([(x * 60) as u8, (y * 60) as u8, 200]));
+        let filtered = apply_filters(DynamicImage::ImageRgb8(image.clone()), &config).unwrap();
+
+        assert_eq!(filtered.to_rgb8(), image);
+    }
+
+    #[test]
+    fn grayscale_uses_linear_luma() {
+        let image = DynamicImage::ImageRgb8(RgbImage::from_pixel(2, 2, Rgb([0, 255, 0])));
+        let filtered = apply_filters(image, &filters(r#"{"grayscale": true}"#)).unwrap();
+
+        assert_eq!(filtered.color(), ColorType::L8);
+        // NOTE: linear 0.7152 encodes to about 0.8625 in sRGB
+        assert_eq!(filtered.to_luma8().get_pixel(0, 0), &Luma([220]));
+    }
+
+    #[test]
+    fn output_keeps_depth_and_alpha() {
+        let grayscale = filters(r#"{"grayscale": true}"#);
+        let brightness = filters(r#"{"brightness": 10}"#);
+        let rgba8 = DynamicImage::ImageRgba8(RgbaImage::from_pixel(2, 2, Rgba([10, 20, 30, 128])));
+        let rgb16 = DynamicImage::ImageRgb16(ImageBuffer::from_pixel(2, 2, Rgb([1000u16, 2000, 3000])));
+        let rgb32f = DynamicImage::ImageRgb32F(Rgb32FImage::from_pixel(2, 2, Rgb([0.1, 0.2, 0.3])));
+
+        assert_eq!(apply_filters(rgba8.clone(), &brightness).unwrap().color(), ColorType::Rgba8);
+        assert_eq!(apply_filters(rgba8, &grayscale).unwrap().color(), ColorType::La8);
+        assert_eq!(apply_filters(rgb16.clone(), &brightness).unwrap().color(), ColorType::Rgb16);
+        assert_eq!(apply_filters(rgb16, &grayscale).unwrap().color(), ColorType::L16);
+        assert_eq!(apply_filters(rgb32f.clone(), &brightness).unwrap().color(), ColorType::Rgb32F);
+        assert_eq!(apply_filters(rgb32f, &grayscale).unwrap().color(), ColorType::Rgb32F);
+    }
+
+    #[test]
+    fn blur_does_not_bleed_transparent_colors() {
+        // NOTE: Transparent red next to opaque blue
+        let image = RgbaImage::from_fn(8, 1, |x, _| {
+            if x < 4 { Rgba([255, 0, 0, 0]) } else { Rgba([0, 0, 255, 255]) }
+        });
+        let blurred = apply_filters(DynamicImage::ImageRgba8(image), &filters(r#"{"blur": 1.5}"#))
+            .unwrap()
+            .to_rgba8();
+
+        let edge = blurred.get_pixel(3, 0);
+        assert!(edge[3] > 0);
+        assert_eq!(edge[0], 0);
+        assert_eq!(edge[2], 255);
+    }
+
+    #[test]
+    fn unsharp_increases_edge_contrast_above_threshold() {
+        let edge = DynamicImage::ImageRgb8(RgbImage::from_fn(16, 1, |x, _| {
+            if x < 8 { Rgb([100, 100, 100]) } else { Rgb([150, 150, 150]) }
+        }));
+        let sharpened = apply_filters(edge.clone(), &filters(r#"{"unsharp": {"radius": 1.5}}"#))
+            .unwrap()
+            .to_rgb8();
+
+        assert!(sharpened.get_pixel(7, 0)[0] < 100);
+        assert!(sharpened.get_pixel(8, 0)[0] > 150);
+        assert_eq!(sharpened.get_pixel(0, 0)[0], 100);
+
+        // NOTE: The edge differs by at most 25 sRGB levels from its blurred version
+        let config = filters(r#"{"unsharp": {"radius": 1.5, "threshold": 30}}"#);
+        assert_eq!(apply_filters(edge.clone(), &config).unwrap().to_rgb8(), edge.to_rgb8());
+    }
+
+    #[test]
+    fn validate_rejects_out_of_range_values() {
+        for json in [
+            r#"{"blur": 0}"#,
+            r#"{"gamma": -1}"#,
+            r#"{"unsharp": {"radius": 0}}"#,
+            r#"{"brightness": 101}"#,
+            r#"{"contrast": -150}"#,
+        ] {
+            assert!(apply_filters(gray(128), &filters(json)).is_err(), "{}", json);
+        }
+
+        assert!(apply_filters(gray(128), &filters(r#"{"brightness": -100, "contrast": 100}"#)).is_ok());
+    }
+}
@@ -0,0 +1,119 @@
+use crate::imaging::transform;
+use image::imageops::FilterType;
+use image::{DynamicImage, GenericImageView, Rgba, RgbImage};
+use serde::Serialize;
+
+const WINDOW_SIZE: usize = 8;
+const WINDOW_STEP: usize = 4;
+
+/// 圧縮前後の画質指標
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct Metrics {
+    /// 1.0: 同一
+    pub ssim: f64,
+    /// 1 / SSIM - 1（0.0: 同一）
+    pub dssim: f64,
+    /// dB（同一の場合は無限大）
+    pub psnr: f64,
+}
+
+/// エンコード前の画像と圧縮後の画像を比較（サイズが異なる場合はエンコード前の画像を圧縮後のサイズに合わせる）
+pub fn compare(original: &DynamicImage, compressed: &DynamicImage) -> Metrics {
+    let (width, height) = compressed.dimensions();
+
+    let original = if original.dimensions() != (width, height) {
+        original.resize_exact(width, height, FilterType::Triangle)
+    } else {
+        original.clone()
+    };
+
+    // NOTE: Compare on a white background so that hidden colours under transparent pixels are ignored
+    let background = Rgba([255, 255, 255, 255]);
+    let original = transform::flatten(&original, background).to_rgb8();
+    let compressed = transform::flatten(compressed, background).to_rgb8();
+
+    let ssim = ssim(&luma(&original), &luma(&compressed), width as usize, height as usize);
+    let dssim = if ssim > 0.0 {
+        1.0 / ssim - 1.0
+    } else {
+        f64::INFINITY
+    };
+
+    Metrics {
+        ssim,
+        dssim,
+        psnr: psnr(&original, &compressed),
+    }
+}
+
+fn luma(image: &RgbImage) -> Vec<f64> {
+    image
+        .pixels()
+        .map(|p| 0.299 * p[0] as f64 + 0.587 * p[1] as f64 + 0.114 * p[2] as f64)
+        .collect()
+}
+
+fn psnr(a: &RgbImage, b: &RgbImage) -> f64 {
+    let (sum, count) = a
+        .as_raw()
+        .iter()
+        .zip(b.as_raw())
+        .fold((0.0, 0usize), |(sum, count), (x, y)| {
+            let diff = *x as f64 - *y as f64;
+            (sum + diff * diff, count + 1)
+        });
+
+    if count == 0 || sum == 0.0 {
+        return f64::INFINITY;
+    }
+
+    let mse = sum / count as f64;
+    10.0 * (255.0 * 255.0 / mse).log10()
+}
+
+/// 8x8 ウィンドウ（4px 間隔）の平均 SSIM
+fn ssim(a: &[f64], b: &[f64], width: usize, height: usize) -> f64 {
+    const C1: f64 = (0.01 * 255.0) * (0.01 * 255.0);
+    const C2: f64 = (0.03 * 255.0) * (0.03 * 255.0);
+
+    if width == 0 || height == 0 {
+        return 1.0;
+    }
+
+    let window_width = WINDOW_SIZE.min(width);
+    let window_height = WINDOW_SIZE.min(height);
+    let count = (window_width * window_height) as f64;
+
+    let mut total = 0.0;
+    let mut windows = 0;
+
+    for top in (0..=height - window_height).step_by(WINDOW_STEP) {
+        for left in (0..=width - window_width).step_by(WINDOW_STEP) {
+            let (mut sum_a, mut sum_b) = (0.0, 0.0);
+            let (mut sum_aa, mut sum_bb, mut sum_ab) = (0.0, 0.0, 0.0);
+
+            for y in top..top + window_height {
+                let row = y * width;
+                for x in left..left + window_width {
+                    let (va, vb) = (a[row + x], b[row + x]);
+                    sum_a += va;
+                    sum_b += vb;
+                    sum_aa += va * va;
+                    sum_bb += vb * vb;
+                    sum_ab += va * vb;
+                }
+            }
+
+            let (mean_a, mean_b) = (sum_a / count, sum_b / count);
+            let var_a = sum_aa / count - mean_a * mean_a;
+            let var_b = sum_bb / count - mean_b * mean_b;
+            let covariance = sum_ab / count - mean_a * mean_b;
+
+            total += ((2.0 * mean_a * mean_b + C1) * (2.0 * covariance + C2))
+                / ((mean_a * mean_a + mean_b * mean_b + C1) * (var_a + var_b + C2));
+            windows += 1;
+        }
+    }
+
+    total / windows as f64
+}
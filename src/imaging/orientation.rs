@@ -0,0 +1,249 @@
+use crate::file_type::FileType;
+use image::DynamicImage;
+
+/// EXIF の Orientation タグ
+const ORIENTATION_TAG: u16 = 0x0112;
+const EXIF_HEADER: &[u8] = b"Exif\0\0";
+
+/// 入力の EXIF（JPEG の APP1、PNG の eXIf、WebP の EXIF）から Orientation（1 .. 8）を取得
+///
+/// EXIF がない場合は 1（回転なし）。HEIF・AVIF の irot/imir はデコード時に libheif が適用する
+pub fn read(file_type: &FileType, input: &[u8]) -> u16 {
+    let exif = match file_type {
+        FileType::JPEG => jpeg_exif(input),
+        FileType::PNG => png_exif(input),
+        FileType::WEBP => webp_exif(input),
+        _ => None,
+    };
+
+    exif.and_then(tiff_orientation).unwrap_or(1)
+}
+
+/// Orientation に従って画素を回転・反転
+pub fn apply(image: DynamicImage, orientation: u16) -> DynamicImage {
+    match orientation {
+        2 => image.fliph(),
+        3 => image.rotate180(),
+        4 => image.flipv(),
+        5 => image.rotate90().fliph(),
+        6 => image.rotate90(),
+        7 => image.rotate270().fliph(),
+        8 => image.rotate270(),
+        _ => image,
+    }
+}
+
+/// 縦横が入れ替わる Orientation（5 .. 8）かどうか
+pub fn is_transposed(orientation: u16) -> bool {
+    (5..=8).contains(&orientation)
+}
+
+fn jpeg_exif(input: &[u8]) -> Option<&[u8]> {
+    // NOTE: Walk the marker segments after SOI until the image data starts
+    let mut offset = 2;
+    while let Some(marker) = input.get(offset..offset + 4) {
+        if marker[0] != 0xFF || marker[1] == 0xDA {
+            return None;
+        }
+
+        let length = u16::from_be_bytes([marker[2], marker[3]]) as usize;
+        let segment = input.get(offset + 4..offset + 2 + length)?;
+        if marker[1] == 0xE1 && segment.starts_with(EXIF_HEADER) {
+            return Some(&segment[EXIF_HEADER.len()..]);
+        }
+
+        offset += 2 + length;
+    }
+
+    None
+}
+
+fn png_exif(input: &[u8]) -> Option<&[u8]> {
+    // NOTE: Walk the chunks after the 8-byte signature (length, type, data, CRC)
+    let mut offset = 8;
+    while let Some(header) = input.get(offset..offset + 8) {
+        let length = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as usize;
+
+        match &header[4..8] {
+            b"eXIf" => return input.get(offset + 8..offset + 8 + length),
+            b"IEND" => return None,
+            _ => {}
+        }
+
+        offset = offset.saturating_add(length).saturating_add(12);
+    }
+
+    None
+}
+
+fn webp_exif(input: &[u8]) -> Option<&[u8]> {
+    if input.get(..4)? != b"RIFF" || input.get(8..12)? != b"WEBP" {
+        return None;
+    }
+
+    // NOTE: Walk the chunks after the RIFF header (FourCC, size, data padded to even length)
+    let mut offset = 12;
+    while let Some(header) = input.get(offset..offset + 8) {
+        let length = u32::from_le_bytes([header[4], header[5], header[6], header[7]]) as usize;
+
+        if &header[..4] == b"EXIF" {
+            let exif = input.get(offset + 8..offset + 8 + length)?;
+            // NOTE: Some writers keep the JPEG "Exif\0\0" header
+            return Some(exif.strip_prefix(EXIF_HEADER).unwrap_or(exif));
+        }
+
+        offset = offset.saturating_add(8).saturating_add(length + (length & 1));
+    }
+
+    None
+}
+
+/// TIFF 形式の EXIF の IFD0 から Orientation を取得
+fn tiff_orientation(tiff: &[u8]) -> Option<u16> {
+    let big_endian = match tiff.get(..4)? {
+        b"MM\0*" => true,
+        b"II*\0" => false,
+        _ => return None,
+    };
+    let read_u16 = |offset: usize| {
+        let bytes = [*tiff.get(offset)?, *tiff.get(offset + 1)?];
+        Some(if big_endian { u16::from_be_bytes(bytes) } else { u16::from_le_bytes(bytes) })
+    };
+    let read_u32 = |offset: usize| {
+        let bytes = tiff.get(offset..offset + 4)?;
+        let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+        Some(if big_endian { u32::from_be_bytes(bytes) } else { u32::from_le_bytes(bytes) })
+    };
+
+    let ifd = read_u32(4)? as usize;
+    let count = read_u16(ifd)? as usize;
+
+    (0..count)
+        .map(|index| ifd + 2 + index * 12)
+        .find(|entry| read_u16(*entry) == Some(ORIENTATION_TAG))
+        .and_then(|entry| read_u16(entry + 8))
+        .filter(|orientation| (1..=8).contains(orientation))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgb, RgbImage};
+
+    /// Orientation だけを持つ TIFF 形式の EXIF
+    fn tiff(big_endian: bool, orientation: u16) -> Vec<u8> {
+        let u16_bytes = |value: u16| if big_endian { value.to_be_bytes() } else { value.to_le_bytes() };
+        let u32_bytes = |value: u32| if big_endian { value.to_be_bytes() } else { value.to_le_bytes() };
+
+        let mut tiff = if big_endian { b"MM\0*".to_vec() } else { b"II*\0".to_vec() };
+        tiff.extend(u32_bytes(8));
+        tiff.extend(u16_bytes(2));
+        // NOTE: An unrelated entry (ImageWidth) comes before Orientation
+        tiff.extend(u16_bytes(0x0100));
+        tiff.extend(u16_bytes(3));
+        tiff.extend(u32_bytes(1));
+        tiff.extend(u16_bytes(640));
+        tiff.extend([0, 0]);
+        tiff.extend(u16_bytes(ORIENTATION_TAG));
+        tiff.extend(u16_bytes(3));
+        tiff.extend(u32_bytes(1));
+        tiff.extend(u16_bytes(orientation));
+        tiff.extend([0, 0]);
+        tiff.extend(u32_bytes(0));
+        tiff
+    }
+
+    fn jpeg(exif: &[u8]) -> Vec<u8> {
+        let mut app1 = EXIF_HEADER.to_vec();
+        app1.extend(exif);
+
+        let mut jpeg = vec![0xFF, 0xD8];
+        // NOTE: APP0 (JFIF) before APP1
+        jpeg.extend([0xFF, 0xE0, 0x00, 0x07, b'J', b'F', b'I', b'F', 0x00]);
+        jpeg.extend([0xFF, 0xE1]);
+        jpeg.extend((app1.len() as u16 + 2).to_be_bytes());
+        jpeg.extend(app1);
+        jpeg.extend([0xFF, 0xDA, 0x00, 0x02, 0xFF, 0xD9]);
+        jpeg
+    }
+
+    fn png(exif: &[u8]) -> Vec<u8> {
+        let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+        for (chunk_type, data) in [(b"IHDR", &[0u8; 13][..]), (b"eXIf", exif), (b"IEND", &[][..])] {
+            png.extend((data.len() as u32).to_be_bytes());
+            png.extend(chunk_type);
+            png.extend(data);
+            png.extend([0, 0, 0, 0]);
+        }
+        png
+    }
+
+    fn webp(exif: &[u8]) -> Vec<u8> {
+        let mut chunks = Vec::new();
+        for (fourcc, data) in [(b"VP8X", &[0u8; 10][..]), (b"EXIF", exif)] {
+            chunks.extend(fourcc);
+            chunks.extend((data.len() as u32).to_le_bytes());
+            chunks.extend(data);
+            if data.len() % 2 == 1 {
+                chunks.push(0);
+            }
+        }
+
+        let mut webp = b"RIFF".to_vec();
+        webp.extend((chunks.len() as u32 + 4).to_le_bytes());
+        webp.extend(b"WEBP");
+        webp.extend(chunks);
+        webp
+    }
+
+    #[test]
+    fn reads_orientation_from_tiff_in_both_byte_orders() {
+        assert_eq!(tiff_orientation(&tiff(true, 6)), Some(6));
+        assert_eq!(tiff_orientation(&tiff(false, 8)), Some(8));
+        assert_eq!(tiff_orientation(&tiff(false, 9)), None);
+        assert_eq!(tiff_orientation(b"XX*\0\0\0\0\x08"), None);
+        assert_eq!(tiff_orientation(&tiff(true, 6)[..20]), None);
+    }
+
+    #[test]
+    fn reads_orientation_from_each_container() {
+        assert_eq!(read(&FileType::JPEG, &jpeg(&tiff(true, 6))), 6);
+        assert_eq!(read(&FileType::PNG, &png(&tiff(false, 3))), 3);
+        assert_eq!(read(&FileType::WEBP, &webp(&tiff(false, 5))), 5);
+
+        let mut prefixed = EXIF_HEADER.to_vec();
+        prefixed.extend(tiff(true, 7));
+        assert_eq!(read(&FileType::WEBP, &webp(&prefixed)), 7);
+    }
+
+    #[test]
+    fn defaults_to_one_without_exif() {
+        assert_eq!(read(&FileType::JPEG, &[0xFF, 0xD8, 0xFF, 0xDA, 0x00, 0x02]), 1);
+        assert_eq!(read(&FileType::PNG, &png(&[])), 1);
+        assert_eq!(read(&FileType::WEBP, b"RIFF\x04\0\0\0WEBP"), 1);
+        assert_eq!(read(&FileType::TIFF, &tiff(false, 6)), 1);
+        // NOTE: Truncated segments must not panic
+        assert_eq!(read(&FileType::JPEG, &jpeg(&tiff(true, 6))[..12]), 1);
+        assert_eq!(read(&FileType::PNG, &png(&tiff(false, 3))[..40]), 1);
+    }
+
+    #[test]
+    fn apply_rotates_and_flips() {
+        // NOTE: 2x1 image with a red pixel on the left
+        let image = DynamicImage::ImageRgb8(RgbImage::from_fn(2, 1, |x, _| {
+            if x == 0 { Rgb([255, 0, 0]) } else { Rgb([0, 0, 255]) }
+        }));
+        let red_at = |orientation| {
+            let rgb = apply(image.clone(), orientation).to_rgb8();
+            let position = rgb.enumerate_pixels().find(|(_, _, pixel)| pixel[0] == 255);
+            (rgb.dimensions(), position.map(|(x, y, _)| (x, y)))
+        };
+
+        assert_eq!(red_at(1), ((2, 1), Some((0, 0))));
+        assert_eq!(red_at(2), ((2, 1), Some((1, 0))));
+        assert_eq!(red_at(3), ((2, 1), Some((1, 0))));
+        assert_eq!(red_at(6), ((1, 2), Some((0, 0))));
+        assert_eq!(red_at(8), ((1, 2), Some((0, 1))));
+        assert!(is_transposed(5) && is_transposed(8) && !is_transposed(4));
+    }
+}
@@ -1,14 +1,587 @@
-use crate::config_json::SizeFilterConfig;
-use image::DynamicImage;
-use image::imageops::FilterType;
-
-pub fn resize_image(image: &DynamicImage, config: &SizeFilterConfig) -> DynamicImage {
-    match config.filter.as_str() {
-        "nearest" => image.resize(config.width, config.height, FilterType::Nearest),
-        "triangle" => image.resize(config.width, config.height, FilterType::Triangle),
-        "catmull_rom" => image.resize(config.width, config.height, FilterType::CatmullRom),
-        "gaussian" => image.resize(config.width, config.height, FilterType::Gaussian),
-        "lanczos3" => image.resize(config.width, config.height, FilterType::Lanczos3),
-        _ => image.clone(),
+use crate::config_json::{
+    CropConfig, FocusConfig, OpConfig, PadConfig, SizeFilterConfig, TrimConfig,
+};
+use crate::error::CompressorError;
+use crate::imaging::color::parse_hex_color;
+use crate::imaging::crop;
+use anyhow::anyhow;
+use image::{DynamicImage, GenericImageView, Rgba, RgbaImage};
+use image::imageops::{self, FilterType};
+
+/// mode（fit, cover, exact, width-only, height-only）に従ってリサイズ
+///
+/// no_upscale の場合は元のサイズより大きくしない（cover は拡大せずに切り抜きのみ）。
+/// cover ではみ出した部分は focus・gravity の位置で切り抜く（既定は中央）
+pub fn resize_image(image: &DynamicImage, config: &SizeFilterConfig) -> anyhow::Result<DynamicImage> {
+    let (resized, crop_size) = scale_image(image, config)?;
+
+    let Some((crop_width, crop_height)) = crop_size else {
+        return Ok(resized);
+    };
+    let (x, y) = crop::crop_origin(
+        &resized,
+        crop_width,
+        crop_height,
+        config.gravity.as_deref(),
+        config.focus.as_ref(),
+    )?;
+
+    Ok(resized.crop_imm(x, y, crop_width, crop_height))
+}
+
+/// gravity（entropy, saliency）を image をリサイズした場合の切り抜き位置の focus に置き換える
+///
+/// アニメーションの全フレームを同じ位置で切り抜くために使用
+pub fn resolve_gravity(
+    image: &DynamicImage,
+    config: &SizeFilterConfig,
+) -> anyhow::Result<SizeFilterConfig> {
+    let is_content_aware = matches!(config.gravity.as_deref(), Some("entropy" | "saliency"));
+    if config.mode.as_deref() != Some("cover") || config.focus.is_some() || !is_content_aware {
+        return Ok(config.clone());
+    }
+
+    let (resized, crop_size) = scale_image(image, config)?;
+    let Some((crop_width, crop_height)) = crop_size else {
+        return Ok(config.clone());
+    };
+    let (x, y) = crop::crop_origin(
+        &resized,
+        crop_width,
+        crop_height,
+        config.gravity.as_deref(),
+        None,
+    )?;
+
+    // NOTE: The focus is the center of the chosen window, so crop_origin returns the same origin
+    Ok(SizeFilterConfig {
+        focus: Some(FocusConfig {
+            x: (x as f64 + crop_width as f64 / 2.0) / resized.width() as f64,
+            y: (y as f64 + crop_height as f64 / 2.0) / resized.height() as f64,
+        }),
+        ..config.clone()
+    })
+}
+
+/// mode に従って拡大・縮小した画像と、cover の場合は切り抜くサイズ
+fn scale_image(
+    image: &DynamicImage,
+    config: &SizeFilterConfig,
+) -> anyhow::Result<(DynamicImage, Option<(u32, u32)>)> {
+    let filter = filter_type(&config.filter)?;
+    let mode = config.mode.as_deref().unwrap_or("fit");
+    let (width, height) = (image.width() as f64, image.height() as f64);
+
+    let (scale_x, scale_y) = match mode {
+        "fit" => {
+            let scale = (target(config.width, "width", mode)? / width)
+                .min(target(config.height, "height", mode)? / height);
+            (scale, scale)
+        }
+        "cover" => {
+            let scale = (target(config.width, "width", mode)? / width)
+                .max(target(config.height, "height", mode)? / height);
+            (scale, scale)
+        }
+        "exact" => (
+            target(config.width, "width", mode)? / width,
+            target(config.height, "height", mode)? / height,
+        ),
+        "width-only" => {
+            let scale = target(config.width, "width", mode)? / width;
+            (scale, scale)
+        }
+        "height-only" => {
+            let scale = target(config.height, "height", mode)? / height;
+            (scale, scale)
+        }
+        _ => return Err(config_error(format!("Unknown resize mode: {}", mode))),
+    };
+
+    let (scale_x, scale_y) = if config.no_upscale.unwrap_or(false) {
+        (scale_x.min(1.0), scale_y.min(1.0))
+    } else {
+        (scale_x, scale_y)
+    };
+
+    let resized_width = ((width * scale_x).round() as u32).max(1);
+    let resized_height = ((height * scale_y).round() as u32).max(1);
+    let resized = if (resized_width, resized_height) == image.dimensions() {
+        image.clone()
+    } else {
+        image.resize_exact(resized_width, resized_height, filter)
+    };
+
+    if mode != "cover" {
+        return Ok((resized, None));
+    }
+
+    let crop_width = config.width.unwrap_or(resized_width).min(resized_width);
+    let crop_height = config.height.unwrap_or(resized_height).min(resized_height);
+
+    Ok((resized, Some((crop_width, crop_height))))
+}
+
+fn filter_type(filter: &str) -> anyhow::Result<FilterType> {
+    match filter {
+        "nearest" => Ok(FilterType::Nearest),
+        "triangle" => Ok(FilterType::Triangle),
+        "catmull_rom" => Ok(FilterType::CatmullRom),
+        "gaussian" => Ok(FilterType::Gaussian),
+        "lanczos3" => Ok(FilterType::Lanczos3),
+        _ => Err(config_error(format!("Unknown resize filter: {}", filter))),
+    }
+}
+
+/// mode で必要な幅・高さ
+fn target(value: Option<u32>, name: &str, mode: &str) -> anyhow::Result<f64> {
+    value
+        .filter(|value| *value > 0)
+        .map(f64::from)
+        .ok_or_else(|| config_error(format!("size.{} is required for {} mode", name, mode)))
+}
+
+fn config_error(message: String) -> anyhow::Error {
+    anyhow!(CompressorError::ConfigError(message))
+}
+
+/// ops（crop, rotate, flip, pad, trim, resize）を順に適用
+pub fn apply_ops(image: DynamicImage, ops: &[OpConfig]) -> anyhow::Result<DynamicImage> {
+    ops.iter().try_fold(image, apply_op)
+}
+
+/// trim を image に ops を適用した場合の範囲の crop に、resize の gravity（entropy, saliency）を focus に置き換える
+///
+/// アニメーションの全フレームを同じ範囲で切り抜くために使用
+pub fn resolve_ops(image: &DynamicImage, ops: &[OpConfig]) -> anyhow::Result<Vec<OpConfig>> {
+    let mut image = image.clone();
+    let mut resolved = Vec::with_capacity(ops.len());
+
+    for op in ops {
+        let op = match op {
+            OpConfig::Trim(trim_config) => OpConfig::Crop(trim_region(&image, trim_config)?),
+            OpConfig::Resize(size_config) => OpConfig::Resize(resolve_gravity(&image, size_config)?),
+            op => op.clone(),
+        };
+        image = apply_op(image, &op)?;
+        resolved.push(op);
+    }
+
+    Ok(resolved)
+}
+
+fn apply_op(image: DynamicImage, op: &OpConfig) -> anyhow::Result<DynamicImage> {
+    match op {
+        OpConfig::Crop(crop_config) => crop_image(&image, crop_config),
+        OpConfig::Rotate(90) => Ok(image.rotate90()),
+        OpConfig::Rotate(180) => Ok(image.rotate180()),
+        OpConfig::Rotate(270) => Ok(image.rotate270()),
+        OpConfig::Rotate(angle) => Err(config_error(format!("Unsupported rotate angle: {}", angle))),
+        OpConfig::Flip(direction) => match direction.as_str() {
+            "horizontal" => Ok(image.fliph()),
+            "vertical" => Ok(image.flipv()),
+            _ => Err(config_error(format!("Unknown flip direction: {}", direction))),
+        },
+        OpConfig::Pad(pad_config) => pad_image(&image, pad_config),
+        OpConfig::Trim(trim_config) => crop_image(&image, &trim_region(&image, trim_config)?),
+        OpConfig::Resize(size_config) => resize_image(&image, size_config),
+    }
+}
+
+/// 画像からはみ出す部分は切り詰める
+fn crop_image(image: &DynamicImage, config: &CropConfig) -> anyhow::Result<DynamicImage> {
+    let (width, height) = image.dimensions();
+
+    if config.x >= width || config.y >= height || config.width == 0 || config.height == 0 {
+        return Err(config_error(format!(
+            "Crop region {}x{}+{}+{} is outside of the {}x{} image",
+            config.width, config.height, config.x, config.y, width, height
+        )));
+    }
+
+    if (config.x, config.y, config.width, config.height) == (0, 0, width, height) {
+        return Ok(image.clone());
+    }
+
+    Ok(image.crop_imm(
+        config.x,
+        config.y,
+        config.width.min(width - config.x),
+        config.height.min(height - config.y),
+    ))
+}
+
+/// 上下左右に余白を追加し、width・height・square に足りない分は両側に均等に追加
+fn pad_image(image: &DynamicImage, config: &PadConfig) -> anyhow::Result<DynamicImage> {
+    let background = match config.background.as_deref() {
+        Some(background) => parse_hex_color(background)
+            .ok_or_else(|| config_error(format!("Invalid pad background: {}", background)))?,
+        None => Rgba([0, 0, 0, 0]),
+    };
+
+    let (width, height) = image.dimensions();
+    let (top, right, bottom, left) = (
+        config.top.unwrap_or(0),
+        config.right.unwrap_or(0),
+        config.bottom.unwrap_or(0),
+        config.left.unwrap_or(0),
+    );
+    let (padded_width, padded_height) = (width + left + right, height + top + bottom);
+
+    let mut canvas_width = padded_width.max(config.width.unwrap_or(0));
+    let mut canvas_height = padded_height.max(config.height.unwrap_or(0));
+    if config.square.unwrap_or(false) {
+        canvas_width = canvas_width.max(canvas_height);
+        canvas_height = canvas_width;
+    }
+
+    if (canvas_width, canvas_height) == (width, height) {
+        return Ok(image.clone());
+    }
+
+    let mut canvas = RgbaImage::from_pixel(canvas_width, canvas_height, background);
+    imageops::replace(
+        &mut canvas,
+        &image.to_rgba8(),
+        (left + (canvas_width - padded_width) / 2) as i64,
+        (top + (canvas_height - padded_height) / 2) as i64,
+    );
+
+    // NOTE: Keep opaque images without alpha so that encoders don't store an unused channel
+    if !image.color().has_alpha() && background[3] == 255 {
+        return Ok(DynamicImage::ImageRgb8(DynamicImage::ImageRgba8(canvas).to_rgb8()));
+    }
+
+    Ok(DynamicImage::ImageRgba8(canvas))
+}
+
+/// 背景色との差が fuzz 以内の画素だけの行・列を周囲から取り除いた範囲
+///
+/// 全ての画素が背景色の場合は画像全体
+fn trim_region(image: &DynamicImage, config: &TrimConfig) -> anyhow::Result<CropConfig> {
+    let rgba_image = image.to_rgba8();
+    let (width, height) = rgba_image.dimensions();
+
+    let background = match config.background.as_deref() {
+        Some(background) => parse_hex_color(background)
+            .ok_or_else(|| config_error(format!("Invalid trim background: {}", background)))?,
+        None => *rgba_image.get_pixel(0, 0),
+    };
+    let threshold = config.fuzz.unwrap_or(0.0).clamp(0.0, 100.0) / 100.0 * 255.0;
+
+    let is_background = |pixel: &Rgba<u8>| {
+        // NOTE: Fully transparent pixels match regardless of their colour
+        if pixel[3] == 0 && background[3] == 0 {
+            return true;
+        }
+
+        (0..4).all(|i| (pixel[i] as f64 - background[i] as f64).abs() <= threshold)
+    };
+
+    let (mut min_x, mut min_y, mut max_x, mut max_y) = (width, height, 0, 0);
+    for (x, y, pixel) in rgba_image.enumerate_pixels() {
+        if !is_background(pixel) {
+            min_x = min_x.min(x);
+            min_y = min_y.min(y);
+            max_x = max_x.max(x);
+            max_y = max_y.max(y);
+        }
+    }
+
+    if min_x > max_x || min_y > max_y {
+        return Ok(CropConfig {
+            x: 0,
+            y: 0,
+            width,
+            height,
+        });
+    }
+
+    Ok(CropConfig {
+        x: min_x,
+        y: min_y,
+        width: max_x - min_x + 1,
+        height: max_y - min_y + 1,
+    })
+}
+
+/// 透過画像を背景色に合成して不透明にする
+pub fn flatten(image: &DynamicImage, background: Rgba<u8>) -> DynamicImage {
+    let mut rgba_image = image.to_rgba8();
+
+    for pixel in rgba_image.pixels_mut() {
+        let alpha = pixel[3] as u32;
+        for i in 0..3 {
+            pixel[i] = ((pixel[i] as u32 * alpha + background[i] as u32 * (255 - alpha) + 127) / 255) as u8;
+        }
+        pixel[3] = 255;
+    }
+
+    DynamicImage::ImageRgb8(DynamicImage::ImageRgba8(rgba_image).to_rgb8())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgb, RgbImage};
+
+    /// 灰色の背景の (x, y) に size x size の模様がある画像
+    fn feature_image(width: u32, height: u32, x: u32, y: u32, size: u32) -> DynamicImage {
+        DynamicImage::ImageRgb8(RgbImage::from_fn(width, height, |px, py| {
+            if (x..x + size).contains(&px) && (y..y + size).contains(&py) {
+                let value = ((px * 37 + py * 91) % 256) as u8;
+                Rgb([value, 255 - value, value / 2])
+            } else {
+                Rgb([128, 128, 128])
+            }
+        }))
+    }
+
+    fn cover(width: u32, height: u32, gravity: &str) -> SizeFilterConfig {
+        SizeFilterConfig {
+            width: Some(width),
+            height: Some(height),
+            filter: "triangle".to_string(),
+            mode: Some("cover".to_string()),
+            no_upscale: None,
+            gravity: Some(gravity.to_string()),
+            focus: None,
+        }
+    }
+
+    fn ops(json: &str) -> Vec<OpConfig> {
+        serde_json::from_str(json).unwrap()
+    }
+
+    fn region(config: &CropConfig) -> (u32, u32, u32, u32) {
+        (config.x, config.y, config.width, config.height)
+    }
+
+    fn trim(fuzz: Option<f64>, background: Option<&str>) -> TrimConfig {
+        TrimConfig {
+            fuzz,
+            background: background.map(|background| background.to_string()),
+        }
+    }
+
+    #[test]
+    fn trim_region_finds_content() {
+        let image = feature_image(100, 80, 20, 10, 30);
+
+        assert_eq!(region(&trim_region(&image, &trim(None, None)).unwrap()), (20, 10, 30, 30));
+        assert_eq!(
+            region(&trim_region(&image, &trim(None, Some("#000"))).unwrap()),
+            (0, 0, 100, 80)
+        );
+        assert!(trim_region(&image, &trim(None, Some("gray"))).is_err());
+    }
+
+    #[test]
+    fn trim_region_uses_fuzz() {
+        let mut image = feature_image(50, 50, 0, 0, 0).to_rgb8();
+        image.put_pixel(5, 5, Rgb([140, 140, 140]));
+        image.put_pixel(40, 30, Rgb([0, 0, 0]));
+        let image = DynamicImage::ImageRgb8(image);
+
+        assert_eq!(region(&trim_region(&image, &trim(None, None)).unwrap()), (5, 5, 36, 26));
+        assert_eq!(region(&trim_region(&image, &trim(Some(10.0), None)).unwrap()), (40, 30, 1, 1));
+    }
+
+    #[test]
+    fn trim_region_keeps_uniform_images() {
+        let image = feature_image(40, 20, 0, 0, 0);
+
+        assert_eq!(region(&trim_region(&image, &trim(None, None)).unwrap()), (0, 0, 40, 20));
+    }
+
+    #[test]
+    fn apply_ops_runs_in_order() {
+        let image = feature_image(100, 50, 0, 0, 0);
+
+        let padded = apply_ops(
+            image.clone(),
+            &ops(r##"[{"pad": {"square": true, "background": "#fff"}}]"##),
+        )
+        .unwrap();
+        assert_eq!(padded.dimensions(), (100, 100));
+        assert!(!padded.color().has_alpha());
+
+        let rotated =
+            apply_ops(image.clone(), &ops(r#"[{"rotate": 90}, {"flip": "vertical"}]"#)).unwrap();
+        assert_eq!(rotated.dimensions(), (50, 100));
+
+        let cropped = apply_ops(
+            image.clone(),
+            &ops(r#"[
+                {"crop": {"x": 80, "y": 10, "width": 50, "height": 20}},
+                {"pad": {"left": 5}}
+            ]"#),
+        )
+        .unwrap();
+        assert_eq!(cropped.dimensions(), (25, 20));
+        assert!(cropped.color().has_alpha());
+    }
+
+    #[test]
+    fn apply_ops_rejects_invalid_ops() {
+        let image = feature_image(100, 50, 0, 0, 0);
+
+        let outside = ops(r#"[{"crop": {"x": 100, "y": 0, "width": 1, "height": 1}}]"#);
+
+        assert!(apply_ops(image.clone(), &outside).is_err());
+        assert!(apply_ops(image.clone(), &ops(r#"[{"rotate": 45}]"#)).is_err());
+        assert!(apply_ops(image, &ops(r#"[{"flip": "diagonal"}]"#)).is_err());
+    }
+
+    #[test]
+    fn resolve_ops_replaces_trim_with_crop() {
+        let image = feature_image(100, 80, 20, 10, 30);
+        let resolved = resolve_ops(
+            &image,
+            &ops(r#"[{"rotate": 180}, {"trim": {}}, {"flip": "horizontal"}]"#),
+        )
+        .unwrap();
+
+        match resolved.as_slice() {
+            [OpConfig::Rotate(180), OpConfig::Crop(crop), OpConfig::Flip(_)] => {
+                assert_eq!(region(crop), (50, 40, 30, 30));
+            }
+            resolved => panic!("unexpected ops: {:?}", resolved),
+        }
+
+        // NOTE: Later frames are cropped to the first frame's region even without the content
+        let later = apply_ops(feature_image(100, 80, 0, 0, 0), &resolved).unwrap();
+        assert_eq!(later.dimensions(), (30, 30));
+    }
+
+    #[test]
+    fn resolve_ops_fixes_content_aware_resize() {
+        let image = feature_image(300, 100, 220, 20, 60);
+        let resolved = resolve_ops(
+            &image,
+            &ops(r#"[{"resize": {
+                "width": 50, "height": 50, "filter": "triangle", "mode": "cover", "gravity": "saliency"
+            }}]"#),
+        )
+        .unwrap();
+
+        match resolved.as_slice() {
+            [OpConfig::Resize(size)] => assert!(size.focus.is_some()),
+            resolved => panic!("unexpected ops: {:?}", resolved),
+        }
+    }
+
+    #[test]
+    fn resolve_gravity_keeps_the_crop_of_the_first_frame() {
+        let first = feature_image(300, 100, 220, 20, 60);
+        let config = cover(50, 50, "entropy");
+        let resolved = resolve_gravity(&first, &config).unwrap();
+
+        assert!(resolved.focus.is_some());
+        assert_eq!(
+            resize_image(&first, &resolved).unwrap().to_rgb8(),
+            resize_image(&first, &config).unwrap().to_rgb8()
+        );
+
+        // NOTE: A later frame without the detail is cropped at the same position
+        let later = feature_image(300, 100, 0, 0, 0);
+        let (x, _) = crop::crop_origin(
+            &later.resize_exact(150, 50, FilterType::Triangle),
+            50,
+            50,
+            None,
+            resolved.focus.as_ref(),
+        )
+        .unwrap();
+        assert!(x >= 90, "x = {}", x);
+    }
+
+    #[test]
+    fn resolve_gravity_ignores_fixed_positions() {
+        let image = feature_image(300, 100, 220, 20, 60);
+
+        assert!(resolve_gravity(&image, &cover(50, 50, "north")).unwrap().focus.is_none());
+        assert!(
+            resolve_gravity(&image, &SizeFilterConfig { mode: None, ..cover(50, 50, "entropy") })
+                .unwrap()
+                .focus
+                .is_none()
+        );
+    }
+
+    fn size(mode: &str, width: Option<u32>, height: Option<u32>, no_upscale: bool) -> SizeFilterConfig {
+        SizeFilterConfig {
+            width,
+            height,
+            filter: "triangle".to_string(),
+            mode: Some(mode.to_string()),
+            no_upscale: Some(no_upscale),
+            gravity: None,
+            focus: None,
+        }
+    }
+
+    /// scale_image の結果（拡大・縮小後のサイズ、cover の場合は切り抜くサイズ）
+    fn scaled(width: u32, height: u32, config: &SizeFilterConfig) -> ((u32, u32), Option<(u32, u32)>) {
+        let (resized, crop_size) = scale_image(&feature_image(width, height, 0, 0, 1), config).unwrap();
+        (resized.dimensions(), crop_size)
+    }
+
+    #[test]
+    fn fit_keeps_aspect_ratio_inside_the_box() {
+        assert_eq!(scaled(200, 100, &size("fit", Some(100), Some(100), false)), ((100, 50), None));
+        assert_eq!(scaled(100, 200, &size("fit", Some(100), Some(100), false)), ((50, 100), None));
+        assert_eq!(scaled(40, 20, &size("fit", Some(100), Some(100), false)), ((100, 50), None));
+        assert_eq!(scaled(40, 20, &size("fit", Some(100), Some(100), true)), ((40, 20), None));
+    }
+
+    #[test]
+    fn cover_fills_the_box_and_crops() {
+        assert_eq!(scaled(200, 100, &size("cover", Some(100), Some(100), false)), ((200, 100), Some((100, 100))));
+        assert_eq!(scaled(300, 100, &size("cover", Some(60), Some(40), false)), ((120, 40), Some((60, 40))));
+        assert_eq!(scaled(40, 20, &size("cover", Some(100), Some(100), false)), ((200, 100), Some((100, 100))));
+        // NOTE: Without upscaling, the image is only cropped to the part that fits in the box
+        assert_eq!(scaled(40, 20, &size("cover", Some(100), Some(10), true)), ((40, 20), Some((40, 10))));
+        assert_eq!(
+            resize_image(&feature_image(300, 100, 0, 0, 1), &size("cover", Some(60), Some(40), false)).unwrap().dimensions(),
+            (60, 40)
+        );
+    }
+
+    #[test]
+    fn exact_ignores_aspect_ratio() {
+        assert_eq!(scaled(200, 100, &size("exact", Some(50), Some(80), false)), ((50, 80), None));
+        assert_eq!(scaled(20, 10, &size("exact", Some(50), Some(5), false)), ((50, 5), None));
+        // NOTE: Each side is limited separately
+        assert_eq!(scaled(20, 10, &size("exact", Some(50), Some(5), true)), ((20, 5), None));
+    }
+
+    #[test]
+    fn single_side_modes_keep_aspect_ratio() {
+        assert_eq!(scaled(200, 100, &size("width-only", Some(50), None, false)), ((50, 25), None));
+        assert_eq!(scaled(200, 100, &size("height-only", None, Some(50), false)), ((100, 50), None));
+        assert_eq!(scaled(200, 100, &size("width-only", Some(400), None, true)), ((200, 100), None));
+        // NOTE: Each side is at least 1 pixel
+        assert_eq!(scaled(1000, 10, &size("width-only", Some(10), None, false)), ((10, 1), None));
+    }
+
+    #[test]
+    fn missing_sides_and_unknown_modes_are_config_errors() {
+        let image = feature_image(20, 10, 0, 0, 1);
+
+        for config in [
+            size("fit", Some(10), None, false),
+            size("cover", None, Some(10), false),
+            size("exact", Some(10), Some(0), false),
+            size("height-only", Some(10), None, false),
+            size("fill", Some(10), Some(10), false),
+        ] {
+            let error = scale_image(&image, &config).unwrap_err();
+            assert!(
+                matches!(error.downcast_ref::<CompressorError>(), Some(CompressorError::ConfigError(_))),
+                "{:?}",
+                config.mode
+            );
+        }
     }
 }
@@ -1 +1 @@
-pub(crate) mod file;
\ No newline at end of file
+pub mod file;
\ No newline at end of file
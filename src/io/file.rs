@@ -2,8 +2,12 @@ use crate::error::{CompressorError, Result};
 use crate::file_type::FileType;
 use image::DynamicImage;
 use image::ImageReader;
-use std::fs::File;
-use std::io::{BufReader, Read, Write};
+use filetime::FileTime;
+use std::collections::HashSet;
+use std::fs::{File, Metadata};
+use std::io::{BufReader, Cursor, ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 pub fn read_image_from_file(file_path: &str) -> Result<DynamicImage> {
     let file = File::open(file_path)
@@ -18,6 +22,15 @@ pub fn read_image_from_file(file_path: &str) -> Result<DynamicImage> {
         .map_err(|e| CompressorError::ImageDecodeError(e))
 }
 
+pub fn read_image_from_bytes(buffer: &[u8]) -> Result<DynamicImage> {
+    let image_reader = ImageReader::new(Cursor::new(buffer))
+        .with_guessed_format()
+        .map_err(|e| CompressorError::ImageFormatError(e.to_string()))?;
+
+    image_reader.decode()
+        .map_err(CompressorError::ImageDecodeError)
+}
+
 pub fn detect_file_type(file_path: &str) -> Result<FileType> {
     let file = File::open(file_path)
         .map_err(|e| CompressorError::IoError(e))?;
@@ -40,12 +53,128 @@ pub fn read_file_bytes(file_path: &str) -> Result<Vec<u8>> {
     Ok(buffer)
 }
 
+/// 同じディレクトリの一時ファイルに書き込み、fsync 後に置き換える
+///
+/// 書き込み途中で失敗しても出力先が壊れない（既存ファイルのパーミッションは維持）
 pub fn write_file_bytes(file_path: &str, data: &[u8]) -> Result<()> {
-    let mut file = File::create(file_path)
-        .map_err(|e| CompressorError::IoError(e))?;
+    let path = Path::new(file_path);
+    let temp_path = temp_file_path(path);
 
-    file.write_all(data)
-        .map_err(|e| CompressorError::IoError(e))?;
+    let result = write_and_rename(path, &temp_path, data);
+    if result.is_err() {
+        let _ = std::fs::remove_file(&temp_path);
+    }
+
+    result.map_err(CompressorError::IoError)
+}
+
+fn write_and_rename(path: &Path, temp_path: &Path, data: &[u8]) -> std::io::Result<()> {
+    let mut file = File::create(temp_path)?;
+    file.write_all(data)?;
+
+    if let Ok(metadata) = std::fs::metadata(path) {
+        file.set_permissions(metadata.permissions())?;
+    }
+
+    file.sync_all()?;
+    drop(file);
+
+    std::fs::rename(temp_path, path)?;
+
+    // NOTE: Persist the rename itself (directories cannot be opened on some platforms)
+    if let Some(parent) = path.parent()
+        && let Ok(dir) = File::open(parent_or_current(parent))
+    {
+        let _ = dir.sync_all();
+    }
+
+    Ok(())
+}
+
+fn temp_file_path(path: &Path) -> PathBuf {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    let file_name = path
+        .file_name()
+        .map(|file_name| file_name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    path.with_file_name(format!(
+        ".{}.{}.{}.tmp",
+        file_name,
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    ))
+}
+
+fn parent_or_current(parent: &Path) -> &Path {
+    if parent.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        parent
+    }
+}
+
+pub fn get_file_metadata(file_path: &str) -> Result<Metadata> {
+    std::fs::metadata(file_path).map_err(CompressorError::IoError)
+}
+
+/// 入力ファイルの所有者（権限がある場合のみ）・パーミッション・アクセス/更新日時を出力ファイルに反映
+pub fn apply_file_attributes(file_path: &str, metadata: &Metadata) -> Result<()> {
+    // NOTE: Change the owner first, because chown may clear the setuid/setgid bits
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+
+        let owner = std::os::unix::fs::chown(file_path, Some(metadata.uid()), Some(metadata.gid()));
+        if let Err(e) = owner
+            && e.kind() != ErrorKind::PermissionDenied
+        {
+            return Err(CompressorError::IoError(e));
+        }
+    }
+
+    std::fs::set_permissions(file_path, metadata.permissions())
+        .map_err(CompressorError::IoError)?;
+
+    filetime::set_file_times(
+        file_path,
+        FileTime::from_last_access_time(metadata),
+        FileTime::from_last_modification_time(metadata),
+    )
+    .map_err(CompressorError::IoError)?;
+
+    Ok(())
+}
+
+/// in-place で置き換える前の元ファイルの拡張子
+pub const BACKUP_EXTENSION: &str = "bak";
+
+/// 元ファイルを `<file>.bak` にコピー
+pub fn backup_file(file_path: &str) -> Result<String> {
+    let backup_path = format!("{}.{}", file_path, BACKUP_EXTENSION);
+    copy_file(file_path, &backup_path)?;
+
+    Ok(backup_path)
+}
+
+/// 標準入力・標準出力を表すパス
+pub const STDIO_PATH: &str = "-";
+
+pub fn read_stdin_bytes() -> Result<Vec<u8>> {
+    let mut buffer = Vec::new();
+    std::io::stdin()
+        .lock()
+        .read_to_end(&mut buffer)
+        .map_err(CompressorError::IoError)?;
+
+    Ok(buffer)
+}
+
+pub fn write_stdout_bytes(data: &[u8]) -> Result<()> {
+    let mut stdout = std::io::stdout().lock();
+    stdout.write_all(data).map_err(CompressorError::IoError)?;
+    stdout.flush().map_err(CompressorError::IoError)?;
 
     Ok(())
 }
@@ -55,4 +184,193 @@ pub fn get_file_size(file_path: &str) -> Result<u64> {
         .map_err(|e| CompressorError::IoError(e))?;
 
     Ok(metadata.len())
-}
\ No newline at end of file
+}
+
+/// ディレクトリ配下のファイルを再帰的に列挙（シンボリックリンクで同じディレクトリに戻る場合は1回だけ）
+pub fn list_files(dir_path: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut visited = HashSet::new();
+
+    collect_files(dir_path, &mut visited, &mut files)?;
+
+    Ok(files)
+}
+
+fn collect_files(dir_path: &Path, visited: &mut HashSet<PathBuf>, files: &mut Vec<PathBuf>) -> Result<()> {
+    // NOTE: A symlink to an ancestor directory would otherwise recurse forever
+    let canonical = dir_path.canonicalize().map_err(CompressorError::IoError)?;
+    if !visited.insert(canonical) {
+        return Ok(());
+    }
+
+    let mut entries = std::fs::read_dir(dir_path)
+        .map_err(CompressorError::IoError)?
+        .collect::<std::io::Result<Vec<_>>>()
+        .map_err(CompressorError::IoError)?;
+    entries.sort_by_key(|entry| entry.file_name());
+
+    for entry in entries {
+        let path = entry.path();
+        if path.is_dir() {
+            collect_files(&path, visited, files)?;
+        } else if path.is_file() {
+            files.push(path);
+        }
+    }
+
+    Ok(())
+}
+
+pub fn create_parent_dir(file_path: &str) -> Result<()> {
+    if let Some(parent) = Path::new(file_path).parent()
+        && !parent.as_os_str().is_empty()
+    {
+        std::fs::create_dir_all(parent).map_err(CompressorError::IoError)?;
+    }
+
+    Ok(())
+}
+
+pub fn copy_file(from_path: &str, to_path: &str) -> Result<u64> {
+    std::fs::copy(from_path, to_path)
+        .map_err(CompressorError::IoError)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// テストごとの一時ディレクトリ
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "rs-image-compressor-file-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn list_files_recurses_in_name_order() {
+        let dir = temp_dir("list");
+        std::fs::create_dir_all(dir.join("b/c")).unwrap();
+        for name in ["b/c/z.png", "b/a.png", "a.png"] {
+            std::fs::write(dir.join(name), b"data").unwrap();
+        }
+
+        assert_eq!(
+            list_files(&dir).unwrap(),
+            vec![dir.join("a.png"), dir.join("b/a.png"), dir.join("b/c/z.png")]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn list_files_stops_at_symlink_loops() {
+        let dir = temp_dir("symlink");
+        std::fs::create_dir_all(dir.join("sub")).unwrap();
+        std::fs::write(dir.join("sub/a.png"), b"data").unwrap();
+        std::os::unix::fs::symlink(&dir, dir.join("sub/loop")).unwrap();
+
+        assert_eq!(list_files(&dir).unwrap(), vec![dir.join("sub/a.png")]);
+    }
+
+    #[test]
+    fn write_file_bytes_replaces_without_leftovers() {
+        let dir = temp_dir("write");
+        let path = dir.join("a.png");
+        std::fs::write(&path, b"old data").unwrap();
+
+        write_file_bytes(&path.to_string_lossy(), b"new").unwrap();
+
+        assert_eq!(std::fs::read(&path).unwrap(), b"new");
+        assert_eq!(list_files(&dir).unwrap(), vec![path]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn write_file_bytes_keeps_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = temp_dir("permissions");
+        let path = dir.join("a.png");
+        std::fs::write(&path, b"old data").unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o640)).unwrap();
+
+        write_file_bytes(&path.to_string_lossy(), b"new").unwrap();
+
+        assert_eq!(std::fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o640);
+    }
+
+    #[test]
+    fn write_file_bytes_fails_without_touching_the_directory() {
+        let dir = temp_dir("missing");
+        let path = dir.join("missing/a.png");
+
+        assert!(write_file_bytes(&path.to_string_lossy(), b"new").is_err());
+        assert!(list_files(&dir).unwrap().is_empty());
+    }
+
+    #[test]
+    fn backup_file_copies_next_to_the_original() {
+        let dir = temp_dir("backup");
+        let path = dir.join("a.png");
+        std::fs::write(&path, b"original").unwrap();
+
+        let backup_path = backup_file(&path.to_string_lossy()).unwrap();
+        write_file_bytes(&path.to_string_lossy(), b"compressed").unwrap();
+
+        assert_eq!(backup_path, format!("{}.bak", path.to_string_lossy()));
+        assert_eq!(std::fs::read(&backup_path).unwrap(), b"original");
+        assert_eq!(std::fs::read(&path).unwrap(), b"compressed");
+    }
+
+    #[test]
+    fn apply_file_attributes_copies_times() {
+        let dir = temp_dir("times");
+        let input = dir.join("in.png");
+        let output = dir.join("out.png");
+        std::fs::write(&input, b"input").unwrap();
+        std::fs::write(&output, b"output").unwrap();
+        filetime::set_file_times(&input, FileTime::from_unix_time(1_000_000_000, 0), FileTime::from_unix_time(1_200_000_000, 0)).unwrap();
+
+        let metadata = get_file_metadata(&input.to_string_lossy()).unwrap();
+        apply_file_attributes(&output.to_string_lossy(), &metadata).unwrap();
+
+        let output_metadata = std::fs::metadata(&output).unwrap();
+        assert_eq!(FileTime::from_last_modification_time(&output_metadata).unix_seconds(), 1_200_000_000);
+        assert_eq!(FileTime::from_last_access_time(&output_metadata).unix_seconds(), 1_000_000_000);
+        assert_eq!(std::fs::read(&output).unwrap(), b"output");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn apply_file_attributes_copies_permissions_and_owner() {
+        use std::os::unix::fs::{MetadataExt, PermissionsExt};
+
+        let dir = temp_dir("mode");
+        let input = dir.join("in.png");
+        let output = dir.join("out.png");
+        std::fs::write(&input, b"input").unwrap();
+        std::fs::write(&output, b"output").unwrap();
+        std::fs::set_permissions(&input, std::fs::Permissions::from_mode(0o604)).unwrap();
+
+        let metadata = get_file_metadata(&input.to_string_lossy()).unwrap();
+        apply_file_attributes(&output.to_string_lossy(), &metadata).unwrap();
+
+        let output_metadata = std::fs::metadata(&output).unwrap();
+        assert_eq!(output_metadata.permissions().mode() & 0o777, 0o604);
+        assert_eq!((output_metadata.uid(), output_metadata.gid()), (metadata.uid(), metadata.gid()));
+    }
+
+    #[test]
+    fn apply_file_attributes_fails_for_missing_outputs() {
+        let dir = temp_dir("attributes-missing");
+        std::fs::write(dir.join("in.png"), b"input").unwrap();
+        let metadata = get_file_metadata(&dir.join("in.png").to_string_lossy()).unwrap();
+
+        assert!(apply_file_attributes(&dir.join("missing.png").to_string_lossy(), &metadata).is_err());
+    }
+}
@@ -0,0 +1,11 @@
+pub mod compressor;
+pub mod config_json;
+pub mod error;
+pub mod file_type;
+pub mod imaging;
+pub mod io;
+
+pub use compressor::{compress_bytes, convert_bytes, CompressedOutput, Status};
+pub use config_json::Config;
+pub use error::CompressorError;
+pub use file_type::FileType;
@@ -1,35 +1,78 @@
-mod compressor;
-mod config_json;
-mod error;
-mod file_type;
-mod imaging;
-mod io;
-
-use crate::config_json::Config;
-use crate::error::CompressorError;
+mod batch;
+mod cache;
+mod report;
+
+use batch::{FileResult, Outcome, Summary};
+use cache::Cache;
+use rs_image_compressor::config_json::Config;
+use rs_image_compressor::error::CompressorError;
+use rs_image_compressor::compressor::OutputOptions;
+use rs_image_compressor::io::file::STDIO_PATH;
+use rs_image_compressor::{compressor, config_json, file_type};
 use anyhow::{anyhow, Result};
 use clap::Parser;
+use std::io::{self, Write};
+use std::path::Path;
+use std::time::Instant;
 
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
 struct Args {
-    #[arg(short, long)]
-    input: String,
+    /// Input file, directory or glob pattern (can be repeated, "-": standard input)
+    #[arg(short, long, required = true, num_args = 1..)]
+    input: Vec<String>,
 
-    #[arg(short, long)]
-    output: String,
+    /// Output file, or output directory in batch mode ("-": standard output)
+    #[arg(short, long, required_unless_present = "in_place", conflicts_with = "in_place")]
+    output: Option<String>,
 
     #[arg(short, long)]
     config: Option<String>,
 
     #[arg(short, long)]
     verbose: bool,
+
+    /// Copy unsupported files to the output directory instead of skipping them
+    #[arg(long)]
+    copy_unsupported: bool,
+
+    /// Output format (png, jpeg, webp, heif, avif, jxl, tiff, ico). Inferred from the output file extension if omitted
+    #[arg(short, long)]
+    format: Option<String>,
+
+    /// Number of files compressed in parallel in batch mode (0: number of CPUs)
+    #[arg(short, long, default_value_t = 1)]
+    jobs: usize,
+
+    /// Write the compressed output even if it is larger than the original
+    #[arg(long)]
+    allow_larger: bool,
+
+    /// Write a JSON report of the results to a file ("-": standard output)
+    #[arg(long, value_name = "PATH")]
+    report: Option<String>,
+
+    /// Replace the input files with the compressed output (files whose output format differs fail)
+    #[arg(long, conflicts_with = "format")]
+    in_place: bool,
+
+    /// Keep the original file as "<file>.bak" in in-place mode
+    #[arg(long, requires = "in_place")]
+    backup: bool,
+
+    /// Copy timestamps, permissions and (when permitted) ownership from the input to the output
+    #[arg(long)]
+    preserve_attributes: bool,
+
+    /// Skip unchanged files in batch mode using a manifest of content and config hashes
+    #[arg(long, value_name = "PATH")]
+    cache: Option<String>,
 }
 
 fn main() -> Result<()> {
     let args = Args::parse();
 
-    let config = if args.config.is_none() {
+    let mut config = if args.config.is_none() {
         Config::default()
     } else {
         match config_json::parse(args.config.unwrap().as_str()) {
@@ -38,7 +81,136 @@ fn main() -> Result<()> {
         }
     };
 
-    compressor::compress(config, args.verbose, &args.input, &args.output)?;
+    if args.allow_larger {
+        config.allow_larger = Some(true);
+    }
+
+    let format = match args.format.as_ref() {
+        Some(format) => Some(
+            file_type::from_extension(format)
+                .filter(compressor::is_output_format)
+                .ok_or_else(|| anyhow!("Unsupported output format: {}", format))?,
+        ),
+        None => None,
+    };
+
+    // NOTE: In-place mode writes back to the input file
+    let output = args.output.clone().unwrap_or_else(|| args.input[0].clone());
+    let options = OutputOptions {
+        backup: args.backup,
+        preserve_attributes: args.preserve_attributes,
+        metrics: args.report.is_some(),
+        report_to_stdout: args.report.as_deref() == Some(STDIO_PATH),
+    };
+
+    let uses_stdio = args.input.iter().any(|input| input == STDIO_PATH) || output == STDIO_PATH;
+
+    if args.in_place && uses_stdio {
+        return Err(anyhow!("Standard input cannot be replaced in place"));
+    }
+
+    if args.report.as_deref() == Some(STDIO_PATH) && output == STDIO_PATH {
+        return Err(anyhow!(
+            "The report and the output cannot both be written to standard output"
+        ));
+    }
+
+    if batch::is_batch(&args.input) {
+        if uses_stdio {
+            return Err(anyhow!("Standard input and output cannot be used in batch mode"));
+        }
+
+        let jobs = batch::collect_jobs(&args.input, args.output.as_deref())?;
+        let worker_count = match args.jobs {
+            0 => std::thread::available_parallelism().map_or(1, |n| n.get()),
+            jobs => jobs,
+        };
+        let cache = match args.cache.as_ref() {
+            Some(cache_path) => Some(Cache::load(cache_path)?),
+            None => None,
+        };
+        let settings = batch::Settings {
+            copy_unsupported: args.copy_unsupported,
+            format: format.as_ref(),
+            worker_count,
+            output_options: &options,
+            cache: cache.as_ref(),
+        };
+        let summary = batch::run(&config, args.verbose, &jobs, &settings);
+        // NOTE: Keep the standard output for the JSON report
+        let mut out: Box<dyn Write> = if options.report_to_stdout {
+            Box::new(io::stderr())
+        } else {
+            Box::new(io::stdout())
+        };
+        summary.print(&mut out, args.verbose)?;
+
+        if let Some(cache) = cache.as_ref() {
+            cache.save()?;
+        }
+
+        if let Some(report_path) = args.report.as_ref() {
+            report::write(report_path, &config, &summary)?;
+        }
+
+        if summary.failed > 0 {
+            return Err(anyhow!("{} file(s) failed to compress", summary.failed));
+        }
+
+        return Ok(());
+    }
+
+    if args.cache.is_some() {
+        return Err(anyhow!("--cache can only be used in batch mode"));
+    }
+
+    // NOTE: Infer the output format from the output file extension (in-place mode follows the config)
+    let format = format.or_else(|| {
+        if args.in_place {
+            return None;
+        }
+
+        Path::new(&output)
+            .extension()
+            .and_then(|extension| file_type::from_extension(&extension.to_string_lossy()))
+    });
+
+    let now = Instant::now();
+    let result = compressor::compress(
+        &config,
+        args.verbose,
+        &args.input[0],
+        &output,
+        format.as_ref(),
+        &options,
+    );
+
+    let Some(report_path) = args.report.as_ref() else {
+        return result.map(|_| ());
+    };
+
+    let outcome = match result {
+        Ok(result) => Outcome::Compressed(result),
+        Err(e) => Outcome::Failed(e.to_string()),
+    };
+
+    let mut summary = Summary::default();
+    summary.push(FileResult {
+        input_path: args.input[0].clone(),
+        output_path: output,
+        outcome,
+        elapsed: now.elapsed(),
+    });
+
+    report::write(report_path, &config, &summary)?;
+
+    if let Some(FileResult {
+        outcome: Outcome::Failed(e),
+        ..
+    }) = summary.results.first()
+    {
+        return Err(anyhow!(e.clone()));
+    }
 
     Ok(())
 }
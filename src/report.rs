@@ -0,0 +1,261 @@
+use crate::batch::{FileResult, Outcome, Summary};
+use crate::cache::CacheStats;
+use rs_image_compressor::compressor::{Dimensions, Status, VariantOutput};
+use rs_image_compressor::config_json::Config;
+use rs_image_compressor::file_type::FileType;
+use rs_image_compressor::imaging::metrics::Metrics;
+use rs_image_compressor::io::file::write_file_bytes;
+use anyhow::Result;
+use serde::Serialize;
+use serde_json::Value;
+
+#[derive(Serialize)]
+struct Report<'a> {
+    files: Vec<FileReport<'a>>,
+    summary: SummaryReport,
+}
+
+/// ファイルごとの結果
+#[derive(Serialize)]
+struct FileReport<'a> {
+    input_path: &'a str,
+    output_path: &'a str,
+    /// 全ページを個別に出力した場合の出力ファイル（tiff.all_pages）
+    page_paths: Option<&'a [String]>,
+    /// 幅・形式ごとに出力した画像（responsive.variants）
+    variants: Option<&'a [VariantOutput]>,
+    /// compressed, kept_original, cached, copied, skipped, failed
+    status: &'static str,
+    input_format: Option<&'a FileType>,
+    output_format: Option<&'a FileType>,
+    /// 出力形式に適用された設定
+    options: Option<Value>,
+    before_bytes: Option<u64>,
+    after_bytes: Option<u64>,
+    /// after_bytes / before_bytes
+    ratio: Option<f64>,
+    before_dimensions: Option<Dimensions>,
+    after_dimensions: Option<Dimensions>,
+    quality: Option<u8>,
+    metrics: Option<Metrics>,
+    elapsed_secs: f64,
+    error: Option<&'a str>,
+}
+
+#[derive(Serialize)]
+struct SummaryReport {
+    compressed: usize,
+    kept_original: usize,
+    cached: usize,
+    copied: usize,
+    skipped: usize,
+    failed: usize,
+    before_bytes: u64,
+    after_bytes: u64,
+    saved_bytes: i64,
+    /// after_bytes / before_bytes
+    ratio: Option<f64>,
+    cache: Option<CacheStats>,
+}
+
+/// 処理結果を JSON で出力（"-" の場合は標準出力）
+pub fn write(report_path: &str, config: &Config, summary: &Summary) -> Result<()> {
+    let report = Report {
+        files: summary
+            .results
+            .iter()
+            .map(|result| file_report(config, result))
+            .collect(),
+        summary: SummaryReport {
+            compressed: summary.compressed,
+            kept_original: summary.kept_original,
+            cached: summary.cached,
+            copied: summary.copied,
+            skipped: summary.skipped,
+            failed: summary.failed,
+            before_bytes: summary.before_bytes,
+            after_bytes: summary.after_bytes,
+            saved_bytes: summary.before_bytes as i64 - summary.after_bytes as i64,
+            ratio: ratio(summary.before_bytes, summary.after_bytes),
+            cache: summary.cache,
+        },
+    };
+
+    let json = serde_json::to_string_pretty(&report)?;
+
+    if report_path == "-" {
+        println!("{}", json);
+    } else {
+        write_file_bytes(report_path, json.as_bytes())?;
+    }
+
+    Ok(())
+}
+
+fn file_report<'a>(config: &Config, result: &'a FileResult) -> FileReport<'a> {
+    let mut report = FileReport {
+        input_path: &result.input_path,
+        output_path: &result.output_path,
+        page_paths: None,
+        variants: None,
+        status: "skipped",
+        input_format: None,
+        output_format: None,
+        options: None,
+        before_bytes: None,
+        after_bytes: None,
+        ratio: None,
+        before_dimensions: None,
+        after_dimensions: None,
+        quality: None,
+        metrics: None,
+        elapsed_secs: result.elapsed.as_secs_f64(),
+        error: None,
+    };
+
+    match &result.outcome {
+        Outcome::Compressed(compressed) => {
+            report.status = match compressed.status {
+                Status::Compressed => "compressed",
+                Status::KeptOriginal => "kept_original",
+            };
+            report.page_paths =
+                (!compressed.page_paths.is_empty()).then_some(compressed.page_paths.as_slice());
+            report.variants =
+                (!compressed.variants.is_empty()).then_some(compressed.variants.as_slice());
+            report.input_format = Some(&compressed.input_type);
+            report.output_format = Some(&compressed.file_type);
+            report.options = config.effective_options(&compressed.file_type);
+            report.before_bytes = Some(compressed.before_bytes);
+            report.after_bytes = Some(compressed.after_bytes);
+            report.ratio = ratio(compressed.before_bytes, compressed.after_bytes);
+            report.before_dimensions = compressed.before_dimensions;
+            report.after_dimensions = compressed.after_dimensions;
+            report.quality = compressed.quality;
+            report.metrics = compressed.metrics;
+        }
+        Outcome::Cached {
+            before_bytes,
+            after_bytes,
+        } => {
+            report.status = "cached";
+            report.before_bytes = Some(*before_bytes);
+            report.after_bytes = Some(*after_bytes);
+            report.ratio = ratio(*before_bytes, *after_bytes);
+        }
+        Outcome::Copied { file_size } => {
+            report.status = "copied";
+            report.before_bytes = Some(*file_size);
+            report.after_bytes = Some(*file_size);
+            report.ratio = ratio(*file_size, *file_size);
+        }
+        Outcome::Skipped => {}
+        Outcome::Failed(e) => {
+            report.status = "failed";
+            report.error = Some(e);
+        }
+    }
+
+    report
+}
+
+fn ratio(before_bytes: u64, after_bytes: u64) -> Option<f64> {
+    (before_bytes > 0).then(|| after_bytes as f64 / before_bytes as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rs_image_compressor::compressor::CompressionResult;
+    use rs_image_compressor::config_json::WebpConfig;
+    use std::time::Duration;
+
+    fn file_result(input_path: &str, outcome: Outcome) -> FileResult {
+        FileResult {
+            input_path: input_path.to_string(),
+            output_path: format!("out/{}", input_path),
+            outcome,
+            elapsed: Duration::from_millis(250),
+        }
+    }
+
+    fn compressed(status: Status, before_bytes: u64, after_bytes: u64) -> Outcome {
+        Outcome::Compressed(CompressionResult {
+            input_type: FileType::PNG,
+            file_type: FileType::WEBP,
+            status,
+            before_bytes,
+            after_bytes,
+            before_dimensions: Some(Dimensions { width: 64, height: 32 }),
+            after_dimensions: Some(Dimensions { width: 32, height: 16 }),
+            quality: Some(70),
+            metrics: Some(Metrics { ssim: 1.0, dssim: 0.0, psnr: f64::INFINITY }),
+            page_paths: Vec::new(),
+            variants: Vec::new(),
+        })
+    }
+
+    /// summary を JSON で書き出して読み込み
+    fn written(summary: &Summary) -> Value {
+        let path = std::env::temp_dir().join(format!("rs-image-compressor-report-{}.json", std::process::id()));
+        write(&path.to_string_lossy(), &Config::default(), summary).unwrap();
+        let json = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        serde_json::from_str(&json).unwrap()
+    }
+
+    #[test]
+    fn report_lists_every_file_in_order() {
+        let mut summary = Summary::default();
+        for result in [
+            file_result("a.png", compressed(Status::Compressed, 1000, 400)),
+            file_result("b.png", compressed(Status::KeptOriginal, 500, 500)),
+            file_result("c.png", Outcome::Cached { before_bytes: 300, after_bytes: 100 }),
+            file_result("d.txt", Outcome::Copied { file_size: 50 }),
+            file_result("e.svg", Outcome::Skipped),
+            file_result("f.png", Outcome::Failed("broken".to_string())),
+        ] {
+            summary.push(result);
+        }
+        let report = written(&summary);
+        let files = report["files"].as_array().unwrap();
+
+        let statuses: Vec<&str> = files.iter().map(|file| file["status"].as_str().unwrap()).collect();
+        assert_eq!(statuses, ["compressed", "kept_original", "cached", "copied", "skipped", "failed"]);
+        assert_eq!(files[5]["error"], "broken");
+        assert_eq!(files[5]["output_path"], "out/f.png");
+        assert!(files[4]["before_bytes"].is_null());
+
+        let summary = &report["summary"];
+        assert_eq!((summary["compressed"].as_u64(), summary["kept_original"].as_u64()), (Some(1), Some(1)));
+        assert_eq!((summary["before_bytes"].as_u64(), summary["after_bytes"].as_u64()), (Some(1850), Some(1050)));
+        assert_eq!(summary["saved_bytes"], 800);
+        assert!(summary["cache"].is_null());
+    }
+
+    #[test]
+    fn compressed_file_reports_formats_options_and_metrics() {
+        let mut summary = Summary::default();
+        summary.push(file_result("a.png", compressed(Status::Compressed, 1000, 250)));
+        let file = &written(&summary)["files"][0];
+
+        assert_eq!(file["input_format"], "PNG");
+        assert_eq!(file["output_format"], "WEBP");
+        assert_eq!(file["ratio"], 0.25);
+        assert_eq!(file["after_dimensions"]["width"], 32);
+        assert_eq!(file["quality"], 70);
+        assert_eq!(file["elapsed_secs"], 0.25);
+        // NOTE: The options are those of the output format, and an infinite PSNR has no JSON number
+        assert_eq!(file["options"]["quality"], WebpConfig::default().quality);
+        assert_eq!(file["metrics"]["ssim"], 1.0);
+        assert!(file["metrics"]["psnr"].is_null());
+        assert!(file["page_paths"].is_null());
+    }
+
+    #[test]
+    fn ratio_is_empty_without_input_bytes() {
+        assert_eq!(ratio(0, 10), None);
+        assert_eq!(ratio(200, 50), Some(0.25));
+    }
+}
@@ -0,0 +1,62 @@
+use image::{DynamicImage, GenericImageView, ImageFormat, Rgba, RgbaImage};
+use std::io::{Cursor, Write};
+use std::process::{Command, Output, Stdio};
+
+fn png_bytes() -> Vec<u8> {
+    let image = RgbaImage::from_fn(48, 32, |x, y| Rgba([(x * 5) as u8, (y * 7) as u8, 128, 255]));
+    let mut bytes = Vec::new();
+    DynamicImage::ImageRgba8(image)
+        .write_to(&mut Cursor::new(&mut bytes), ImageFormat::Png)
+        .unwrap();
+    bytes
+}
+
+/// 標準入力に input を渡して実行
+fn run(args: &[&str], input: &[u8]) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_rs-image-compressor"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    // NOTE: Close the standard input so the command sees the end of the data
+    child.stdin.take().unwrap().write_all(input).unwrap();
+    child.wait_with_output().unwrap()
+}
+
+#[test]
+fn compresses_standard_input_to_standard_output() {
+    let output = run(&["-i", "-", "-o", "-"], &png_bytes());
+
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let image = image::load_from_memory_with_format(&output.stdout, ImageFormat::Png).unwrap();
+    assert_eq!(image.dimensions(), (48, 32));
+}
+
+#[test]
+fn converts_standard_input_with_format() {
+    let output = run(&["-i", "-", "-o", "-", "-f", "webp"], &png_bytes());
+
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let image = image::load_from_memory_with_format(&output.stdout, ImageFormat::WebP).unwrap();
+    assert_eq!(image.dimensions(), (48, 32));
+}
+
+#[test]
+fn verbose_output_goes_to_standard_error() {
+    let output = run(&["-i", "-", "-o", "-", "-f", "jpeg", "-v"], &png_bytes());
+
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(output.stdout.starts_with(&[0xff, 0xd8]));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("===== End ====="));
+}
+
+#[test]
+fn unknown_standard_input_fails_without_output() {
+    let output = run(&["-i", "-", "-o", "-"], b"not an image");
+
+    assert!(!output.status.success());
+    assert!(output.stdout.is_empty());
+}